@@ -0,0 +1,373 @@
+//! A cheap over-approximation of the values an expression can take, computed once when the expression is built.
+//!
+//! Every value is described by an unsigned interval and the bits known to be zero or one. Bools are 1 bit wide values.
+//! Since the abstraction of a value holds on every path, a condition whose abstraction is a single bool needs no solver call.
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    scfia::bv_mask,
+    values::{
+        active_value::{ActiveExpression, ActiveValueZ3},
+        bool_comparison_expression::ComparisonPredicate,
+    },
+    ScfiaComposition,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Abstraction {
+    pub width: u32,
+    pub min: u128,
+    pub max: u128,
+    pub known_zeros: u128,
+    pub known_ones: u128,
+}
+
+impl Abstraction {
+    pub fn top(width: u32) -> Self {
+        Abstraction {
+            width,
+            min: 0,
+            max: bv_mask(width),
+            known_zeros: 0,
+            known_ones: 0,
+        }
+    }
+
+    pub fn exact(value: u128, width: u32) -> Self {
+        let value = value & bv_mask(width);
+        Abstraction {
+            width,
+            min: value,
+            max: value,
+            known_zeros: !value & bv_mask(width),
+            known_ones: value,
+        }
+    }
+
+    pub fn bool(value: Option<bool>) -> Self {
+        match value {
+            Some(value) => Abstraction::exact(value as u128, 1),
+            None => Abstraction::top(1),
+        }
+    }
+
+    pub fn get_exact(&self) -> Option<u128> {
+        if self.min == self.max {
+            Some(self.min)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_bool(&self) -> Option<bool> {
+        self.get_exact().map(|value| value == 1)
+    }
+
+    pub fn contains(&self, value: u128) -> bool {
+        self.min <= value && value <= self.max && value & self.known_zeros == 0 && value & self.known_ones == self.known_ones
+    }
+
+    /// Tightens the interval and the known bits against each other.
+    fn new(width: u32, min: u128, max: u128, known_zeros: u128, known_ones: u128) -> Self {
+        let mask = bv_mask(width);
+        let (mut known_zeros, mut known_ones) = (known_zeros & mask, known_ones & mask);
+        let min = min.max(known_ones);
+        let max = max.min(!known_zeros & mask);
+        if min > max || known_zeros & known_ones != 0 {
+            // Only an imprecise transfer function can get here, so give up on this value
+            return Abstraction::top(width);
+        }
+
+        // The leading bits min and max agree on are shared by every value in between
+        let difference = min ^ max;
+        let prefix = if difference == 0 {
+            mask
+        } else {
+            mask & !(u128::MAX >> difference.leading_zeros())
+        };
+        known_ones |= min & prefix;
+        known_zeros |= !min & prefix;
+        Abstraction {
+            width,
+            min: min.max(known_ones),
+            max: max.min(!known_zeros & mask),
+            known_zeros,
+            known_ones,
+        }
+    }
+
+    fn join(&self, other: &Abstraction) -> Self {
+        Abstraction::new(
+            self.width,
+            self.min.min(other.min),
+            self.max.max(other.max),
+            self.known_zeros & other.known_zeros,
+            self.known_ones & other.known_ones,
+        )
+    }
+
+    /// The number of low bits which are known.
+    fn known_low_bits(&self) -> u32 {
+        (self.known_zeros | self.known_ones).trailing_ones().min(self.width)
+    }
+
+    /// Maps signed order onto unsigned order by flipping the sign bit.
+    fn flip_sign(&self) -> Self {
+        let sign = 1 << (self.width - 1);
+        let (min, max) = if self.min & sign == self.max & sign {
+            (self.min ^ sign, self.max ^ sign)
+        } else {
+            (0, bv_mask(self.width))
+        };
+        let flipped = |bits: u128, other: u128| (bits & !sign) | (other & sign);
+        Abstraction::new(
+            self.width,
+            min,
+            max,
+            flipped(self.known_zeros, self.known_ones),
+            flipped(self.known_ones, self.known_zeros),
+        )
+    }
+}
+
+/// Like `bv_mask`, but also defined for no bits at all.
+fn low_bits(bits: u32) -> u128 {
+    if bits == 0 {
+        0
+    } else {
+        bv_mask(bits)
+    }
+}
+
+fn unsigned_less_than(s1: &Abstraction, s2: &Abstraction) -> Option<bool> {
+    if s1.max < s2.min {
+        Some(true)
+    } else if s1.min >= s2.max {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn signed_less_than(s1: &Abstraction, s2: &Abstraction) -> Option<bool> {
+    unsigned_less_than(&s1.flip_sign(), &s2.flip_sign())
+}
+
+fn equal(s1: &Abstraction, s2: &Abstraction) -> Option<bool> {
+    if s1.get_exact().is_some() && s1.get_exact() == s2.get_exact() {
+        Some(true)
+    } else if s1.max < s2.min || s2.max < s1.min || (s1.known_ones & s2.known_zeros) | (s1.known_zeros & s2.known_ones) != 0 {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn compare(predicate: ComparisonPredicate, s1: &Abstraction, s2: &Abstraction) -> Option<bool> {
+    match predicate {
+        ComparisonPredicate::UnsignedLessEqual => unsigned_less_than(s2, s1).map(|value| !value),
+        ComparisonPredicate::UnsignedGreaterThan => unsigned_less_than(s2, s1),
+        ComparisonPredicate::UnsignedGreaterEqual => unsigned_less_than(s1, s2).map(|value| !value),
+        ComparisonPredicate::SignedLessEqual => signed_less_than(s2, s1).map(|value| !value),
+        ComparisonPredicate::SignedGreaterThan => signed_less_than(s2, s1),
+        ComparisonPredicate::SignedGreaterEqual => signed_less_than(s1, s2).map(|value| !value),
+    }
+}
+
+/// Addition and subtraction modulo 2^width, which preserve the low bits known in both operands.
+fn add_sub(s1: &Abstraction, s2: &Abstraction, width: u32, subtract: bool) -> Abstraction {
+    let low = low_bits(s1.known_low_bits().min(s2.known_low_bits()));
+    let (interval, low_value) = if subtract {
+        let interval = if s1.min >= s2.max { Some((s1.min - s2.max, s1.max - s2.min)) } else { None };
+        (interval, s1.known_ones.wrapping_sub(s2.known_ones) & low)
+    } else {
+        let interval = match s1.max.checked_add(s2.max) {
+            Some(max) if max <= bv_mask(width) => Some((s1.min + s2.min, max)),
+            _ => None,
+        };
+        (interval, s1.known_ones.wrapping_add(s2.known_ones) & low)
+    };
+    let (min, max) = interval.unwrap_or((0, bv_mask(width)));
+    Abstraction::new(width, min, max, !low_value & low, low_value)
+}
+
+fn multiply(s1: &Abstraction, s2: &Abstraction, width: u32) -> Abstraction {
+    let trailing_zeros = (s1.known_zeros.trailing_ones() + s2.known_zeros.trailing_ones()).min(width);
+    let (min, max) = match s1.max.checked_mul(s2.max) {
+        Some(max) if max <= bv_mask(width) => (s1.min * s2.min, max),
+        _ => (0, bv_mask(width)),
+    };
+    Abstraction::new(width, min, max, low_bits(trailing_zeros), 0)
+}
+
+fn shift_left(s1: &Abstraction, shift: u128, width: u32) -> Abstraction {
+    if shift >= width as u128 {
+        return Abstraction::exact(0, width);
+    }
+    let (min, max) = if s1.max <= bv_mask(width) >> shift {
+        (s1.min << shift, s1.max << shift)
+    } else {
+        (0, bv_mask(width))
+    };
+    Abstraction::new(width, min, max, (s1.known_zeros << shift) | low_bits(shift as u32), s1.known_ones << shift)
+}
+
+fn shift_right(s1: &Abstraction, shift: u128, width: u32) -> Abstraction {
+    if shift >= width as u128 {
+        return Abstraction::exact(0, width);
+    }
+    let high = !(bv_mask(width) >> shift);
+    Abstraction::new(
+        width,
+        s1.min >> shift,
+        s1.max >> shift,
+        (s1.known_zeros >> shift) | high,
+        s1.known_ones >> shift,
+    )
+}
+
+fn rotate_left(s1: &Abstraction, amount: u128, width: u32) -> Abstraction {
+    let amount = (amount % width as u128) as u32;
+    let rotate = |bits: u128| {
+        if amount == 0 {
+            bits
+        } else {
+            ((bits << amount) | (bits >> (width - amount))) & bv_mask(width)
+        }
+    };
+    Abstraction::new(width, 0, bv_mask(width), rotate(s1.known_zeros), rotate(s1.known_ones))
+}
+
+fn extend(s1: &Abstraction, width: u32, signed: bool) -> Abstraction {
+    let upper = bv_mask(width) & !bv_mask(s1.width);
+    let sign = 1 << (s1.width - 1);
+    if !signed || s1.known_zeros & sign != 0 {
+        Abstraction::new(width, s1.min, s1.max, s1.known_zeros | upper, s1.known_ones)
+    } else if s1.known_ones & sign != 0 {
+        Abstraction::new(width, s1.min | upper, s1.max | upper, s1.known_zeros, s1.known_ones | upper)
+    } else {
+        Abstraction::new(width, 0, bv_mask(width), s1.known_zeros, s1.known_ones)
+    }
+}
+
+fn slice(s1: &Abstraction, high: u32, low: u32) -> Abstraction {
+    let width = high - low + 1;
+    let (min, max) = if high + 1 == s1.width {
+        (s1.min >> low, s1.max >> low)
+    } else {
+        (0, bv_mask(width))
+    };
+    Abstraction::new(width, min, max, s1.known_zeros >> low, s1.known_ones >> low)
+}
+
+/// Computes the abstraction of a new expression from the abstractions of its parents.
+pub(crate) fn abstract_expression<SC: ScfiaComposition>(expression: &ActiveExpression<SC>) -> Abstraction {
+    let of = |value: &Rc<RefCell<ActiveValueZ3<SC>>>| value.try_borrow().unwrap().abstraction;
+    let bool_of = |value: &Rc<RefCell<ActiveValueZ3<SC>>>| of(value).get_bool();
+    match expression {
+        ActiveExpression::BoolComparisonExpression(e) => Abstraction::bool(compare(e.predicate, &of(&e.s1), &of(&e.s2))),
+        ActiveExpression::BoolEqExpression(e) => Abstraction::bool(equal(&of(&e.s1), &of(&e.s2))),
+        ActiveExpression::BoolNotExpression(e) => Abstraction::bool(bool_of(&e.s1).map(|value| !value)),
+        ActiveExpression::BoolSignedLessThanExpression(e) => Abstraction::bool(signed_less_than(&of(&e.s1), &of(&e.s2))),
+        ActiveExpression::BoolUnsignedLessThanExpression(e) => Abstraction::bool(unsigned_less_than(&of(&e.s1), &of(&e.s2))),
+        ActiveExpression::BoolAndExpression(e) => Abstraction::bool(match (bool_of(&e.s1), bool_of(&e.s2)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        }),
+        ActiveExpression::BoolImpliesExpression(e) => Abstraction::bool(match (bool_of(&e.s1), bool_of(&e.s2)) {
+            (Some(false), _) | (_, Some(true)) => Some(true),
+            (Some(true), Some(false)) => Some(false),
+            _ => None,
+        }),
+        ActiveExpression::BoolOrExpression(e) => Abstraction::bool(match (bool_of(&e.s1), bool_of(&e.s2)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        }),
+        ActiveExpression::BoolXorExpression(e) => Abstraction::bool(bool_of(&e.s1).zip(bool_of(&e.s2)).map(|(s1, s2)| s1 != s2)),
+        ActiveExpression::BVAddExpression(e) => add_sub(&of(&e.s1), &of(&e.s2), e.width, false),
+        ActiveExpression::BVAndExpression(e) => {
+            let (s1, s2) = (of(&e.s1), of(&e.s2));
+            Abstraction::new(e.width, 0, s1.max.min(s2.max), s1.known_zeros | s2.known_zeros, s1.known_ones & s2.known_ones)
+        }
+        ActiveExpression::BVConcatExpression(e) => {
+            let (s1, s2) = (of(&e.s1), of(&e.s2));
+            let concat = |upper: u128, lower: u128| (upper << s2.width) | lower;
+            Abstraction::new(
+                e.width,
+                concat(s1.min, s2.min),
+                concat(s1.max, s2.max),
+                concat(s1.known_zeros, s2.known_zeros),
+                concat(s1.known_ones, s2.known_ones),
+            )
+        }
+        ActiveExpression::BVConcreteExpression(e) => Abstraction::exact(e.value, e.width),
+        ActiveExpression::BVIteExpression(e) => match bool_of(&e.condition) {
+            Some(true) => of(&e.s1),
+            Some(false) => of(&e.s2),
+            None => of(&e.s1).join(&of(&e.s2)),
+        },
+        ActiveExpression::BVMultiplyExpression(e) => multiply(&of(&e.s1), &of(&e.s2), e.width),
+        ActiveExpression::BVNotExpression(e) => {
+            let s1 = of(&e.s1);
+            let mask = bv_mask(e.width);
+            Abstraction::new(e.width, mask - s1.max, mask - s1.min, s1.known_ones, s1.known_zeros)
+        }
+        ActiveExpression::BVOrExpression(e) => {
+            let (s1, s2) = (of(&e.s1), of(&e.s2));
+            Abstraction::new(
+                e.width,
+                s1.min.max(s2.min),
+                bv_mask(e.width),
+                s1.known_zeros & s2.known_zeros,
+                s1.known_ones | s2.known_ones,
+            )
+        }
+        ActiveExpression::BVRotateLeftExpression(e) => match of(&e.s2).get_exact() {
+            Some(amount) => rotate_left(&of(&e.s1), amount, e.width),
+            None => Abstraction::top(e.width),
+        },
+        ActiveExpression::BVRotateRightExpression(e) => match of(&e.s2).get_exact() {
+            Some(amount) => rotate_left(&of(&e.s1), e.width as u128 - amount % e.width as u128, e.width),
+            None => Abstraction::top(e.width),
+        },
+        ActiveExpression::BVSignExtendExpression(e) => extend(&of(&e.s1), e.width, true),
+        ActiveExpression::BVZeroExtendExpression(e) => extend(&of(&e.s1), e.width, false),
+        ActiveExpression::BVSliceExpression(e) => slice(&of(&e.s1), e.high, e.low),
+        ActiveExpression::BVSllExpression(e) => match of(&e.s2).get_exact() {
+            Some(shift) => shift_left(&of(&e.s1), shift, e.width),
+            None => Abstraction::top(e.width),
+        },
+        ActiveExpression::BVSrlExpression(e) => match of(&e.s2).get_exact() {
+            Some(shift) => shift_right(&of(&e.s1), shift, e.width),
+            None => Abstraction::new(e.width, 0, of(&e.s1).max, 0, 0),
+        },
+        ActiveExpression::BVSubExpression(e) => add_sub(&of(&e.s1), &of(&e.s2), e.width, true),
+        ActiveExpression::BVSymbol(e) => Abstraction::top(e.width),
+        ActiveExpression::BVUnsignedDivideExpression(e) => {
+            let (s1, s2) = (of(&e.s1), of(&e.s2));
+            match s1.max.checked_div(s2.min) {
+                Some(max) => Abstraction::new(e.width, s1.min / s2.max, max, 0, 0),
+                None => Abstraction::top(e.width),
+            }
+        }
+        ActiveExpression::BVUnsignedRemainderExpression(e) => {
+            let (s1, s2) = (of(&e.s1), of(&e.s2));
+            // Division by zero yields s1
+            let max = if s2.min > 0 { s1.max.min(s2.max - 1) } else { s1.max };
+            Abstraction::new(e.width, 0, max, 0, 0)
+        }
+        ActiveExpression::BVXorExpression(e) => {
+            let (s1, s2) = (of(&e.s1), of(&e.s2));
+            Abstraction::new(
+                e.width,
+                0,
+                bv_mask(e.width),
+                (s1.known_zeros & s2.known_zeros) | (s1.known_ones & s2.known_ones),
+                (s1.known_ones & s2.known_zeros) | (s1.known_zeros & s2.known_ones),
+            )
+        }
+        ActiveExpression::BVAshrExpression(e) => Abstraction::top(e.width),
+    }
+}
@@ -3,9 +3,12 @@ use scfia::Scfia;
 use std::fmt::Debug;
 use values::active_value::ActiveValue;
 
+pub mod abstract_domain;
 pub mod memory;
 pub mod models;
+pub mod report;
 pub mod scfia;
+mod simplifier;
 pub mod values;
 pub mod z3_handle;
 
@@ -0,0 +1,240 @@
+use log::{debug, warn};
+use z3_sys::Z3_L_FALSE;
+
+use crate::{
+    scfia::{bv_mask, Scfia},
+    values::active_value::ActiveValue,
+    ScfiaComposition,
+};
+
+use super::{
+    findings::{MemoryFinding, MemoryFindingKind},
+    Memory,
+};
+
+/// The most bytes a bulk operation touches.
+pub const MAX_BULK_LENGTH: u64 = 0x10000;
+
+/// Bulk operations over a possibly symbolic length, e.g. to summarise `memcpy`, `memset` or `memcmp` in a function hook.
+///
+/// The maximum of the length is found by a binary search, and every byte up to that maximum is guarded by `offset < length`,
+/// so the operation does not fork once per possible length.
+/// Addresses which may take more than one value are reported as findings, and the operation is skipped.
+impl<SC: ScfiaComposition> Memory<SC> {
+    /// Copies `length` bytes from `source` to `destination`. Overlapping ranges behave like `memmove`.
+    pub fn copy(
+        &mut self,
+        destination: &ActiveValue<SC>,
+        source: &ActiveValue<SC>,
+        length: &ActiveValue<SC>,
+        scfia: &Scfia<SC>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) {
+        let (Some((destination, address_width)), Some((source, _))) = (self.resolve_address(destination, scfia), self.resolve_address(source, scfia)) else {
+            return;
+        };
+        let max_length = self.max_length(destination, length, scfia);
+        debug!("copy(*{:#x}, *{:#x}, <= {:#x})", destination, source, max_length);
+
+        let mut bytes = vec![];
+        for offset in 0..max_length {
+            let in_bounds = in_bounds(offset, length, scfia, fork_sink);
+            bytes.push(self.read_byte(offset_address(source, offset, address_width), address_width, &in_bounds, scfia, fork_sink));
+        }
+        for (offset, byte) in bytes.iter().enumerate() {
+            self.write_guarded(destination, offset as u64, address_width, byte, length, scfia, fork_sink);
+        }
+    }
+
+    /// Sets `length` bytes at `destination` to the 8-bit `value`.
+    pub fn fill(
+        &mut self,
+        destination: &ActiveValue<SC>,
+        value: &ActiveValue<SC>,
+        length: &ActiveValue<SC>,
+        scfia: &Scfia<SC>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) {
+        let Some((destination, address_width)) = self.resolve_address(destination, scfia) else {
+            return;
+        };
+        let max_length = self.max_length(destination, length, scfia);
+        debug!("fill(*{:#x}, <= {:#x})", destination, max_length);
+
+        for offset in 0..max_length {
+            self.write_guarded(destination, offset, address_width, value, length, scfia, fork_sink);
+        }
+    }
+
+    /// Compares `length` bytes like `memcmp`, yielding a `width` wide value which is 0 if the ranges are equal,
+    /// and -1 or 1 if the first differing byte of `lhs` is lower or higher than the one of `rhs`.
+    /// If either address is ambiguous, the result is a fresh symbol.
+    #[allow(clippy::too_many_arguments)]
+    pub fn compare(
+        &mut self,
+        lhs: &ActiveValue<SC>,
+        rhs: &ActiveValue<SC>,
+        length: &ActiveValue<SC>,
+        width: u32,
+        scfia: &Scfia<SC>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> ActiveValue<SC> {
+        let (Some((lhs, address_width)), Some((rhs, _))) = (self.resolve_address(lhs, scfia), self.resolve_address(rhs, scfia)) else {
+            return scfia.new_bv_symbol(width, None, fork_sink, None);
+        };
+        let max_length = self.max_length(lhs, length, scfia);
+        debug!("compare(*{:#x}, *{:#x}, <= {:#x})", lhs, rhs, max_length);
+
+        let zero = scfia.new_bv_concrete(0, width);
+        let lower = scfia.new_bv_concrete(u128::MAX >> (128 - width), width);
+        let higher = scfia.new_bv_concrete(1, width);
+        // Walk backwards, so every byte decides the result if it differs and defers to the following bytes otherwise
+        let mut result = zero.clone();
+        for offset in (0..max_length).rev() {
+            let in_bounds = in_bounds(offset, length, scfia, fork_sink);
+            let lhs_byte = self.read_byte(offset_address(lhs, offset, address_width), address_width, &in_bounds, scfia, fork_sink);
+            let rhs_byte = self.read_byte(offset_address(rhs, offset, address_width), address_width, &in_bounds, scfia, fork_sink);
+            let equal = scfia.new_bool_eq(&lhs_byte, &rhs_byte, None, false, fork_sink, None);
+            let less = scfia.new_bool_unsigned_less_than(&lhs_byte, &rhs_byte, None, false, fork_sink, None);
+            let difference = scfia.new_bv_ite(&less, &lower, &higher, width, None, fork_sink, None);
+            let byte_result = scfia.new_bv_ite(&equal, &result, &difference, width, None, fork_sink, None);
+            result = scfia.new_bv_ite(&in_bounds, &byte_result, &zero, width, None, fork_sink, None);
+        }
+        result
+    }
+
+    /// Writes `byte` to `destination + offset` if `offset < length`, and keeps the previous value otherwise.
+    #[allow(clippy::too_many_arguments)]
+    fn write_guarded(
+        &mut self,
+        destination: u64,
+        offset: u64,
+        address_width: u32,
+        byte: &ActiveValue<SC>,
+        length: &ActiveValue<SC>,
+        scfia: &Scfia<SC>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) {
+        let address = offset_address(destination, offset, address_width);
+        let in_bounds = in_bounds(offset, length, scfia, fork_sink);
+        let value = if let ActiveValue::BoolConcrete(true) = in_bounds {
+            byte.clone()
+        } else {
+            let previous = self.read_byte(address, address_width, &in_bounds, scfia, fork_sink);
+            scfia.new_bv_ite(&in_bounds, byte, &previous, 8, None, fork_sink, None)
+        };
+        self.write(&scfia.new_bv_concrete(address.into(), address_width), &value, 8, scfia, &mut None, fork_sink);
+    }
+
+    /// Reads the byte at `address`. Bytes which may lie past the length are never read by the program,
+    /// so they are read without permission checks, findings or peripheral callbacks.
+    fn read_byte(
+        &mut self,
+        address: u64,
+        address_width: u32,
+        in_bounds: &ActiveValue<SC>,
+        scfia: &Scfia<SC>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> ActiveValue<SC> {
+        if let ActiveValue::BoolConcrete(true) = in_bounds {
+            return self.read(&scfia.new_bv_concrete(address.into(), address_width), 8, scfia, &mut None, fork_sink);
+        }
+        if let Some((index, offset)) = self.find_concrete_symbolic_volatile(address) {
+            return self.symbolic_volatiles[index].read(Some(offset), 8, scfia, fork_sink);
+        }
+        if self.peripherals.get(address).is_some() || self.volatiles.get(address).is_some() {
+            return scfia.new_bv_symbol(8, None, fork_sink, None);
+        }
+        if let Some(region) = self.stables.get(address) {
+            return region.read(address, 8, self.uninitialized_read_policy, scfia, fork_sink);
+        }
+        panic!("Bulk operation at {:#x} not satisfied by any region", address)
+    }
+
+    /// Resolves an address which must have exactly one interpretation, returning it and its width.
+    fn resolve_address(&mut self, address: &ActiveValue<SC>, scfia: &Scfia<SC>) -> Option<(u64, u32)> {
+        match address {
+            ActiveValue::BoolConcrete(_) => panic!(),
+            ActiveValue::BVConcrete(address, width) => Some((u64::try_from(*address).unwrap(), *width)),
+            ActiveValue::Expression(_) => {
+                let address_ast = address.get_z3_ast();
+                if let Some(unique) = scfia.z3.get_unique(&address_ast) {
+                    return Some((u64::try_from(unique).unwrap(), scfia.z3.get_bv_width(&address_ast)));
+                }
+                warn!("Bulk operation on ambiguous address {:?}", address);
+                self.findings.push(MemoryFinding {
+                    pc: None,
+                    address: None,
+                    width: 8,
+                    kind: MemoryFindingKind::AmbiguousBulkAddress,
+                });
+                None
+            }
+        }
+    }
+
+    /// The largest value `length` may take under the current path condition, at most `MAX_BULK_LENGTH`.
+    fn max_length(&mut self, address: u64, length: &ActiveValue<SC>, scfia: &Scfia<SC>) -> u64 {
+        match length {
+            ActiveValue::BoolConcrete(_) => panic!(),
+            ActiveValue::BVConcrete(length, _) => {
+                if *length > MAX_BULK_LENGTH.into() {
+                    self.cap_length(address);
+                    return MAX_BULK_LENGTH;
+                }
+                *length as u64
+            }
+            ActiveValue::Expression(_) => {
+                let length_ast = length.get_z3_ast();
+                let width = scfia.z3.get_bv_width(&length_ast);
+                let may_exceed = |bound: u64| {
+                    let bound_ast = scfia.z3.new_bv_concrete(bound.into(), width);
+                    let exceeds = scfia.z3.new_bvult(&bound_ast, &length_ast, false);
+                    scfia.z3.check_assumptions(&[&exceeds]) != Z3_L_FALSE
+                };
+
+                // The abstraction is a cheap upper bound, the solver narrows it down to the real maximum
+                let abstraction_max = u64::try_from(length.get_abstraction().max).unwrap_or(u64::MAX);
+                let mut high = abstraction_max.min(MAX_BULK_LENGTH);
+                if abstraction_max > MAX_BULK_LENGTH && may_exceed(MAX_BULK_LENGTH) {
+                    self.cap_length(address);
+                    return MAX_BULK_LENGTH;
+                }
+
+                let mut low = 0;
+                while low < high {
+                    let middle = low + (high - low) / 2;
+                    if may_exceed(middle) {
+                        low = middle + 1
+                    } else {
+                        high = middle
+                    }
+                }
+                high
+            }
+        }
+    }
+
+    fn cap_length(&mut self, address: u64) {
+        warn!("Bulk operation at {:#x} capped at {:#x} bytes", address, MAX_BULK_LENGTH);
+        self.findings.push(MemoryFinding {
+            pc: None,
+            address: Some(address),
+            width: 8,
+            kind: MemoryFindingKind::BulkLengthCapped { cap: MAX_BULK_LENGTH },
+        });
+    }
+}
+
+fn offset_address(base: u64, offset: u64, address_width: u32) -> u64 {
+    (u128::from(base.wrapping_add(offset)) & bv_mask(address_width)) as u64
+}
+
+fn in_bounds<SC: ScfiaComposition>(offset: u64, length: &ActiveValue<SC>, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC> {
+    let length_width = match length {
+        ActiveValue::BVConcrete(_, width) => *width,
+        _ => scfia.z3.get_bv_width(&length.get_z3_ast()),
+    };
+    let offset = scfia.new_bv_concrete(offset.into(), length_width);
+    scfia.new_bool_unsigned_less_than(&offset, length, None, false, fork_sink, None)
+}
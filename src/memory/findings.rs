@@ -0,0 +1,28 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryAccess {
+    Read,
+    Write,
+    Execute,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MemoryFindingKind {
+    /// An access hit a region which does not permit it.
+    PermissionViolation { access: MemoryAccess, region_start_address: u64 },
+    /// A read hit bytes which were never written.
+    UninitializedRead,
+    /// A bulk operation got an address which may take more than one value, so it was skipped.
+    AmbiguousBulkAddress,
+    /// A bulk operation got a length which may exceed the cap, so bytes beyond the cap were left untouched.
+    BulkLengthCapped { cap: u64 },
+}
+
+/// Something noteworthy the memory observed during execution.
+/// Symbolic addresses are reported without a concrete address, and the pc is filled in by the model after the step.
+#[derive(Clone, Debug)]
+pub struct MemoryFinding {
+    pub pc: Option<u64>,
+    pub address: Option<u64>,
+    pub width: u32,
+    pub kind: MemoryFindingKind,
+}
@@ -1,8 +1,13 @@
+pub mod bulk;
+pub mod findings;
+pub mod pages;
+pub mod region_map;
 pub mod regions;
+pub mod write_log;
 
 use std::{collections::BTreeMap, time::Instant};
 
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use z3_sys::Z3_L_FALSE;
 
 use crate::{
@@ -14,33 +19,47 @@ use crate::{
     ScfiaComposition, SymbolicHints,
 };
 
-use self::regions::{StableMemoryRegion, SymbolicVolatileMemoryRegion, VolatileMemoryRegion};
+use self::findings::{MemoryAccess, MemoryFinding, MemoryFindingKind};
+use self::region_map::{Region, RegionMap};
+use self::regions::{
+    PeripheralRegion, StableMemoryRegion, SymbolicVolatileMemoryRegion, SymbolicVolatilePolicy, UninitializedReadPolicy, VolatileMemoryRegion,
+};
+use self::write_log::MemoryWrite;
 
 pub struct Memory<SC: ScfiaComposition> {
-    pub stables: Vec<StableMemoryRegion<SC>>,
-    pub volatiles: Vec<VolatileMemoryRegion>,
+    pub stables: RegionMap<StableMemoryRegion<SC>>,
+    pub volatiles: RegionMap<VolatileMemoryRegion>,
     pub symbolic_volatiles: Vec<SymbolicVolatileMemoryRegion<SC>>,
+    pub peripherals: RegionMap<Box<dyn PeripheralRegion<SC>>>,
+    pub findings: Vec<MemoryFinding>,
+    pub uninitialized_read_policy: UninitializedReadPolicy,
+    /// Writes performed through `write`, recorded only if this is `Some`.
+    pub write_log: Option<Vec<MemoryWrite<SC>>>,
 }
 
 impl<SC: ScfiaComposition> Memory<SC> {
     pub fn new() -> Self {
         Memory {
-            stables: vec![],
-            volatiles: vec![],
+            stables: RegionMap::new(),
+            volatiles: RegionMap::new(),
             symbolic_volatiles: vec![],
+            peripherals: RegionMap::new(),
+            findings: vec![],
+            uninitialized_read_policy: UninitializedReadPolicy::Symbol,
+            write_log: None,
         }
     }
 
     pub fn get_highest_depth(&self) -> Option<(u64, usize)> {
         let mut highest = None;
         for stable in &self.stables {
-            for (address, value) in &stable.memory {
+            for (address, value) in stable.memory.iter() {
                 if let Some((_, highest_depth)) = highest {
                     if value.get_depth() > highest_depth {
-                        highest = Some((*address, value.get_depth()))
+                        highest = Some((address, value.get_depth()))
                     }
                 } else {
-                    highest = Some((*address, value.get_depth()))
+                    highest = Some((address, value.get_depth()))
                 }
             }
         }
@@ -54,10 +73,35 @@ impl<SC: ScfiaComposition> Memory<SC> {
         scfia: &Scfia<SC>,
         hints: &mut Option<SymbolicHints>,
         fork_sink: &mut Option<SC::ForkSink>,
+    ) -> ActiveValue<SC> {
+        self.check_permissions(address, width, MemoryAccess::Read, scfia);
+        self.dispatch_read(address, width, scfia, hints, fork_sink)
+    }
+
+    /// Reads an instruction, which requires execute instead of read permissions.
+    pub fn fetch(
+        &mut self,
+        address: &ActiveValue<SC>,
+        width: u32,
+        scfia: &Scfia<SC>,
+        hints: &mut Option<SymbolicHints>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> ActiveValue<SC> {
+        self.check_permissions(address, width, MemoryAccess::Execute, scfia);
+        self.dispatch_read(address, width, scfia, hints, fork_sink)
+    }
+
+    fn dispatch_read(
+        &mut self,
+        address: &ActiveValue<SC>,
+        width: u32,
+        scfia: &Scfia<SC>,
+        hints: &mut Option<SymbolicHints>,
+        fork_sink: &mut Option<SC::ForkSink>,
     ) -> ActiveValue<SC> {
         match address {
             ActiveValue::BoolConcrete(_) => panic!(),
-            ActiveValue::BVConcrete(address, _) => self.read_concrete(*address, width, scfia, fork_sink),
+            ActiveValue::BVConcrete(address, _) => self.read_concrete(*address as u64, width, scfia, fork_sink),
             ActiveValue::Expression(e) => self.read_symbolic(&e.try_borrow().unwrap(), width, scfia, hints, fork_sink),
         }
     }
@@ -71,10 +115,58 @@ impl<SC: ScfiaComposition> Memory<SC> {
         hints: &mut Option<SymbolicHints>,
         fork_sink: &mut Option<SC::ForkSink>,
     ) {
+        self.check_permissions(address, width, MemoryAccess::Write, scfia);
+        if let Some(write_log) = &mut self.write_log {
+            write_log.push(MemoryWrite {
+                pc: None,
+                address: address.clone(),
+                width,
+                value: value.clone(),
+            });
+        }
         match address {
             ActiveValue::BoolConcrete(_) => panic!(),
-            ActiveValue::BVConcrete(address, _) => self.write_concrete(*address, value, width, scfia, fork_sink),
-            ActiveValue::Expression(e) => self.write_symbolic(&e.try_borrow().unwrap(), value, width, scfia, hints),
+            ActiveValue::BVConcrete(address, _) => self.write_concrete(*address as u64, value, width, scfia, fork_sink),
+            ActiveValue::Expression(e) => self.write_symbolic(&e.try_borrow().unwrap(), value, width, scfia, hints, fork_sink),
+        }
+    }
+
+    /// Records a finding for every region the access hits or, for symbolic addresses, may hit without permission.
+    /// The access itself is still performed, so the analysis can continue.
+    fn check_permissions(&mut self, address: &ActiveValue<SC>, width: u32, access: MemoryAccess, scfia: &Scfia<SC>) {
+        let bytes = (width as u128 / 8).max(1);
+        let abstraction = address.get_abstraction();
+        // Every byte the access may touch lies in [first, last]
+        let first = u64::try_from(abstraction.min).unwrap_or(u64::MAX);
+        let last = u64::try_from(abstraction.max + bytes - 1).unwrap_or(u64::MAX);
+        let mut restricted = restricted_regions(&self.stables, first, last, access);
+        restricted.extend(restricted_regions(&self.volatiles, first, last, access));
+        restricted.extend(restricted_regions(&self.peripherals, first, last, access));
+
+        // A concrete access touches every region in its range, a symbolic one only those the solver cannot rule out
+        let is_concrete = abstraction.min == abstraction.max;
+        let mut violated = vec![];
+        for (region_start, region_length) in restricted {
+            if is_concrete || may_touch(address, bytes, region_start, region_length, scfia) {
+                violated.push(region_start);
+            }
+        }
+        for region in &self.symbolic_volatiles {
+            if !region.permissions.allows(access) && region.may_overlap(address, bytes as u64, scfia) {
+                // Symbolic bases are reported by the lowest value they may take
+                violated.push(u64::try_from(region.base.get_abstraction().min).unwrap());
+            }
+        }
+
+        let address = if is_concrete { Some(first) } else { None };
+        for region_start_address in violated {
+            warn!("{:?} of {:x?} violates permissions of region {:#x}", access, address, region_start_address);
+            self.findings.push(MemoryFinding {
+                pc: None,
+                address,
+                width,
+                kind: MemoryFindingKind::PermissionViolation { access, region_start_address },
+            });
         }
     }
 
@@ -87,29 +179,23 @@ impl<SC: ScfiaComposition> Memory<SC> {
         fork_sink: &mut Option<SC::ForkSink>,
     ) -> ActiveValue<SC> {
         // First we check whether the address points into a symbolic volatile region.
-        for region in &self.symbolic_volatiles {
-            let begin = Instant::now();
-            let base_ast = region.base_symbol.get_z3_ast().clone();
-            // address < base_address
-            let lt = scfia.z3.new_bvult(&address.z3_ast, &base_ast, false);
-
-            // address >= base_address + length
-            let length_ast = scfia.z3.new_bv_concrete(region.length, 32); // TODO don't hardcode 32
-            let add_ast = scfia.z3.new_bvadd(&base_ast, &length_ast);
-            let ge = scfia.z3.new_bvuge(&address.z3_ast, &add_ast, false);
-            let assumption = scfia.z3.new_or(&lt, &ge);
-
-            let check_result = scfia.z3.check_assumptions(&[&assumption]);
-            if check_result == Z3_L_FALSE {
-                // If the address CAN NOT be outside the symbolic volatile region, we can return a new BVS
-                debug!("Symbolic volatile region returning new symbol after {}ms", begin.elapsed().as_millis());
-                return scfia.new_bv_symbol(width, None, fork_sink, None);
-            }
+        let begin = Instant::now();
+        if let Some(region) = self.symbolic_volatiles.iter_mut().find(|region| region.must_contain(&address.z3_ast, scfia)) {
+            // If the address CAN NOT be outside the symbolic volatile region, the region's policy answers the read
+            let offset = match region.policy {
+                SymbolicVolatilePolicy::FreshSymbol => None,
+                _ => region.offset(&address.z3_ast, scfia),
+            };
+            debug!(
+                "Symbolic volatile region answering read at offset {:x?} after {}ms",
+                offset,
+                begin.elapsed().as_millis()
+            );
+            return region.read(offset, width, scfia, fork_sink);
         }
 
         // Then we monomorphize the address value.
-        let mut address_candidates = if let Some(hints) = hints { hints.hints.pop().unwrap() } else { vec![] };
-        scfia.z3.monomorphize(&address.z3_ast, &mut address_candidates);
+        let address_candidates = monomorphize_address(address, scfia, hints);
         let unanimous_address = address_candidates.windows(2).all(|w| w[0] == w[1]);
         assert!(!address_candidates.is_empty());
 
@@ -148,32 +234,32 @@ impl<SC: ScfiaComposition> Memory<SC> {
         }
     }
 
-    fn write_symbolic(&mut self, address: &ActiveValueZ3<SC>, _value: &ActiveValue<SC>, width: u32, scfia: &Scfia<SC>, hints: &mut Option<SymbolicHints>) {
+    fn write_symbolic(
+        &mut self,
+        address: &ActiveValueZ3<SC>,
+        value: &ActiveValue<SC>,
+        width: u32,
+        scfia: &Scfia<SC>,
+        hints: &mut Option<SymbolicHints>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) {
         debug!("write_symbolic");
         // Symbolic writes can be symbolic volatile region writes or unanimous writes
 
         // Check for symbolic volatile region write
-        for region in &self.symbolic_volatiles {
-            let region_base = region.base_symbol.get_z3_ast();
-            // address < base_address
-            let lt = scfia.z3.new_bvult(&address.z3_ast, &region_base, false);
-
-            // address >= base_address + length
-            let region_length = scfia.z3.new_bv_concrete(region.length, 32);
-            let region_end = scfia.z3.new_bvadd(&region_base, &region_length);
-            let ge = scfia.z3.new_bvult(&region_end, &address.z3_ast, false);
-
-            let assumptions = [&lt, &ge];
-            debug!("checking symbolic offset write assumptions");
-            if scfia.z3.check_assumptions(&assumptions) == Z3_L_FALSE {
-                // If the address CAN NOT be outside the symbolic volatile region, we can skip the write
-                debug!("Symbolic offset write covered");
-                return;
-            }
+        debug!("checking symbolic offset write assumptions");
+        if let Some(region) = self.symbolic_volatiles.iter_mut().find(|region| region.must_contain(&address.z3_ast, scfia)) {
+            // If the address CAN NOT be outside the symbolic volatile region, the region's policy handles the write
+            debug!("Symbolic offset write covered");
+            let offset = match region.policy {
+                SymbolicVolatilePolicy::Shadow => region.offset(&address.z3_ast, scfia),
+                _ => None,
+            };
+            region.write(offset, value, width, scfia, fork_sink);
+            return;
         }
 
-        let mut candidates = if let Some(hints) = hints { hints.hints.pop().unwrap() } else { vec![] };
-        scfia.z3.monomorphize(&address.z3_ast, &mut candidates);
+        let candidates = monomorphize_address(address, scfia, hints);
         let _unanimous_address = candidates.windows(2).all(|w| w[0] == w[1]);
         assert!(!candidates.is_empty());
         if candidates.len() == 1 {
@@ -187,36 +273,57 @@ impl<SC: ScfiaComposition> Memory<SC> {
         }
     }
 
+    /// Finds the symbolic volatile region with a concrete base that contains `address`, and the offset into it.
+    /// Regions with a symbolic base are only considered for symbolic addresses.
+    fn find_concrete_symbolic_volatile(&self, address: u64) -> Option<(usize, u64)> {
+        self.symbolic_volatiles
+            .iter()
+            .enumerate()
+            .find_map(|(index, region)| region.concrete_offset(address).map(|offset| (index, offset)))
+    }
+
     fn read_concrete(&mut self, address: u64, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC> {
-        // Volatile regions may be inside larger stable regions, so we check them first
+        // Peripherals and volatile regions may be inside larger stable regions, so they take precedence
         //debug!("*{:x}", address);
-        for region in &self.volatiles {
-            if address >= region.start_address && address < region.start_address + region.length {
-                debug!("Volatile region 0x{:x} yielding fresh symbol at 0x{:x}", region.start_address, address);
-                return scfia.new_bv_symbol(width, None, fork_sink, None);
-            }
+        if let Some((index, offset)) = self.find_concrete_symbolic_volatile(address) {
+            return self.symbolic_volatiles[index].read(Some(offset), width, scfia, fork_sink);
         }
-        for region in &self.stables {
-            if address >= region.start_address && address < region.start_address + region.length {
-                return region.read(address, width, scfia, fork_sink);
+        if let Some(peripheral) = self.peripherals.get_mut(address) {
+            return peripheral.read(address, width, scfia, fork_sink);
+        }
+        if let Some(region) = self.volatiles.get(address) {
+            debug!("Volatile region 0x{:x} yielding fresh symbol at 0x{:x}", region.start_address, address);
+            return scfia.new_bv_symbol(width, None, fork_sink, None);
+        }
+        if let Some(region) = self.stables.get(address) {
+            if self.uninitialized_read_policy == UninitializedReadPolicy::Finding && !region.is_initialized(address, width) {
+                self.findings.push(MemoryFinding {
+                    pc: None,
+                    address: Some(address),
+                    width,
+                    kind: MemoryFindingKind::UninitializedRead,
+                });
             }
+            return region.read(address, width, self.uninitialized_read_policy, scfia, fork_sink);
         }
         panic!("read_concrete failed to resolve 0x{:x}", address)
     }
 
     pub fn write_concrete(&mut self, address: u64, value: &ActiveValue<SC>, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) {
         //debug!("*{:x} = {:?}", address, value);
-        for region in &mut self.stables {
-            if address >= region.start_address && address < region.start_address + region.length {
-                // TODO add width
-                return region.write(address, value, width, scfia, fork_sink);
-            }
+        if let Some((index, offset)) = self.find_concrete_symbolic_volatile(address) {
+            return self.symbolic_volatiles[index].write(Some(offset), value, width, scfia, fork_sink);
         }
-        for region in &self.volatiles {
-            if address >= region.start_address && address < region.start_address + region.length {
-                trace!("Volatile region 0x{:x} ignoring write", region.start_address);
-                return;
-            }
+        if let Some(peripheral) = self.peripherals.get_mut(address) {
+            return peripheral.write(address, value, width, scfia, fork_sink);
+        }
+        if let Some(region) = self.stables.get_mut(address) {
+            // TODO add width
+            return region.write(address, value, width, scfia, fork_sink);
+        }
+        if let Some(region) = self.volatiles.get(address) {
+            trace!("Volatile region 0x{:x} ignoring write", region.start_address);
+            return;
         }
 
         panic!("Concrete address {:x?} not satisfied by any region", address)
@@ -228,33 +335,86 @@ impl<SC: ScfiaComposition> Memory<SC> {
         cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
         cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
     ) -> Memory<SC> {
-        let mut cloned_stables = vec![];
+        let mut cloned_stables = RegionMap::new();
         let mut symbolic_volatiles = vec![];
+        let mut peripherals = RegionMap::new();
 
         for stable in &self.stables {
-            cloned_stables.push(stable.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired))
+            cloned_stables.insert(stable.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired))
         }
 
         for symbolic_volatile in &self.symbolic_volatiles {
             symbolic_volatiles.push(symbolic_volatile.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired))
         }
 
+        for peripheral in &self.peripherals {
+            peripherals.insert(peripheral.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired))
+        }
+
         Memory {
             stables: cloned_stables,
             volatiles: self.volatiles.clone(),
             symbolic_volatiles,
+            peripherals,
+            findings: self.findings.clone(),
+            uninitialized_read_policy: self.uninitialized_read_policy,
+            write_log: self.write_log.as_ref().map(|write_log| {
+                write_log
+                    .iter()
+                    .map(|write| write.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired))
+                    .collect()
+            }),
+        }
+    }
+
+    pub fn write_log_len(&self) -> usize {
+        self.write_log.as_ref().map_or(0, |write_log| write_log.len())
+    }
+
+    /// Attributes the writes logged since `first_write` to the instruction at `pc`.
+    pub fn attribute_writes(&mut self, first_write: usize, pc: Option<u64>) {
+        if let Some(write_log) = &mut self.write_log {
+            for write in &mut write_log[first_write..] {
+                write.pc = pc;
+            }
+        }
+    }
+
+    /// Lists the stable memory cells whose values differ in `other`, as `(address, value here, value in other)`.
+    ///
+    /// Symbolic cells are compared by identity, so this is meant for states which share an ancestor.
+    #[allow(clippy::type_complexity)]
+    pub fn diff(&self, other: &Memory<SC>) -> Vec<(u64, Option<ActiveValue<SC>>, Option<ActiveValue<SC>>)> {
+        let mut changes = vec![];
+        for region in &self.stables {
+            match other.stables.get(region.start_address) {
+                Some(other_region) if other_region.start_address == region.start_address => changes.append(&mut region.memory.diff(&other_region.memory)),
+                _ => changes.extend(region.memory.iter().map(|(address, value)| (address, Some(value), None))),
+            }
+        }
+        for other_region in &other.stables {
+            match self.stables.get(other_region.start_address) {
+                Some(region) if region.start_address == other_region.start_address => {}
+                _ => changes.extend(other_region.memory.iter().map(|(address, value)| (address, None, Some(value)))),
+            }
+        }
+        changes.sort_by_key(|(address, _, _)| *address);
+        changes
+    }
+
+    /// Attributes the findings recorded since `first_finding` to the instruction at `pc`.
+    pub fn attribute_findings(&mut self, first_finding: usize, pc: Option<u64>) {
+        for finding in &mut self.findings[first_finding..] {
+            finding.pc = pc;
         }
     }
 
     fn is_volatile(&self, candidates: &[u64], width: u32) -> bool {
         for candidate in candidates {
-            let mut covered = false;
-            for volatile_region in &self.volatiles {
-                if *candidate >= volatile_region.start_address && *candidate + width as u64 / 8 < volatile_region.start_address + volatile_region.length {
-                    covered = true;
-                    break;
-                }
-            }
+            let covered = match self.volatiles.get(*candidate) {
+                Some(volatile_region) => *candidate + width as u64 / 8 < volatile_region.start_address + volatile_region.length,
+                None => false,
+            };
 
             if !covered {
                 debug!("0x{:x} not covered by volatile regions", candidate);
@@ -271,3 +431,41 @@ impl<SC: ScfiaComposition> Default for Memory<SC> {
         Self::new()
     }
 }
+
+/// All interpretations of a symbolic address, starting with the hinted ones.
+fn monomorphize_address<SC: ScfiaComposition>(address: &ActiveValueZ3<SC>, scfia: &Scfia<SC>, hints: &mut Option<SymbolicHints>) -> Vec<u64> {
+    let mut candidates = match hints {
+        Some(hints) => hints.hints.pop().unwrap().into_iter().map(u128::from).collect(),
+        None => vec![],
+    };
+    scfia.z3.monomorphize(&address.z3_ast, &mut candidates);
+    candidates.into_iter().map(|candidate| u64::try_from(candidate).unwrap()).collect()
+}
+
+/// The start and length of every region in `[first, last]` which does not permit the access.
+fn restricted_regions<R: Region>(regions: &RegionMap<R>, first: u64, last: u64, access: MemoryAccess) -> Vec<(u64, u64)> {
+    regions
+        .overlapping(first, last)
+        .filter(|region| !region.permissions().allows(access))
+        .map(|region| (region.start_address(), region.length()))
+        .collect()
+}
+
+/// Whether a symbolic access of `bytes` bytes may overlap the region, i.e. start in `[region_start - bytes + 1, region_start + region_length)`.
+fn may_touch<SC: ScfiaComposition>(address: &ActiveValue<SC>, bytes: u128, region_start: u64, region_length: u64, scfia: &Scfia<SC>) -> bool {
+    let abstraction = address.get_abstraction();
+    let lowest = (region_start as u128).saturating_sub(bytes - 1);
+    let end = region_start as u128 + region_length as u128;
+    let address_ast = address.get_z3_ast();
+    let mut bounds = vec![];
+    if abstraction.min < lowest {
+        let lowest_ast = scfia.z3.new_bv_concrete(lowest, abstraction.width);
+        bounds.push(scfia.z3.new_bvuge(&address_ast, &lowest_ast, false));
+    }
+    if abstraction.max >= end {
+        let end_ast = scfia.z3.new_bv_concrete(end, abstraction.width);
+        bounds.push(scfia.z3.new_bvult(&address_ast, &end_ast, false));
+    }
+    let assumptions: Vec<_> = bounds.iter().collect();
+    scfia.z3.check_assumptions(&assumptions) != Z3_L_FALSE
+}
@@ -0,0 +1,207 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use crate::{
+    scfia::Scfia,
+    values::{active_value::ActiveValue, retired_value::RetiredValue},
+    ScfiaComposition,
+};
+
+pub const PAGE_SIZE: u64 = 0x1000;
+
+/// Byte-addressed storage split into pages whose concrete bytes are shared copy-on-write between clones.
+///
+/// Symbolic bytes belong to a specific `Scfia` and are translated into the clone's `Scfia` instead.
+#[derive(Debug)]
+pub struct PagedMemory<SC: ScfiaComposition> {
+    pages: BTreeMap<u64, Page<SC>>,
+}
+
+#[derive(Debug)]
+struct Page<SC: ScfiaComposition> {
+    concrete: Rc<ConcretePage>,
+    /// Sparse overlay of symbolic bytes, which are never also set in `concrete`.
+    symbolic: BTreeMap<u64, ActiveValue<SC>>,
+}
+
+/// Dense concrete bytes of a page, with a bitmap of the offsets that have been written.
+#[derive(Clone, Debug)]
+struct ConcretePage {
+    bytes: Vec<u8>,
+    initialized: Vec<u64>,
+}
+
+impl ConcretePage {
+    fn new() -> Self {
+        ConcretePage {
+            bytes: vec![0; PAGE_SIZE as usize],
+            initialized: vec![0; PAGE_SIZE as usize / 64],
+        }
+    }
+
+    fn get(&self, offset: u64) -> Option<u8> {
+        if self.contains(offset) {
+            Some(self.bytes[offset as usize])
+        } else {
+            None
+        }
+    }
+
+    fn contains(&self, offset: u64) -> bool {
+        self.initialized[offset as usize / 64] & (1 << (offset % 64)) != 0
+    }
+
+    fn insert(&mut self, offset: u64, byte: u8) {
+        self.bytes[offset as usize] = byte;
+        self.initialized[offset as usize / 64] |= 1 << (offset % 64);
+    }
+
+    fn remove(&mut self, offset: u64) {
+        self.initialized[offset as usize / 64] &= !(1 << (offset % 64));
+    }
+
+    fn len(&self) -> usize {
+        self.initialized.iter().map(|e| e.count_ones() as usize).sum()
+    }
+}
+
+impl<SC: ScfiaComposition> PagedMemory<SC> {
+    pub fn new() -> Self {
+        PagedMemory { pages: BTreeMap::new() }
+    }
+
+    pub fn get(&self, address: u64) -> Option<ActiveValue<SC>> {
+        let page = self.pages.get(&(address / PAGE_SIZE))?;
+        let offset = address % PAGE_SIZE;
+        if let Some(value) = page.symbolic.get(&offset) {
+            Some(value.clone())
+        } else {
+            page.concrete.get(offset).map(|byte| ActiveValue::BVConcrete(byte as u128, 8))
+        }
+    }
+
+    /// Reads `bytes` little-endian bytes if all of them are concrete, without touching any expressions.
+    pub fn get_concrete(&self, address: u64, bytes: u32) -> Option<u64> {
+        assert!(bytes <= 8);
+        let mut value = 0;
+        for i in (0..bytes as u64).rev() {
+            let page = self.pages.get(&((address + i) / PAGE_SIZE))?;
+            let byte = page.concrete.get((address + i) % PAGE_SIZE)?;
+            value = (value << 8) | byte as u64;
+        }
+        Some(value)
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => {
+                let offset = address % PAGE_SIZE;
+                page.symbolic.contains_key(&offset) || page.concrete.contains(offset)
+            }
+            None => false,
+        }
+    }
+
+    /// Stores a byte, copying the page's concrete bytes first if they are shared with another state.
+    pub fn insert(&mut self, address: u64, value: ActiveValue<SC>) {
+        let page = self.pages.entry(address / PAGE_SIZE).or_insert_with(|| Page {
+            concrete: Rc::new(ConcretePage::new()),
+            symbolic: BTreeMap::new(),
+        });
+        let offset = address % PAGE_SIZE;
+        if let ActiveValue::BVConcrete(byte, width) = value {
+            assert_eq!(width, 8);
+            page.symbolic.remove(&offset);
+            if page.concrete.get(offset) != Some(byte as u8) {
+                Rc::make_mut(&mut page.concrete).insert(offset, byte as u8);
+            }
+        } else {
+            if page.concrete.contains(offset) {
+                Rc::make_mut(&mut page.concrete).remove(offset);
+            }
+            page.symbolic.insert(offset, value);
+        }
+    }
+
+    /// Iterates over all stored bytes in ascending address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, ActiveValue<SC>)> + '_ {
+        self.pages.keys().flat_map(move |page_index| {
+            (0..PAGE_SIZE).filter_map(move |offset| {
+                let address = page_index * PAGE_SIZE + offset;
+                self.get(address).map(|value| (address, value))
+            })
+        })
+    }
+
+    /// The number of stored bytes.
+    pub fn len(&self) -> usize {
+        self.pages.values().map(|page| page.concrete.len() + page.symbolic.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lists the bytes which differ in `other`, skipping pages which still share their concrete bytes.
+    #[allow(clippy::type_complexity)]
+    pub fn diff(&self, other: &PagedMemory<SC>) -> Vec<(u64, Option<ActiveValue<SC>>, Option<ActiveValue<SC>>)> {
+        let mut changes = vec![];
+        let page_indices: BTreeSet<u64> = self.pages.keys().chain(other.pages.keys()).copied().collect();
+        for page_index in page_indices {
+            if let (Some(page), Some(other_page)) = (self.pages.get(&page_index), other.pages.get(&page_index)) {
+                if Rc::ptr_eq(&page.concrete, &other_page.concrete) && page.symbolic.is_empty() && other_page.symbolic.is_empty() {
+                    continue;
+                }
+            }
+
+            for address in page_index * PAGE_SIZE..(page_index + 1) * PAGE_SIZE {
+                let (value, other_value) = (self.get(address), other.get(address));
+                let same = match (&value, &other_value) {
+                    (Some(value), Some(other_value)) => value.is_same_value(other_value),
+                    (None, None) => true,
+                    _ => false,
+                };
+                if !same {
+                    changes.push((address, value, other_value));
+                }
+            }
+        }
+        changes
+    }
+
+    /// The number of pages whose concrete bytes are shared with at least one other state.
+    pub fn shared_pages(&self) -> usize {
+        self.pages.values().filter(|page| Rc::strong_count(&page.concrete) > 1).count()
+    }
+
+    pub(crate) fn clone_to_stdlib(
+        &self,
+        cloned_scfia: &Scfia<SC>,
+        cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
+        cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
+    ) -> PagedMemory<SC> {
+        let mut pages = BTreeMap::new();
+        for (page_index, page) in &self.pages {
+            let mut symbolic = BTreeMap::new();
+            for (offset, value) in &page.symbolic {
+                symbolic.insert(*offset, value.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired));
+            }
+            pages.insert(
+                *page_index,
+                Page {
+                    concrete: page.concrete.clone(),
+                    symbolic,
+                },
+            );
+        }
+        PagedMemory { pages }
+    }
+}
+
+impl<SC: ScfiaComposition> Default for PagedMemory<SC> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
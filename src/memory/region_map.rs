@@ -0,0 +1,113 @@
+use std::collections::{btree_map::Values, BTreeMap};
+
+use log::error;
+
+use super::regions::Permissions;
+
+pub trait Region {
+    fn start_address(&self) -> u64;
+
+    fn length(&self) -> u64;
+
+    fn permissions(&self) -> Permissions {
+        Permissions::RWX
+    }
+}
+
+/// Non-overlapping memory regions, indexed by their start address.
+#[derive(Clone, Debug)]
+pub struct RegionMap<R: Region> {
+    regions: BTreeMap<u64, R>,
+}
+
+impl<R: Region> RegionMap<R> {
+    pub fn new() -> Self {
+        RegionMap { regions: BTreeMap::new() }
+    }
+
+    /// Adds a region, panicking if it overlaps any region already in the map or does not fit the address space.
+    pub fn insert(&mut self, region: R) {
+        let start_address = region.start_address();
+        let length = region.length();
+        if start_address.checked_add(length.saturating_sub(1)).is_none() {
+            error!("Region {:#x} (len={:#x}) exceeds the address space", start_address, length);
+            panic!("Region {:#x} (len={:#x}) exceeds the address space", start_address, length)
+        }
+        let overlaps_previous = self
+            .regions
+            .range(..start_address)
+            .next_back()
+            .is_some_and(|(_, previous)| contains(previous, start_address));
+        let overlaps_next = self
+            .regions
+            .range(start_address..)
+            .next()
+            .is_some_and(|(next_start_address, _)| next_start_address - start_address < length);
+        if overlaps_previous || overlaps_next {
+            error!("Region {:#x} (len={:#x}) overlaps an existing region", start_address, region.length());
+            panic!("Region {:#x} (len={:#x}) overlaps an existing region", start_address, region.length())
+        }
+
+        self.regions.insert(start_address, region);
+    }
+
+    pub fn get(&self, address: u64) -> Option<&R> {
+        let (_, region) = self.regions.range(..=address).next_back()?;
+        if contains(region, address) {
+            Some(region)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, address: u64) -> Option<&mut R> {
+        let (_, region) = self.regions.range_mut(..=address).next_back()?;
+        if contains(region, address) {
+            Some(region)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the regions which contain any address in `[first, last]`, in ascending address order.
+    pub fn overlapping(&self, first: u64, last: u64) -> impl Iterator<Item = &R> {
+        let containing_first = self.regions.range(..first).next_back().map(|(_, region)| region);
+        containing_first
+            .filter(|region| contains(*region, first))
+            .into_iter()
+            .chain(self.regions.range(first..=last).map(|(_, region)| region))
+    }
+
+    /// Iterates over the regions in ascending address order.
+    pub fn iter(&self) -> Values<'_, u64, R> {
+        self.regions.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+/// Whether the region contains `address`, which must not be below its start, without computing its end address.
+fn contains<R: Region>(region: &R, address: u64) -> bool {
+    address - region.start_address() < region.length()
+}
+
+impl<'a, R: Region> IntoIterator for &'a RegionMap<R> {
+    type Item = &'a R;
+    type IntoIter = Values<'a, u64, R>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<R: Region> Default for RegionMap<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,35 +1,267 @@
-use std::collections::{BTreeMap, VecDeque};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+};
 
 use log::{debug, trace, warn};
+use z3_sys::Z3_L_FALSE;
 
+use super::{findings::MemoryAccess, pages::PagedMemory, region_map::Region};
 use crate::{
-    scfia::Scfia,
+    scfia::{bv_mask, Scfia},
     values::{
         active_value::{ActiveValue, ValueComment},
         retired_value::RetiredValue,
     },
+    z3_handle::Z3Ast,
     ScfiaComposition,
 };
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+/// How stable regions answer reads from bytes which were never written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UninitializedReadPolicy {
+    /// Yield a fresh symbol.
+    Symbol,
+    /// Yield zero.
+    Zero,
+    /// Yield a fresh symbol and record a finding.
+    Finding,
+}
+
+/// The byte order in which a region stores multi-byte values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
 #[derive(Debug)]
 pub struct StableMemoryRegion<SC: ScfiaComposition> {
-    pub memory: BTreeMap<u64, ActiveValue<SC>>,
+    pub memory: PagedMemory<SC>,
     pub start_address: u64,
     pub length: u64,
+    pub permissions: Permissions,
+    pub endianness: Endianness,
 }
 
 #[derive(Clone, Debug)]
 pub struct VolatileMemoryRegion {
     pub start_address: u64,
     pub length: u64,
+    pub permissions: Permissions,
 }
 
+/// What a symbolic volatile region yields on reads and does with writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolicVolatilePolicy {
+    /// Every read yields a fresh symbol, writes are dropped.
+    FreshSymbol,
+    /// Every byte yields the same symbol on each read, writes are dropped.
+    ConstantSymbol,
+    /// Bytes are kept in a shadow region, so reads yield what was written before.
+    Shadow,
+}
+
+/// A region whose base and length may be symbolic, e.g. a DMA buffer configured through device registers.
 #[derive(Debug)]
 pub struct SymbolicVolatileMemoryRegion<SC: ScfiaComposition> {
-    pub base_symbol: ActiveValue<SC>,
-    pub length: u64,
+    pub base: ActiveValue<SC>,
+    pub length: ActiveValue<SC>,
+    pub policy: SymbolicVolatilePolicy,
+    pub permissions: Permissions,
+    /// The smallest value the length could take when the region was created, so concrete offsets below it are inside without asking the solver.
+    pub min_length: u64,
+    /// The region's bytes by offset, used by the `ConstantSymbol` and `Shadow` policies.
+    pub shadow: StableMemoryRegion<SC>,
+}
+
+/// A memory-mapped device whose accesses are handled by the implementation.
+///
+/// Implementations may keep (symbolic) state between accesses, e.g. to model status registers or FIFOs.
+/// Peripherals shadow any stable or volatile regions they overlap.
+pub trait PeripheralRegion<SC: ScfiaComposition> {
+    fn start_address(&self) -> u64;
+
+    fn length(&self) -> u64;
+
+    fn permissions(&self) -> Permissions {
+        Permissions::RWX
+    }
+
+    fn read(&mut self, address: u64, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC>;
+
+    fn write(&mut self, address: u64, value: &ActiveValue<SC>, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>);
+
+    /// Clones the peripheral and its state into `cloned_scfia` when a state is cloned or forked.
+    fn clone_to_stdlib(
+        &self,
+        cloned_scfia: &Scfia<SC>,
+        cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
+        cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
+    ) -> Box<dyn PeripheralRegion<SC>>;
+}
+
+impl<SC: ScfiaComposition> Region for StableMemoryRegion<SC> {
+    fn start_address(&self) -> u64 {
+        self.start_address
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn permissions(&self) -> Permissions {
+        self.permissions
+    }
+}
+
+impl Region for VolatileMemoryRegion {
+    fn start_address(&self) -> u64 {
+        self.start_address
+    }
+
+    fn length(&self) -> u64 {
+        self.length
+    }
+
+    fn permissions(&self) -> Permissions {
+        self.permissions
+    }
+}
+
+impl<SC: ScfiaComposition> Region for Box<dyn PeripheralRegion<SC>> {
+    fn start_address(&self) -> u64 {
+        PeripheralRegion::start_address(self.as_ref())
+    }
+
+    fn length(&self) -> u64 {
+        PeripheralRegion::length(self.as_ref())
+    }
+
+    fn permissions(&self) -> Permissions {
+        PeripheralRegion::permissions(self.as_ref())
+    }
+}
+
+impl VolatileMemoryRegion {
+    pub fn new(start_address: u64, length: u64) -> Self {
+        Self::new_with_permissions(start_address, length, Permissions::RWX)
+    }
+
+    pub fn new_with_permissions(start_address: u64, length: u64, permissions: Permissions) -> Self {
+        VolatileMemoryRegion {
+            start_address,
+            length,
+            permissions,
+        }
+    }
 }
 impl<SC: ScfiaComposition> SymbolicVolatileMemoryRegion<SC> {
+    pub fn new(base: ActiveValue<SC>, length: ActiveValue<SC>, policy: SymbolicVolatilePolicy, scfia: &Scfia<SC>) -> Self {
+        let min_length = min_length(&length, scfia);
+        SymbolicVolatileMemoryRegion {
+            base,
+            length,
+            policy,
+            permissions: Permissions::RWX,
+            min_length,
+            // Offsets are bounded by the region's length, which may be symbolic
+            shadow: StableMemoryRegion::new(0, u64::MAX),
+        }
+    }
+
+    /// Whether `address` lies inside the region under every interpretation.
+    pub(crate) fn must_contain(&self, address: &Z3Ast<SC>, scfia: &Scfia<SC>) -> bool {
+        // address - base >= length also covers addresses below base, and does not overflow like base + length would
+        let width = scfia.z3.get_bv_width(address);
+        let offset = scfia.z3.new_bvsub(address, &to_z3_ast(&self.base, width, scfia));
+        let inside = self.offset_inside(&offset, scfia);
+        let outside = scfia.z3.new_not(&inside, false);
+        scfia.z3.check_assumptions(&[&outside]) == Z3_L_FALSE
+    }
+
+    /// Whether an access of `bytes` bytes at `address` may touch the region under some interpretation.
+    pub(crate) fn may_overlap(&self, address: &ActiveValue<SC>, bytes: u64, scfia: &Scfia<SC>) -> bool {
+        let width = address.get_abstraction().width;
+        let address = to_z3_ast(address, width, scfia);
+        let base = to_z3_ast(&self.base, width, scfia);
+        let bytes = scfia.z3.new_bv_concrete(bytes.into(), width);
+        let starts_inside = self.offset_inside(&scfia.z3.new_bvsub(&address, &base), scfia);
+        let ends_inside = scfia.z3.new_bvult(&scfia.z3.new_bvsub(&base, &address), &bytes, false);
+        let overlaps = scfia.z3.new_or(&starts_inside, &ends_inside, false);
+        scfia.z3.check_assumptions(&[&overlaps]) != Z3_L_FALSE
+    }
+
+    /// Whether `offset < length`, compared at the wider of both widths so that neither is truncated.
+    fn offset_inside(&self, offset: &Z3Ast<SC>, scfia: &Scfia<SC>) -> Z3Ast<SC> {
+        let width = scfia.z3.get_bv_width(offset).max(self.length.get_abstraction().width);
+        let offset = resize(offset.clone(), width, scfia);
+        scfia.z3.new_bvult(&offset, &to_z3_ast(&self.length, width, scfia), false)
+    }
+
+    /// The offset of the concrete `address` into the region, if the region has a concrete base and certainly contains it.
+    pub(crate) fn concrete_offset(&self, address: u64) -> Option<u64> {
+        let (base, width) = match self.base {
+            ActiveValue::BVConcrete(base, width) => (base, width),
+            _ => return None,
+        };
+        let offset = (address as u128).wrapping_sub(base) & bv_mask(width);
+        if offset < self.min_length as u128 {
+            Some(offset as u64)
+        } else {
+            None
+        }
+    }
+
+    /// The offset of `address` into the region, if it has exactly one interpretation.
+    pub(crate) fn offset(&self, address: &Z3Ast<SC>, scfia: &Scfia<SC>) -> Option<u64> {
+        let base = to_z3_ast(&self.base, scfia.z3.get_bv_width(address), scfia);
+        let offset = scfia.z3.new_bvsub(address, &base);
+        scfia.z3.get_unique(&offset).map(|offset| offset as u64)
+    }
+
+    pub(crate) fn read(&mut self, offset: Option<u64>, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC> {
+        let offset = match (self.policy, offset) {
+            (SymbolicVolatilePolicy::FreshSymbol, _) => return scfia.new_bv_symbol(width, None, fork_sink, None),
+            (_, None) => {
+                warn!("Symbolic volatile region read from ambiguous offset yielding fresh symbol");
+                return scfia.new_bv_symbol(width, None, fork_sink, None);
+            }
+            (_, Some(offset)) => offset,
+        };
+
+        // Materialise bytes on their first read, so later reads yield the same symbols
+        for byte in 0..width as u64 / 8 {
+            if !self.shadow.is_initialized(offset + byte, 8) {
+                let symbol = scfia.new_bv_symbol(8, None, fork_sink, None);
+                self.shadow.write(offset + byte, &symbol, 8, scfia, fork_sink);
+            }
+        }
+        self.shadow.read(offset, width, UninitializedReadPolicy::Symbol, scfia, fork_sink)
+    }
+
+    pub(crate) fn write(&mut self, offset: Option<u64>, value: &ActiveValue<SC>, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) {
+        if self.policy != SymbolicVolatilePolicy::Shadow {
+            return;
+        }
+
+        match offset {
+            Some(offset) => self.shadow.write(offset, value, width, scfia, fork_sink),
+            None => {
+                // Any byte may have been overwritten, so all of them are fresh symbols on their next read
+                warn!("Symbolic volatile region forgetting its shadow after write to ambiguous offset");
+                self.shadow = StableMemoryRegion::new(0, u64::MAX);
+            }
+        }
+    }
+
     pub(crate) fn clone_to_stdlib(
         &self,
         cloned_scfia: &Scfia<SC>,
@@ -37,50 +269,160 @@ impl<SC: ScfiaComposition> SymbolicVolatileMemoryRegion<SC> {
         cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
     ) -> SymbolicVolatileMemoryRegion<SC> {
         SymbolicVolatileMemoryRegion {
-            base_symbol: self.base_symbol.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
-            length: self.length,
+            base: self.base.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
+            length: self.length.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
+            policy: self.policy,
+            permissions: self.permissions,
+            min_length: self.min_length,
+            shadow: self.shadow.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
+        }
+    }
+}
+
+/// Searches the smallest value `length` may take under the current path condition.
+fn min_length<SC: ScfiaComposition>(length: &ActiveValue<SC>, scfia: &Scfia<SC>) -> u64 {
+    let length_ast = match length {
+        ActiveValue::BoolConcrete(_) => panic!(),
+        ActiveValue::BVConcrete(length, _) => return u64::try_from(*length).unwrap(),
+        ActiveValue::Expression(_) => length.get_z3_ast(),
+    };
+    let width = scfia.z3.get_bv_width(&length_ast);
+    let abstraction = length.get_abstraction();
+    let (mut low, mut high) = (abstraction.min, abstraction.max);
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        let below = scfia.z3.new_bvult(&length_ast, &scfia.z3.new_bv_concrete(middle, width), false);
+        if scfia.z3.check_assumptions(&[&below]) == Z3_L_FALSE {
+            low = middle
+        } else {
+            high = middle - 1
+        }
+    }
+    u64::try_from(low).unwrap_or(u64::MAX)
+}
+
+/// `value` zero-extended or truncated to `width` bits.
+fn to_z3_ast<SC: ScfiaComposition>(value: &ActiveValue<SC>, width: u32, scfia: &Scfia<SC>) -> Z3Ast<SC> {
+    match value {
+        ActiveValue::BoolConcrete(_) => panic!(),
+        ActiveValue::BVConcrete(value, _) => scfia.z3.new_bv_concrete(*value & bv_mask(width), width),
+        ActiveValue::Expression(_) => resize(value.get_z3_ast(), width, scfia),
+    }
+}
+
+fn resize<SC: ScfiaComposition>(value: Z3Ast<SC>, width: u32, scfia: &Scfia<SC>) -> Z3Ast<SC> {
+    let value_width = scfia.z3.get_bv_width(&value);
+    match value_width.cmp(&width) {
+        Ordering::Less => scfia.z3.new_zero_ext(width - value_width, &value),
+        Ordering::Equal => value,
+        Ordering::Greater => scfia.z3.new_extract(width - 1, 0, &value),
+    }
+}
+
+impl Permissions {
+    pub const RWX: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: true,
+    };
+    pub const RW: Permissions = Permissions {
+        read: true,
+        write: true,
+        execute: false,
+    };
+    pub const RX: Permissions = Permissions {
+        read: true,
+        write: false,
+        execute: true,
+    };
+    pub const R: Permissions = Permissions {
+        read: true,
+        write: false,
+        execute: false,
+    };
+
+    pub fn allows(&self, access: MemoryAccess) -> bool {
+        match access {
+            MemoryAccess::Read => self.read,
+            MemoryAccess::Write => self.write,
+            MemoryAccess::Execute => self.execute,
         }
     }
 }
 
 impl<SC: ScfiaComposition> StableMemoryRegion<SC> {
     pub fn new(start_address: u64, length: u64) -> Self {
-        debug!("StableMemoryRegion(start_address={:#x}, {:#x})", start_address, length);
+        Self::new_with_permissions(start_address, length, Permissions::RWX)
+    }
+
+    pub fn new_with_permissions(start_address: u64, length: u64, permissions: Permissions) -> Self {
+        debug!("StableMemoryRegion(start_address={:#x}, {:#x}, {:?})", start_address, length, permissions);
         StableMemoryRegion {
-            memory: BTreeMap::default(),
+            memory: PagedMemory::new(),
             start_address,
             length,
+            permissions,
+            endianness: Endianness::Little,
         }
     }
 
-    pub(crate) fn read(&self, address: u64, width: u32, scfia: &Scfia<SC>, fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC> {
+    /// The address of the `index`th least significant byte of a `bytes` wide value at `address`.
+    fn byte_address(&self, address: u64, bytes: u32, index: u32) -> u64 {
+        match self.endianness {
+            Endianness::Little => address + index as u64,
+            Endianness::Big => address + (bytes - 1 - index) as u64,
+        }
+    }
+
+    pub(crate) fn is_initialized(&self, address: u64, width: u32) -> bool {
+        (0..width as u64 / 8).all(|i| self.memory.contains(address + i))
+    }
+
+    pub(crate) fn read(
+        &self,
+        address: u64,
+        width: u32,
+        uninitialized_read_policy: UninitializedReadPolicy,
+        scfia: &Scfia<SC>,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> ActiveValue<SC> {
         assert_eq!(width % 8, 0);
         let bytes = width / 8;
+        if width <= 64 {
+            if let Some(value) = self.memory.get_concrete(address, bytes) {
+                let value = match self.endianness {
+                    Endianness::Little => value,
+                    Endianness::Big => value.swap_bytes() >> (64 - width),
+                };
+                trace!("{:#x} = *{:x}", value, address);
+                return scfia.new_bv_concrete(value.into(), width);
+            }
+        }
+
         let mut byte_values = VecDeque::new();
         let mut uninitialized = false;
         for i in 0..bytes {
-            if let Some(byte) = self.memory.get(&(address + i as u64)) {
-                byte_values.push_back(byte.clone());
+            let byte_address = self.byte_address(address, bytes, i);
+            if let Some(byte) = self.memory.get(byte_address) {
+                byte_values.push_back(byte);
+            } else if uninitialized_read_policy == UninitializedReadPolicy::Zero {
+                byte_values.push_back(scfia.new_bv_concrete(0, 8));
             } else {
                 warn!(
                     "Region {:#x} (len={:#x}) reading from uninitialized {:#x}",
-                    self.start_address,
-                    self.length,
-                    address + i as u64
+                    self.start_address, self.length, byte_address
                 );
                 uninitialized = true;
                 byte_values.push_back(scfia.new_bv_symbol(
                     8,
                     None,
                     fork_sink,
-                    Some(ValueComment::new(
-                        format!("Read from uninitialized address {:#x}", address + i as u64).to_string(),
-                    )),
+                    Some(ValueComment::new(format!("Read from uninitialized address {:#x}", byte_address).to_string())),
                 ))
             }
         }
 
-        // Little endian
+        // byte_values is ordered from least to most significant
         let mut value = byte_values.pop_front().unwrap();
         while !byte_values.is_empty() {
             let rhs = byte_values.pop_front().unwrap();
@@ -106,10 +448,18 @@ impl<SC: ScfiaComposition> StableMemoryRegion<SC> {
         assert_eq!(width % 8, 0);
         trace!("*{:x} (width={}) = {:?}", address, width, value);
         let bytes = width / 8;
+        if let ActiveValue::BVConcrete(value, _) = value {
+            for byte in 0..bytes {
+                self.memory
+                    .insert(self.byte_address(address, bytes, byte), scfia.new_bv_concrete((value >> (byte * 8)) & 0xff, 8));
+            }
+            return;
+        }
+
         for byte in 0..bytes {
             let v = scfia.new_bv_slice(value, (byte * 8) + 7, byte * 8, None, fork_sink, None);
             trace!("*{:x} = {:?}", address, v);
-            self.memory.insert(address + byte as u64, v);
+            self.memory.insert(self.byte_address(address, bytes, byte), v);
         }
     }
 
@@ -119,19 +469,12 @@ impl<SC: ScfiaComposition> StableMemoryRegion<SC> {
         cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
         cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
     ) -> StableMemoryRegion<SC> {
-        let mut clone = StableMemoryRegion {
+        StableMemoryRegion {
             length: self.length,
-            memory: BTreeMap::new(),
+            memory: self.memory.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
             start_address: self.start_address,
-        };
-
-        for (offset, value) in &self.memory {
-            // trace!("memcloning {:?}", value);
-            clone
-                .memory
-                .insert(*offset, value.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired));
+            permissions: self.permissions,
+            endianness: self.endianness,
         }
-
-        clone
     }
 }
@@ -0,0 +1,33 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    scfia::Scfia,
+    values::{active_value::ActiveValue, retired_value::RetiredValue},
+    ScfiaComposition,
+};
+
+/// A write performed through `Memory::write`.
+#[derive(Debug)]
+pub struct MemoryWrite<SC: ScfiaComposition> {
+    /// The address of the instruction which performed the write, if it was concrete.
+    pub pc: Option<u64>,
+    pub address: ActiveValue<SC>,
+    pub width: u32,
+    pub value: ActiveValue<SC>,
+}
+
+impl<SC: ScfiaComposition> MemoryWrite<SC> {
+    pub(crate) fn clone_to_stdlib(
+        &self,
+        cloned_scfia: &Scfia<SC>,
+        cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
+        cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
+    ) -> MemoryWrite<SC> {
+        MemoryWrite {
+            pc: self.pc,
+            address: self.address.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
+            width: self.width,
+            value: self.value.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
+        }
+    }
+}
@@ -5,7 +5,7 @@
 use log::debug;
 use std::{borrow::BorrowMut, fmt::Debug, collections::BTreeMap, rc::Rc};
 
-use crate::{memory::Memory, scfia::Scfia, values::{active_value::ActiveValue, retired_value::RetiredValue}, GenericForkSink, ScfiaComposition, StepContext, SymbolicHints};
+use crate::{memory::Memory, report::{ForkRecord, RegisterValue, StateDiff, StateReport, TerminationReason}, scfia::Scfia, values::{active_value::ActiveValue, retired_value::RetiredValue}, GenericForkSink, ScfiaComposition, StepContext, SymbolicHints};
 
 pub struct ARMv7M {
     pub state: SystemState,
@@ -18,6 +18,7 @@ pub struct ARMv7MForkSink {
     base_state: ARMv7M,
     new_values_history: Vec<ActiveValue<ARMv7MScfiaComposition>>,
     forks: Vec<ARMv7M>,
+    taken_forks: Vec<ForkRecord>,
 }
 
 #[derive(Debug, Clone)]
@@ -44,6 +45,9 @@ impl GenericForkSink<ARMv7MScfiaComposition> for ARMv7MForkSink {
         clone.scfia.next_symbol_id.set(fork_symbol_id + 1);
         let cloned_fork_symbol = cloned_actives.get(&fork_symbol_id).unwrap();
         cloned_fork_symbol.assert(&clone.scfia);
+        let pc = self.base_state.state.PC.try_get_concrete();
+        clone.scfia.fork_history.try_borrow_mut().unwrap().push(ForkRecord { pc, taken: false });
+        self.taken_forks.push(ForkRecord { pc, taken: true });
         self.forks.push(clone);
     }
 
@@ -60,6 +64,9 @@ impl ScfiaComposition for ARMv7MScfiaComposition {
 impl ARMv7M {
     pub fn step(&mut self, mut hints: Option<SymbolicHints>) {
         unsafe {
+            let first_finding = self.memory.findings.len();
+            let first_write = self.memory.write_log_len();
+            let pc = self.state.PC.try_get_concrete();
             let mut context = StepContext {
                 memory: &mut self.memory,
                 scfia: &self.scfia,
@@ -67,6 +74,8 @@ impl ARMv7M {
                 fork_sink: None,
             };
             _step(&mut self.state, &mut context);
+            self.memory.attribute_findings(first_finding, pc);
+            self.memory.attribute_writes(first_write, pc);
         }
     }
 
@@ -76,6 +85,9 @@ impl ARMv7M {
             let mut results = vec![];
 
             while let Some(mut state) = states.pop() {
+                let first_finding = state.memory.findings.len();
+                let first_write = state.memory.write_log_len();
+                let pc = state.state.PC.try_get_concrete();
                 let mut context = StepContext {
                     memory: &mut state.memory,
                     scfia: &state.scfia,
@@ -84,12 +96,17 @@ impl ARMv7M {
                         base_state: state.clone_model().0,
                         new_values_history: vec![],
                         forks: vec![],
+                        taken_forks: vec![],
                     }),
                 };
                 debug!("forking step start");
                 _step(&mut state.state, &mut context);
                 debug!("forking step done");
-                states.append(&mut context.fork_sink.unwrap().forks);
+                let mut fork_sink = context.fork_sink.unwrap();
+                states.append(&mut fork_sink.forks);
+                state.scfia.fork_history.try_borrow_mut().unwrap().append(&mut fork_sink.taken_forks);
+                state.memory.attribute_findings(first_finding, pc);
+                state.memory.attribute_writes(first_write, pc);
                 results.push(state)
             }
 
@@ -100,6 +117,13 @@ impl ARMv7M {
     pub fn clone_model(&self) -> (ARMv7M, BTreeMap<u64, ActiveValue<ARMv7MScfiaComposition>>, BTreeMap<u64, RetiredValue<ARMv7MScfiaComposition>>) {
         unsafe {
             let cloned_scfia = Scfia::new(Some(self.scfia.next_symbol_id.get()));
+            *cloned_scfia.fork_history.try_borrow_mut().unwrap() = self.scfia.fork_history.try_borrow().unwrap().clone();
+            if self.scfia.hash_consing_enabled() {
+                cloned_scfia.enable_hash_consing();
+            }
+            if let Some((max_depth, policy)) = self.scfia.expression_depth_limit() {
+                cloned_scfia.limit_expression_depth(max_depth, policy);
+            }
             let mut cloned_actives = BTreeMap::new();
             let mut cloned_retireds = BTreeMap::new();
             debug!("cloning scfia {:?} to {:?}",
@@ -112,6 +136,49 @@ impl ARMv7M {
             }, cloned_actives, cloned_retireds)
         }
     }
+
+    pub fn registers(&self) -> Vec<(&'static str, &ActiveValue<ARMv7MScfiaComposition>)> {
+        vec![
+            ("R0", &self.state.R0),
+            ("R1", &self.state.R1),
+            ("R2", &self.state.R2),
+            ("R3", &self.state.R3),
+            ("R4", &self.state.R4),
+            ("R5", &self.state.R5),
+            ("R6", &self.state.R6),
+            ("R7", &self.state.R7),
+            ("R8", &self.state.R8),
+            ("R9", &self.state.R9),
+            ("R10", &self.state.R10),
+            ("R11", &self.state.R11),
+            ("R12", &self.state.R12),
+            ("SP", &self.state.SP),
+            ("LR", &self.state.LR),
+            ("PC", &self.state.PC),
+            ("APSR.N", &self.state.APSR.N),
+            ("APSR.Z", &self.state.APSR.Z),
+            ("APSR.C", &self.state.APSR.C),
+            ("APSR.V", &self.state.APSR.V),
+            ("APSR.Q", &self.state.APSR.Q),
+            ("APSR.GE", &self.state.APSR.GE),
+        ]
+    }
+
+    /// Lists the registers and memory cells which differ in `other`, e.g. a state after running a routine.
+    pub fn diff(&self, other: &ARMv7M) -> StateDiff {
+        StateDiff::new(&self.registers(), &other.registers(), &self.memory, &other.memory)
+    }
+
+    pub fn report(&self, termination: TerminationReason) -> StateReport {
+        StateReport {
+            pc: RegisterValue::new(&self.state.PC),
+            registers: self.registers().into_iter().map(|(name, value)| (name.to_string(), RegisterValue::new(value))).collect(),
+            path_condition_size: self.scfia.z3.get_assertions_count(),
+            fork_history: self.scfia.fork_history.try_borrow().unwrap().clone(),
+            findings: self.memory.findings.clone(),
+            termination,
+        }
+    }
 }
 
 impl Debug for ARMv7M {
@@ -236,7 +303,7 @@ unsafe fn _step(mut state: *mut SystemState, context: *mut StepContext<ARMv7MScf
     let mut address_low: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_slice(&(*state).PC.clone(), 1, 0, None, &mut (*context).fork_sink, None);
     //if (EQUALS_BV2(address_low, BV2(0b00))) {
     // PC is 4 byte aligned
-    let mut instruction32: ActiveValue<ARMv7MScfiaComposition> = (*(*context).memory).read(&(*state).PC.clone(), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
+    let mut instruction32: ActiveValue<ARMv7MScfiaComposition> = (*(*context).memory).fetch(&(*state).PC.clone(), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
     _progress_pc_4(state, context);
     let mut b5: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_slice(&instruction32.clone(), 15, 11, None, &mut (*context).fork_sink, None);
     if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&b5.clone(), &(*context).scfia.new_bv_concrete(0b11101, 5), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
@@ -252,15 +319,15 @@ unsafe fn _step(mut state: *mut SystemState, context: *mut StepContext<ARMv7MScf
         if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&old_pc.clone(), &(*state).PC.clone(), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
             let mut b5: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_slice(&instruction32.clone(), 31, 27, None, &mut (*context).fork_sink, None);
             if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&b5.clone(), &(*context).scfia.new_bv_concrete(0b11101, 5), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
-                instruction32 = (*context).scfia.new_bv_concat(&(*(*context).memory).read(&(*state).PC.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), &(*context).scfia.new_bv_slice(&instruction32.clone(), 31, 16, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
+                instruction32 = (*context).scfia.new_bv_concat(&(*(*context).memory).fetch(&(*state).PC.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), &(*context).scfia.new_bv_slice(&instruction32.clone(), 31, 16, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
                 _progress_pc_2(state, context);
                 _thumb32(instruction32.clone(), state, context);
             } else if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&b5.clone(), &(*context).scfia.new_bv_concrete(0b11110, 5), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
-                instruction32 = (*context).scfia.new_bv_concat(&(*(*context).memory).read(&(*state).PC.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), &(*context).scfia.new_bv_slice(&instruction32.clone(), 31, 16, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
+                instruction32 = (*context).scfia.new_bv_concat(&(*(*context).memory).fetch(&(*state).PC.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), &(*context).scfia.new_bv_slice(&instruction32.clone(), 31, 16, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
                 _progress_pc_2(state, context);
                 _thumb32(instruction32.clone(), state, context);
             } else if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&b5.clone(), &(*context).scfia.new_bv_concrete(0b11111, 5), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
-                instruction32 = (*context).scfia.new_bv_concat(&(*(*context).memory).read(&(*state).PC.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), &(*context).scfia.new_bv_slice(&instruction32.clone(), 31, 16, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
+                instruction32 = (*context).scfia.new_bv_concat(&(*(*context).memory).fetch(&(*state).PC.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), &(*context).scfia.new_bv_slice(&instruction32.clone(), 31, 16, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
                 _progress_pc_2(state, context);
                 _thumb32(instruction32.clone(), state, context);
             } else {
@@ -1231,7 +1298,7 @@ unsafe fn _thumb16_misc_16_bit_instruction(mut instruction: ActiveValue<ARMv7MSc
         (*state).SP = (*context).scfia.new_bv_add(&(*state).SP.clone(), &(*context).scfia.new_bv_multiply(&(*context).scfia.new_bv_concrete(4, 32), &bit_count.clone(), 32, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
         for i in 0u64..=14 {
             if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&(*context).scfia.new_bv_concrete(1, 1), &(*context).scfia.new_bv_slice(&registers.clone(), i as u32, (i as u32+1-1), None, &mut (*context).fork_sink, None), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
-                _register_write_BV32_wide((*context).scfia.new_bv_concrete(i, 4), (*(*context).memory).read(&address.clone(), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), state, context);
+                _register_write_BV32_wide((*context).scfia.new_bv_concrete(i as u128, 4), (*(*context).memory).read(&address.clone(), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink), state, context);
                 address = (*context).scfia.new_bv_add(&address.clone(), &(*context).scfia.new_bv_concrete(4, 32), 32, None, &mut (*context).fork_sink, None);
             }
         }
@@ -1257,7 +1324,7 @@ unsafe fn _push(mut registers: ActiveValue<ARMv7MScfiaComposition>, mut state: *
     let mut address: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_sub(&SP.clone(), &(*context).scfia.new_bv_multiply(&(*context).scfia.new_bv_concrete(4, 32), &bit_count.clone(), 32, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
     for i in 0u64..=14 {
         if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&(*context).scfia.new_bv_concrete(1, 1), &(*context).scfia.new_bv_slice(&registers.clone(), i as u32, (i as u32+1-1), None, &mut (*context).fork_sink, None), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
-            (*(*context).memory).write(&address.clone(), &_register_read_BV32_wide((*context).scfia.new_bv_concrete(i, 4), state, context), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
+            (*(*context).memory).write(&address.clone(), &_register_read_BV32_wide((*context).scfia.new_bv_concrete(i as u128, 4), state, context), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
             address = (*context).scfia.new_bv_add(&address.clone(), &(*context).scfia.new_bv_concrete(4, 32), 32, None, &mut (*context).fork_sink, None);
         }
     }
@@ -1487,10 +1554,7 @@ unsafe fn _ror_C_BV32(mut x: ActiveValue<ARMv7MScfiaComposition>, mut shift: Act
         unimplemented!();
         //TODO assert
     }
-    let mut m: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_unsigned_remainder(&shift.clone(), &(*context).scfia.new_bv_concrete(32, 32), 32, None, &mut (*context).fork_sink, None);
-    let mut i1: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_sll(&x.clone(), &m.clone(), 32, None, &mut (*context).fork_sink, None);
-    let mut i2: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_sll(&x.clone(), &(*context).scfia.new_bv_sub(&(*context).scfia.new_bv_concrete(31, 32), &m.clone(), 32, None, &mut (*context).fork_sink, None), 32, None, &mut (*context).fork_sink, None);
-    let mut result: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_or(&i1.clone(), &i2.clone(), 32, None, &mut (*context).fork_sink, None);
+    let mut result: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_rotate_right(&x.clone(), &shift.clone(), 32, None, &mut (*context).fork_sink, None);
     let mut carry_out: ActiveValue<ARMv7MScfiaComposition> = (*context).scfia.new_bv_slice(&result.clone(), 31, 31, None, &mut (*context).fork_sink, None);
     return (*context).scfia.new_bv_concat(&result.clone(), &carry_out.clone(), 33, None, &mut (*context).fork_sink, None);
 }
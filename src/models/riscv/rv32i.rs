@@ -4,7 +4,7 @@
 use log::debug;
 use std::{borrow::BorrowMut, fmt::Debug, collections::BTreeMap, rc::Rc};
 
-use crate::{memory::Memory, scfia::Scfia, values::{active_value::ActiveValue, retired_value::RetiredValue}, GenericForkSink, ScfiaComposition, StepContext, SymbolicHints};
+use crate::{memory::Memory, report::{ForkRecord, RegisterValue, StateDiff, StateReport, TerminationReason}, scfia::Scfia, values::{active_value::ActiveValue, retired_value::RetiredValue}, GenericForkSink, ScfiaComposition, StepContext, SymbolicHints};
 
 pub struct RV32i {
     pub state: SystemState,
@@ -17,6 +17,7 @@ pub struct RV32iForkSink {
     base_state: RV32i,
     new_values_history: Vec<ActiveValue<RV32iScfiaComposition>>,
     forks: Vec<RV32i>,
+    taken_forks: Vec<ForkRecord>,
 }
 
 #[derive(Debug, Clone)]
@@ -43,6 +44,9 @@ impl GenericForkSink<RV32iScfiaComposition> for RV32iForkSink {
         clone.scfia.next_symbol_id.set(fork_symbol_id + 1);
         let cloned_fork_symbol = cloned_actives.get(&fork_symbol_id).unwrap();
         cloned_fork_symbol.assert(&clone.scfia);
+        let pc = self.base_state.state.pc.try_get_concrete();
+        clone.scfia.fork_history.try_borrow_mut().unwrap().push(ForkRecord { pc, taken: false });
+        self.taken_forks.push(ForkRecord { pc, taken: true });
         self.forks.push(clone);
     }
 
@@ -59,6 +63,9 @@ impl ScfiaComposition for RV32iScfiaComposition {
 impl RV32i {
     pub fn step(&mut self, mut hints: Option<SymbolicHints>) {
         unsafe {
+            let first_finding = self.memory.findings.len();
+            let first_write = self.memory.write_log_len();
+            let pc = self.state.pc.try_get_concrete();
             let mut context = StepContext {
                 memory: &mut self.memory,
                 scfia: &self.scfia,
@@ -66,6 +73,8 @@ impl RV32i {
                 fork_sink: None,
             };
             _step(&mut self.state, &mut context);
+            self.memory.attribute_findings(first_finding, pc);
+            self.memory.attribute_writes(first_write, pc);
         }
     }
 
@@ -75,6 +84,9 @@ impl RV32i {
             let mut results = vec![];
 
             while let Some(mut state) = states.pop() {
+                let first_finding = state.memory.findings.len();
+                let first_write = state.memory.write_log_len();
+                let pc = state.state.pc.try_get_concrete();
                 let mut context = StepContext {
                     memory: &mut state.memory,
                     scfia: &state.scfia,
@@ -83,12 +95,17 @@ impl RV32i {
                         base_state: state.clone_model().0,
                         new_values_history: vec![],
                         forks: vec![],
+                        taken_forks: vec![],
                     }),
                 };
                 debug!("forking step start");
                 _step(&mut state.state, &mut context);
                 debug!("forking step done");
-                states.append(&mut context.fork_sink.unwrap().forks);
+                let mut fork_sink = context.fork_sink.unwrap();
+                states.append(&mut fork_sink.forks);
+                state.scfia.fork_history.try_borrow_mut().unwrap().append(&mut fork_sink.taken_forks);
+                state.memory.attribute_findings(first_finding, pc);
+                state.memory.attribute_writes(first_write, pc);
                 results.push(state)
             }
 
@@ -99,6 +116,13 @@ impl RV32i {
     pub fn clone_model(&self) -> (RV32i, BTreeMap<u64, ActiveValue<RV32iScfiaComposition>>, BTreeMap<u64, RetiredValue<RV32iScfiaComposition>>) {
         unsafe {
             let cloned_scfia = Scfia::new(Some(self.scfia.next_symbol_id.get()));
+            *cloned_scfia.fork_history.try_borrow_mut().unwrap() = self.scfia.fork_history.try_borrow().unwrap().clone();
+            if self.scfia.hash_consing_enabled() {
+                cloned_scfia.enable_hash_consing();
+            }
+            if let Some((max_depth, policy)) = self.scfia.expression_depth_limit() {
+                cloned_scfia.limit_expression_depth(max_depth, policy);
+            }
             let mut cloned_actives = BTreeMap::new();
             let mut cloned_retireds = BTreeMap::new();
             debug!("cloning scfia {:?} to {:?}",
@@ -112,6 +136,60 @@ impl RV32i {
         }
     }
 
+    pub fn registers(&self) -> Vec<(&'static str, &ActiveValue<RV32iScfiaComposition>)> {
+        vec![
+            ("x0", &self.state.x0),
+            ("x1", &self.state.x1),
+            ("x2", &self.state.x2),
+            ("x3", &self.state.x3),
+            ("x4", &self.state.x4),
+            ("x5", &self.state.x5),
+            ("x6", &self.state.x6),
+            ("x7", &self.state.x7),
+            ("x8", &self.state.x8),
+            ("x9", &self.state.x9),
+            ("x10", &self.state.x10),
+            ("x11", &self.state.x11),
+            ("x12", &self.state.x12),
+            ("x13", &self.state.x13),
+            ("x14", &self.state.x14),
+            ("x15", &self.state.x15),
+            ("x16", &self.state.x16),
+            ("x17", &self.state.x17),
+            ("x18", &self.state.x18),
+            ("x19", &self.state.x19),
+            ("x20", &self.state.x20),
+            ("x21", &self.state.x21),
+            ("x22", &self.state.x22),
+            ("x23", &self.state.x23),
+            ("x24", &self.state.x24),
+            ("x25", &self.state.x25),
+            ("x26", &self.state.x26),
+            ("x27", &self.state.x27),
+            ("x28", &self.state.x28),
+            ("x29", &self.state.x29),
+            ("x30", &self.state.x30),
+            ("x31", &self.state.x31),
+            ("pc", &self.state.pc),
+        ]
+    }
+
+    /// Lists the registers and memory cells which differ in `other`, e.g. a state after running a routine.
+    pub fn diff(&self, other: &RV32i) -> StateDiff {
+        StateDiff::new(&self.registers(), &other.registers(), &self.memory, &other.memory)
+    }
+
+    pub fn report(&self, termination: TerminationReason) -> StateReport {
+        StateReport {
+            pc: RegisterValue::new(&self.state.pc),
+            registers: self.registers().into_iter().map(|(name, value)| (name.to_string(), RegisterValue::new(value))).collect(),
+            path_condition_size: self.scfia.z3.get_assertions_count(),
+            fork_history: self.scfia.fork_history.try_borrow().unwrap().clone(),
+            findings: self.memory.findings.clone(),
+            termination,
+        }
+    }
+
     pub fn debug(&self) {
         debug!("Register depths:");
         debug!("x0:\tdepth={}", self.state.x0.get_depth());
@@ -291,7 +369,7 @@ unsafe fn _sum(state: *mut SystemState, context: *mut StepContext<RV32iScfiaComp
 }
 
 unsafe fn _step(state: *mut SystemState, context: *mut StepContext<RV32iScfiaComposition>) {
-    let mut instruction_32: ActiveValue<RV32iScfiaComposition> = (*(*context).memory).read(&(*state).pc.clone(), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
+    let mut instruction_32: ActiveValue<RV32iScfiaComposition> = (*(*context).memory).fetch(&(*state).pc.clone(), 32, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
     let mut opcode: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_slice(&instruction_32.clone(), 6, 0, None, &mut (*context).fork_sink, None);
     if (*context).scfia.check_condition(&(*context).scfia.new_bool_eq(&opcode.clone(), &(*context).scfia.new_bv_concrete(0b11, 7), None, false, &mut (*context).fork_sink, None), &mut (*context).fork_sink) {
         let mut funct3: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_slice(&instruction_32.clone(), 14, 12, None, &mut (*context).fork_sink, None);
@@ -326,7 +404,7 @@ unsafe fn _step(state: *mut SystemState, context: *mut StepContext<RV32iScfiaCom
             let mut base_address: ActiveValue<RV32iScfiaComposition> = _register_read_BV32(state, rs1.clone(), context);
             let mut address: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_add(&base_address.clone(), &imm32.clone(), 32, None, &mut (*context).fork_sink, None);
             let mut value8: ActiveValue<RV32iScfiaComposition> = (*(*context).memory).read(&address.clone(), 8, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
-            let mut value: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_concat(&(*context).scfia.new_bv_concrete(0b0, 24), &value8.clone(), 32, None, &mut (*context).fork_sink, None);
+            let mut value: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_zero_extend(&value8.clone(), 8, 32, None, &mut (*context).fork_sink, None);
             _register_write_BV32(state, rd.clone(), value.clone(), context);
             _progress_pc_4(state, context);
         }
@@ -338,7 +416,7 @@ unsafe fn _step(state: *mut SystemState, context: *mut StepContext<RV32iScfiaCom
             let mut base_address: ActiveValue<RV32iScfiaComposition> = _register_read_BV32(state, rs1.clone(), context);
             let mut address: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_add(&base_address.clone(), &imm32.clone(), 32, None, &mut (*context).fork_sink, None);
             let mut value16: ActiveValue<RV32iScfiaComposition> = (*(*context).memory).read(&address.clone(), 16, (*context).scfia.clone(), &mut (*context).hints, &mut (*context).fork_sink);
-            let mut value: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_concat(&(*context).scfia.new_bv_concrete(0b0, 16), &value16.clone(), 32, None, &mut (*context).fork_sink, None);
+            let mut value: ActiveValue<RV32iScfiaComposition> = (*context).scfia.new_bv_zero_extend(&value16.clone(), 16, 32, None, &mut (*context).fork_sink, None);
             _register_write_BV32(state, rd.clone(), value.clone(), context);
             _progress_pc_4(state, context);
         }
@@ -0,0 +1,220 @@
+use serde_json::{json, Value};
+
+use crate::{
+    memory::{
+        findings::{MemoryAccess, MemoryFinding, MemoryFindingKind},
+        Memory,
+    },
+    values::active_value::ActiveValue,
+    ScfiaComposition,
+};
+
+/// A fork the state has gone through, and the side of the condition it continued on.
+#[derive(Debug, Clone)]
+pub struct ForkRecord {
+    pub pc: Option<u64>,
+    pub taken: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum TerminationReason {
+    TargetReached,
+    StepLimit,
+    Panicked,
+    Other(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum RegisterValue {
+    Concrete(u128),
+    Symbolic(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct StateReport {
+    pub pc: RegisterValue,
+    pub registers: Vec<(String, RegisterValue)>,
+    pub path_condition_size: u32,
+    pub fork_history: Vec<ForkRecord>,
+    pub findings: Vec<MemoryFinding>,
+    pub termination: TerminationReason,
+}
+
+/// A value before and after a change, `None` if the register or memory cell did not hold a value.
+#[derive(Debug, Clone)]
+pub struct ValueChange {
+    pub before: Option<RegisterValue>,
+    pub after: Option<RegisterValue>,
+}
+
+/// The registers and stable memory cells which differ between two states.
+#[derive(Debug, Clone, Default)]
+pub struct StateDiff {
+    pub registers: Vec<(String, ValueChange)>,
+    pub memory: Vec<(u64, ValueChange)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExplorationReport {
+    pub states: Vec<StateReport>,
+}
+
+impl ForkRecord {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "pc": self.pc,
+            "taken": self.taken,
+        })
+    }
+}
+
+impl MemoryFinding {
+    pub fn to_json(&self) -> Value {
+        let mut finding = json!({
+            "pc": self.pc,
+            "address": self.address,
+            "width": self.width,
+        });
+        match &self.kind {
+            MemoryFindingKind::PermissionViolation { access, region_start_address } => {
+                finding["kind"] = json!("permission_violation");
+                finding["access"] = match access {
+                    MemoryAccess::Read => json!("read"),
+                    MemoryAccess::Write => json!("write"),
+                    MemoryAccess::Execute => json!("execute"),
+                };
+                finding["region_start_address"] = json!(region_start_address);
+            }
+            MemoryFindingKind::UninitializedRead => finding["kind"] = json!("uninitialized_read"),
+            MemoryFindingKind::AmbiguousBulkAddress => finding["kind"] = json!("ambiguous_bulk_address"),
+            MemoryFindingKind::BulkLengthCapped { cap } => {
+                finding["kind"] = json!("bulk_length_capped");
+                finding["cap"] = json!(cap);
+            }
+        }
+        finding
+    }
+}
+
+impl TerminationReason {
+    pub fn to_json(&self) -> Value {
+        match self {
+            TerminationReason::TargetReached => json!("target_reached"),
+            TerminationReason::StepLimit => json!("step_limit"),
+            TerminationReason::Panicked => json!("panicked"),
+            TerminationReason::Other(reason) => json!(reason),
+        }
+    }
+}
+
+impl RegisterValue {
+    pub fn new<SC: ScfiaComposition>(value: &ActiveValue<SC>) -> Self {
+        match value {
+            ActiveValue::BoolConcrete(value) => RegisterValue::Concrete(*value as u128),
+            ActiveValue::BVConcrete(value, _) => RegisterValue::Concrete(*value),
+            ActiveValue::Expression(_) => match value.try_get_concrete_u128() {
+                Some(value) => RegisterValue::Concrete(value),
+                None => RegisterValue::Symbolic(value.get_z3_ast().to_smtlib()),
+            },
+        }
+    }
+
+    /// Concrete values beyond 64 bits do not fit a JSON number and are written as hex strings.
+    pub fn to_json(&self) -> Value {
+        match self {
+            RegisterValue::Concrete(value) => match u64::try_from(*value) {
+                Ok(value) => json!(value),
+                Err(_) => json!(format!("{:#x}", value)),
+            },
+            RegisterValue::Symbolic(smtlib) => json!(smtlib),
+        }
+    }
+}
+
+impl StateReport {
+    pub fn to_json(&self) -> Value {
+        let mut registers = serde_json::Map::new();
+        for (name, value) in &self.registers {
+            registers.insert(name.clone(), value.to_json());
+        }
+        json!({
+            "pc": self.pc.to_json(),
+            "registers": registers,
+            "path_condition_size": self.path_condition_size,
+            "fork_history": self.fork_history.iter().map(|e| e.to_json()).collect::<Vec<_>>(),
+            "findings": self.findings.iter().map(|e| e.to_json()).collect::<Vec<_>>(),
+            "termination": self.termination.to_json(),
+        })
+    }
+}
+
+impl ValueChange {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "before": self.before.as_ref().map(|e| e.to_json()),
+            "after": self.after.as_ref().map(|e| e.to_json()),
+        })
+    }
+}
+
+impl StateDiff {
+    pub fn new<SC: ScfiaComposition>(
+        registers_before: &[(&str, &ActiveValue<SC>)],
+        registers_after: &[(&str, &ActiveValue<SC>)],
+        memory_before: &Memory<SC>,
+        memory_after: &Memory<SC>,
+    ) -> Self {
+        let mut registers = vec![];
+        for ((name, before), (_, after)) in registers_before.iter().zip(registers_after) {
+            if !before.is_same_value(after) {
+                let change = ValueChange {
+                    before: Some(RegisterValue::new(before)),
+                    after: Some(RegisterValue::new(after)),
+                };
+                registers.push((name.to_string(), change));
+            }
+        }
+
+        let mut memory = vec![];
+        for (address, before, after) in memory_before.diff(memory_after) {
+            let change = ValueChange {
+                before: before.as_ref().map(RegisterValue::new),
+                after: after.as_ref().map(RegisterValue::new),
+            };
+            memory.push((address, change));
+        }
+
+        StateDiff { registers, memory }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut registers = serde_json::Map::new();
+        for (name, change) in &self.registers {
+            registers.insert(name.clone(), change.to_json());
+        }
+        json!({
+            "registers": registers,
+            "memory": self.memory.iter().map(|(address, change)| {
+                let mut change = change.to_json();
+                change["address"] = json!(address);
+                change
+            }).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl ExplorationReport {
+    pub fn push(&mut self, state: StateReport) {
+        self.states.push(state)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "states": self.states.iter().map(|e| e.to_json()).collect::<Vec<_>>(),
+        })
+    }
+
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string_pretty(&self.to_json()).unwrap()
+    }
+}
@@ -2,68 +2,126 @@ use std::cell::Cell;
 use std::cell::OnceCell;
 use std::cell::RefCell;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::marker::PhantomData;
 use std::ops::Not;
 use std::rc::Rc;
 use std::rc::Weak;
 
-use log::error;
+use log::debug;
 use log::trace;
+use log::warn;
 
+use crate::abstract_domain::abstract_expression;
+use crate::report::ForkRecord;
 use crate::values::active_value::ActiveExpression;
 use crate::values::active_value::ActiveValue;
 use crate::values::active_value::ActiveValueZ3;
+use crate::values::active_value::StructuralKey;
 use crate::values::active_value::ValueComment;
+use crate::values::bool_and_expression::BoolAndExpression;
+use crate::values::bool_and_expression::RetiredBoolAndExpression;
+use crate::values::bool_comparison_expression::to_signed;
+use crate::values::bool_comparison_expression::BoolComparisonExpression;
+use crate::values::bool_comparison_expression::ComparisonPredicate;
+use crate::values::bool_comparison_expression::RetiredBoolComparisonExpression;
 use crate::values::bool_eq_expression::BoolEqExpression;
 use crate::values::bool_eq_expression::RetiredBoolEqExpression;
+use crate::values::bool_implies_expression::BoolImpliesExpression;
+use crate::values::bool_implies_expression::RetiredBoolImpliesExpression;
 use crate::values::bool_not_expresssion::BoolNotExpression;
 use crate::values::bool_not_expresssion::RetiredBoolNotExpression;
+use crate::values::bool_or_expression::BoolOrExpression;
+use crate::values::bool_or_expression::RetiredBoolOrExpression;
 use crate::values::bool_signed_less_than_expression::BoolSignedLessThanExpression;
 use crate::values::bool_signed_less_than_expression::RetiredBoolSignedLessThanExpression;
 use crate::values::bool_unsigned_less_than_expression::BoolUnsignedLessThanExpression;
 use crate::values::bool_unsigned_less_than_expression::RetiredBoolUnsignedLessThanExpression;
+use crate::values::bool_xor_expression::BoolXorExpression;
+use crate::values::bool_xor_expression::RetiredBoolXorExpression;
 use crate::values::bv_add_expression::BVAddExpression;
 use crate::values::bv_add_expression::RetiredBVAddExpression;
 use crate::values::bv_and_expression::BVAndExpression;
 use crate::values::bv_and_expression::RetiredBVAndExpression;
+use crate::values::bv_ashr_expression::BVAshrExpression;
+use crate::values::bv_ashr_expression::RetiredBVAshrExpression;
 use crate::values::bv_concat_expression::BVConcatExpression;
 use crate::values::bv_concat_expression::RetiredBVConcatExpression;
 use crate::values::bv_concrete_expression::BVConcreteExpression;
 use crate::values::bv_concrete_expression::RetiredBVConcreteExpression;
+use crate::values::bv_ite_expression::BVIteExpression;
+use crate::values::bv_ite_expression::RetiredBVIteExpression;
 use crate::values::bv_multiply_expression::BVMultiplyExpression;
 use crate::values::bv_multiply_expression::RetiredBVMultiplyExpression;
 use crate::values::bv_not_expression::BVNotExpression;
 use crate::values::bv_not_expression::RetiredBVNotExpression;
 use crate::values::bv_or_expression::BVOrExpression;
 use crate::values::bv_or_expression::RetiredBVOrExpression;
+use crate::values::bv_rotate_left_expression::BVRotateLeftExpression;
+use crate::values::bv_rotate_left_expression::RetiredBVRotateLeftExpression;
+use crate::values::bv_rotate_right_expression::BVRotateRightExpression;
+use crate::values::bv_rotate_right_expression::RetiredBVRotateRightExpression;
 use crate::values::bv_sign_extend_expression::BVSignExtendExpression;
 use crate::values::bv_sign_extend_expression::RetiredBVSignExtendExpression;
 use crate::values::bv_slice_expression::BVSliceExpression;
 use crate::values::bv_slice_expression::RetiredBVSliceExpression;
 use crate::values::bv_sll_expression::BVSllExpression;
 use crate::values::bv_sll_expression::RetiredBVSllExpression;
+use crate::values::bv_srl_expression::BVSrlExpression;
 use crate::values::bv_srl_expression::RetiredBVSrlExpression;
 use crate::values::bv_sub_expression::BVSubExpression;
 use crate::values::bv_sub_expression::RetiredBVSubExpression;
 use crate::values::bv_symbol::BVSymbol;
 use crate::values::bv_symbol::RetiredBVSymbol;
+use crate::values::bv_unsigned_divide_expression::BVUnsignedDivideExpression;
+use crate::values::bv_unsigned_divide_expression::RetiredBVUnsignedDivideExpression;
 use crate::values::bv_unsigned_remainder_expression::BVUnsignedRemainderExpression;
 use crate::values::bv_unsigned_remainder_expression::RetiredBVUnsignedRemainderExpression;
 use crate::values::bv_xor_expression::BVXorExpression;
 use crate::values::bv_xor_expression::RetiredBVXorExpression;
+use crate::values::bv_zero_extend_expression::BVZeroExtendExpression;
+use crate::values::bv_zero_extend_expression::RetiredBVZeroExtendExpression;
 use crate::values::retired_value::ParentWeakReference;
 use crate::values::retired_value::RetiredExpression;
 use crate::values::retired_value::RetiredValue;
 use crate::values::retired_value::RetiredValueInner;
+use crate::values::retired_value::RetiredValueWeak;
+use crate::z3_handle::Counterexample;
 use crate::z3_handle::Z3Ast;
 use crate::z3_handle::Z3Handle;
 use crate::GenericForkSink;
 use crate::ScfiaComposition;
 
+type StructuralCache<SC> = BTreeMap<StructuralKey, Weak<RefCell<ActiveValueZ3<SC>>>>;
+
+/// A symbol of this state, which stays relevant after retiring as long as the path condition references it.
+enum SymbolReference<SC: ScfiaComposition> {
+    Active(Weak<RefCell<ActiveValueZ3<SC>>>),
+    Retired(RetiredValueWeak<SC>),
+}
+
+/// How `Scfia::limit_expression_depth` shortens a too deep value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthLimitPolicy {
+    /// Replace it with its only possible concrete value, and keep it if there is more than one.
+    Concretise,
+    /// Replace it with a fresh symbol asserted to equal it.
+    Abstract,
+    /// Concretise it if possible, and abstract it otherwise.
+    ConcretiseOrAbstract,
+}
+
 pub struct Scfia<SC: ScfiaComposition> {
     pub z3: Rc<Z3Handle<SC>>,
     pub next_symbol_id: Cell<u64>,
     pub selff: OnceCell<Weak<Self>>,
+    pub fork_history: RefCell<Vec<ForkRecord>>,
+    retirement_queue: RefCell<Vec<Rc<RefCell<ActiveValueZ3<SC>>>>>,
+    retiring: Cell<bool>,
+    structural_cache: RefCell<Option<StructuralCache<SC>>>,
+    depth_limit: Cell<Option<(usize, DepthLimitPolicy)>>,
+    symbols: RefCell<BTreeMap<u64, SymbolReference<SC>>>,
+    symbol_names: RefCell<BTreeSet<String>>,
     phantom: PhantomData<SC>,
 }
 
@@ -73,12 +131,116 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             z3: Z3Handle::new(),
             next_symbol_id: Cell::new(next_symbol_id.unwrap_or_default()),
             selff: OnceCell::new(),
+            fork_history: RefCell::new(vec![]),
+            retirement_queue: RefCell::new(vec![]),
+            retiring: Cell::new(false),
+            structural_cache: RefCell::new(None),
+            depth_limit: Cell::new(None),
+            symbols: RefCell::new(BTreeMap::new()),
+            symbol_names: RefCell::new(BTreeSet::new()),
             phantom: PhantomData,
         });
         scfia.selff.set(Rc::downgrade(&scfia)).unwrap();
         scfia
     }
 
+    /// Makes constructors return the existing live value instead of a new one for structurally identical expressions.
+    pub fn enable_hash_consing(&self) {
+        let mut structural_cache = self.structural_cache.try_borrow_mut().unwrap();
+        if structural_cache.is_none() {
+            *structural_cache = Some(BTreeMap::new());
+        }
+    }
+
+    pub fn hash_consing_enabled(&self) -> bool {
+        self.structural_cache.try_borrow().unwrap().is_some()
+    }
+
+    /// Keeps new bitvector expressions at most `max_depth` deep as far as `policy` allows, see `Scfia::limit_depth`.
+    pub fn limit_expression_depth(&self, max_depth: usize, policy: DepthLimitPolicy) {
+        self.depth_limit.set(Some((max_depth, policy)));
+    }
+
+    pub fn expression_depth_limit(&self) -> Option<(usize, DepthLimitPolicy)> {
+        self.depth_limit.get()
+    }
+
+    /// The number of live values which can currently be shared.
+    pub fn structural_cache_len(&self) -> usize {
+        self.structural_cache.try_borrow().unwrap().as_ref().map_or(0, |cache| cache.len())
+    }
+
+    pub fn new_bool_and(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        match (s1, s2) {
+            (ActiveValue::BoolConcrete(s1_value), ActiveValue::BoolConcrete(s2_value)) => {
+                return self.new_bool_concrete(*s1_value && *s2_value, None, fork_sink)
+            }
+            (ActiveValue::BoolConcrete(value), other) | (other, ActiveValue::BoolConcrete(value)) => {
+                return if *value {
+                    self.assert_if(other, is_assert)
+                } else {
+                    self.new_bool_concrete(false, None, fork_sink)
+                };
+            }
+            _ => {}
+        }
+
+        let z3_ast = || self.z3.new_and(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        self.new_active(
+            ActiveExpression::BoolAndExpression(BoolAndExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                is_assert,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_bool_comparison(
+        &self,
+        predicate: ComparisonPredicate,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        if let ActiveValue::BVConcrete(s1_value, s1_width) = s1 {
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                return self.new_bool_concrete(predicate.evaluate(*s1_value, *s2_value, *s1_width), None, fork_sink);
+            }
+        };
+
+        let s1 = s1.into_z3_value(self, fork_sink);
+        let s2 = s2.into_z3_value(self, fork_sink);
+        let z3_ast = || self.z3.new_comparison(predicate, &s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        self.new_active(
+            ActiveExpression::BoolComparisonExpression(BoolComparisonExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                predicate,
+                is_assert,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     pub fn new_bool_concrete(&self, value: bool, id: Option<u64>, _fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC> {
         let _id = if let Some(id) = id { id } else { self.next_symbol_id() };
         let _z3_ast = self.z3.new_bool_concrete(value);
@@ -102,8 +264,7 @@ impl<SC: ScfiaComposition> Scfia<SC> {
 
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_eq(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        let z3_ast = || self.z3.new_eq(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
         self.new_active(
             ActiveExpression::BoolEqExpression(BoolEqExpression {
                 s1: s1.get_z3_value(),
@@ -117,6 +278,50 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
+    pub fn new_bool_implies(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        match (s1, s2) {
+            (ActiveValue::BoolConcrete(s1_value), ActiveValue::BoolConcrete(s2_value)) => {
+                return self.new_bool_concrete(!s1_value || *s2_value, None, fork_sink)
+            }
+            (ActiveValue::BoolConcrete(s1_value), s2) => {
+                return if *s1_value {
+                    self.assert_if(s2, is_assert)
+                } else {
+                    self.new_bool_concrete(true, None, fork_sink)
+                };
+            }
+            (s1, ActiveValue::BoolConcrete(s2_value)) => {
+                return if *s2_value {
+                    self.new_bool_concrete(true, None, fork_sink)
+                } else {
+                    self.new_bool_not(s1, None, is_assert, fork_sink, comment)
+                };
+            }
+            _ => {}
+        }
+
+        let z3_ast = || self.z3.new_implies(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        self.new_active(
+            ActiveExpression::BoolImpliesExpression(BoolImpliesExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                is_assert,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     pub fn new_bool_not(
         &self,
         s1: &ActiveValue<SC>,
@@ -129,9 +334,14 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             return self.new_bool_concrete(!value_s1, None, fork_sink);
         };
 
+        if id.is_none() && !is_assert {
+            if let Some(value) = self.simplify_bool_not(s1) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_not(&s1.get_z3_ast(), is_assert);
+        let z3_ast = || self.z3.new_not(&s1.get_z3_ast(), is_assert);
         self.new_active(
             ActiveExpression::BoolNotExpression(BoolNotExpression {
                 s1: s1.get_z3_value(),
@@ -144,6 +354,43 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
+    pub fn new_bool_or(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        match (s1, s2) {
+            (ActiveValue::BoolConcrete(s1_value), ActiveValue::BoolConcrete(s2_value)) => {
+                return self.new_bool_concrete(*s1_value || *s2_value, None, fork_sink)
+            }
+            (ActiveValue::BoolConcrete(value), other) | (other, ActiveValue::BoolConcrete(value)) => {
+                return if *value {
+                    self.new_bool_concrete(true, None, fork_sink)
+                } else {
+                    self.assert_if(other, is_assert)
+                };
+            }
+            _ => {}
+        }
+
+        let z3_ast = || self.z3.new_or(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        self.new_active(
+            ActiveExpression::BoolOrExpression(BoolOrExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                is_assert,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     pub fn new_bool_signed_less_than(
         &self,
         s1: &ActiveValue<SC>,
@@ -153,17 +400,16 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         fork_sink: &mut Option<SC::ForkSink>,
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
-        if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
+        if let ActiveValue::BVConcrete(s1_value, s1_width) = s1 {
             if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
-                let slt = (*s1_value as i64) < (*s2_value as i64); // TODO this probably breaks for 64bit BVs
+                let slt = to_signed(*s1_value, *s1_width) < to_signed(*s2_value, *s1_width);
                 return self.new_bool_concrete(slt, None, fork_sink);
             }
         };
 
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvslt(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        let z3_ast = || self.z3.new_bvslt(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
         self.new_active(
             ActiveExpression::BoolSignedLessThanExpression(BoolSignedLessThanExpression {
                 s1: s1.get_z3_value(),
@@ -195,8 +441,7 @@ impl<SC: ScfiaComposition> Scfia<SC> {
 
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvult(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        let z3_ast = || self.z3.new_bvult(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
         self.new_active(
             ActiveExpression::BoolUnsignedLessThanExpression(BoolUnsignedLessThanExpression {
                 s1: s1.get_z3_value(),
@@ -210,6 +455,113 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
+    pub fn new_bool_unsigned_less_equal(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        self.new_bool_comparison(ComparisonPredicate::UnsignedLessEqual, s1, s2, id, is_assert, fork_sink, comment)
+    }
+
+    pub fn new_bool_unsigned_greater_than(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        self.new_bool_comparison(ComparisonPredicate::UnsignedGreaterThan, s1, s2, id, is_assert, fork_sink, comment)
+    }
+
+    pub fn new_bool_unsigned_greater_equal(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        self.new_bool_comparison(ComparisonPredicate::UnsignedGreaterEqual, s1, s2, id, is_assert, fork_sink, comment)
+    }
+
+    pub fn new_bool_signed_less_equal(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        self.new_bool_comparison(ComparisonPredicate::SignedLessEqual, s1, s2, id, is_assert, fork_sink, comment)
+    }
+
+    pub fn new_bool_signed_greater_than(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        self.new_bool_comparison(ComparisonPredicate::SignedGreaterThan, s1, s2, id, is_assert, fork_sink, comment)
+    }
+
+    pub fn new_bool_signed_greater_equal(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        self.new_bool_comparison(ComparisonPredicate::SignedGreaterEqual, s1, s2, id, is_assert, fork_sink, comment)
+    }
+
+    pub fn new_bool_xor(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        id: Option<u64>,
+        is_assert: bool,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        match (s1, s2) {
+            (ActiveValue::BoolConcrete(s1_value), ActiveValue::BoolConcrete(s2_value)) => return self.new_bool_concrete(s1_value != s2_value, None, fork_sink),
+            (ActiveValue::BoolConcrete(value), other) | (other, ActiveValue::BoolConcrete(value)) => {
+                return if *value {
+                    self.new_bool_not(other, None, is_assert, fork_sink, comment)
+                } else {
+                    self.assert_if(other, is_assert)
+                };
+            }
+            _ => {}
+        }
+
+        let z3_ast = || self.z3.new_xor(&s1.get_z3_ast(), &s2.get_z3_ast(), is_assert);
+        self.new_active(
+            ActiveExpression::BoolXorExpression(BoolXorExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                is_assert,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     pub fn new_bv_add(
         &self,
         s1: &ActiveValue<SC>,
@@ -220,19 +572,21 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
-            if let ActiveValue::BVConcrete(s2_value, s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s2_width).overflowing_sub(1).0; // TODO is rotate_left the right choice here?
-                let sum = s1_value.overflowing_add(*s2_value).0;
-                let value = mask & sum;
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                let value = s1_value.wrapping_add(*s2_value) & bv_mask(width);
                 return ActiveValue::BVConcrete(value, width);
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_add(s1, s2, width, fork_sink) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvadd(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvadd(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVAddExpression(BVAddExpression {
                 s1: s1.get_z3_value(),
@@ -262,10 +616,15 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_and(s1, s2, width) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvand(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvand(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVAndExpression(BVAndExpression {
                 s1: s1.get_z3_value(),
@@ -296,10 +655,15 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_concat(s1, s2, width, fork_sink) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvconcat(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvconcat(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVConcatExpression(BVConcatExpression {
                 s1: s1.get_z3_value(),
@@ -313,20 +677,19 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
-    pub fn new_bv_concrete(&self, value: u64, width: u32) -> ActiveValue<SC> {
+    pub fn new_bv_concrete(&self, value: u128, width: u32) -> ActiveValue<SC> {
         ActiveValue::BVConcrete(value, width)
     }
 
     pub fn new_bv_concrete_z3(
         &self,
-        value: u64,
+        value: u128,
         width: u32,
         id: Option<u64>,
         fork_sink: &mut Option<SC::ForkSink>,
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bv_concrete(value, width);
+        let z3_ast = || self.z3.new_bv_concrete(value, width);
         self.new_active(
             ActiveExpression::BVConcreteExpression(BVConcreteExpression {
                 value,
@@ -340,6 +703,38 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_bv_ite(
+        &self,
+        condition: &ActiveValue<SC>,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        id: Option<u64>,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        if let ActiveValue::BoolConcrete(condition_value) = condition {
+            return if *condition_value { s1.clone() } else { s2.clone() };
+        };
+
+        let s1 = s1.into_z3_value(self, fork_sink);
+        let s2 = s2.into_z3_value(self, fork_sink);
+        let z3_ast = || self.z3.new_ite(&condition.get_z3_ast(), &s1.get_z3_ast(), &s2.get_z3_ast());
+        self.new_active(
+            ActiveExpression::BVIteExpression(BVIteExpression {
+                condition: condition.get_z3_value(),
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                width,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     pub fn new_bv_multiply(
         &self,
         s1: &ActiveValue<SC>,
@@ -350,19 +745,21 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
-            if let ActiveValue::BVConcrete(s2_value, s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s2_width).overflowing_sub(1).0;
-                let product = s1_value.overflowing_mul(*s2_value).0;
-                let value = mask & product;
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                let value = s1_value.wrapping_mul(*s2_value) & bv_mask(width);
                 return ActiveValue::BVConcrete(value, width);
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_multiply(s1, s2, width) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvmul(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvmul(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVMultiplyExpression(BVMultiplyExpression {
                 s1: s1.get_z3_value(),
@@ -385,16 +782,20 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
-            let one: u64 = 1;
-            let mask = one.rotate_left(width).overflowing_sub(1).0;
+            let mask = bv_mask(width);
             let not = s1_value.not();
             let value = mask & not;
             return ActiveValue::BVConcrete(value, width);
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_not(s1) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvnot(&s1.get_z3_ast());
+        let z3_ast = || self.z3.new_bvnot(&s1.get_z3_ast());
         self.new_active(
             ActiveExpression::BVNotExpression(BVNotExpression {
                 s1: s1.get_z3_value(),
@@ -423,10 +824,15 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_or(s1, s2, width) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvor(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvor(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVOrExpression(BVOrExpression {
                 s1: s1.get_z3_value(),
@@ -440,6 +846,82 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
+    pub fn new_bv_rotate_left(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        id: Option<u64>,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                let amount = (s2_value % width as u128) as u32;
+                return ActiveValue::BVConcrete(rotate_left(*s1_value, amount, width), width);
+            }
+        };
+
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_rotate(s1, s2, width) {
+                return value;
+            }
+        }
+
+        let s1 = s1.into_z3_value(self, fork_sink);
+        let s2 = s2.into_z3_value(self, fork_sink);
+        let z3_ast = || self.z3.new_ext_rotate_left(&s1.get_z3_ast(), &s2.get_z3_ast());
+        self.new_active(
+            ActiveExpression::BVRotateLeftExpression(BVRotateLeftExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                width,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
+    pub fn new_bv_rotate_right(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        id: Option<u64>,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                let amount = (s2_value % width as u128) as u32;
+                return ActiveValue::BVConcrete(rotate_left(*s1_value, (width - amount) % width, width), width);
+            }
+        };
+
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_rotate(s1, s2, width) {
+                return value;
+            }
+        }
+
+        let s1 = s1.into_z3_value(self, fork_sink);
+        let s2 = s2.into_z3_value(self, fork_sink);
+        let z3_ast = || self.z3.new_ext_rotate_right(&s1.get_z3_ast(), &s2.get_z3_ast());
+        self.new_active(
+            ActiveExpression::BVRotateRightExpression(BVRotateRightExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                width,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     pub fn new_bv_sign_extend(
         &self,
         s1: &ActiveValue<SC>,
@@ -451,15 +933,20 @@ impl<SC: ScfiaComposition> Scfia<SC> {
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, s1_width) = s1 {
             // https://graphics.stanford.edu/~seander/bithacks.html#VariableSignExtend
-            let m: u64 = 1 << (s1_width - 1);
-            let x = s1_value & ((1 << s1_width) - 1);
-            let value = (x ^ m).overflowing_sub(m).0;
+            let m: u128 = 1 << (s1_width - 1);
+            let x = s1_value & bv_mask(*s1_width);
+            let value = (x ^ m).overflowing_sub(m).0 & bv_mask(output_width);
             return ActiveValue::BVConcrete(value, output_width);
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_sign_extend(s1, input_width, output_width, fork_sink) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_sign_ext(output_width - input_width, &s1.get_z3_ast());
+        let z3_ast = || self.z3.new_sign_ext(output_width - input_width, &s1.get_z3_ast());
         self.new_active(
             ActiveExpression::BVSignExtendExpression(BVSignExtendExpression {
                 s1: s1.get_z3_value(),
@@ -486,15 +973,19 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
             // https://graphics.stanford.edu/~seander/bithacks.html#VariableSignExtend
             let shifted = s1_value >> low;
-            let mask = (1 << width) - 1;
-            let value = shifted & mask;
+            let value = shifted & bv_mask(width);
             return ActiveValue::BVConcrete(value, width);
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_slice(s1, high, low, fork_sink) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
         let width = high - low + 1;
-        let z3_ast = self.z3.new_extract(high, low, &s1.get_z3_ast());
+        let z3_ast = || self.z3.new_extract(high, low, &s1.get_z3_ast());
         self.new_active(
             ActiveExpression::BVSliceExpression(BVSliceExpression {
                 s1: s1.get_z3_value(),
@@ -520,17 +1011,24 @@ impl<SC: ScfiaComposition> Scfia<SC> {
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, s1_width) = s1 {
             if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s1_width).overflowing_sub(1).0;
-                let value = (s1_value << s2_value) & mask;
+                let value = if *s2_value < *s1_width as u128 {
+                    (s1_value << s2_value) & bv_mask(*s1_width)
+                } else {
+                    0
+                };
                 return ActiveValue::BVConcrete(value, width);
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_shift(s1, s2, width) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvshl(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvshl(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVSllExpression(BVSllExpression {
                 s1: s1.get_z3_value(),
@@ -555,19 +1053,26 @@ impl<SC: ScfiaComposition> Scfia<SC> {
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, s1_width) = s1 {
             if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s1_width).overflowing_sub(1).0;
-                let value = (s1_value >> s2_value) & mask;
+                let value = if *s2_value < *s1_width as u128 {
+                    (s1_value >> s2_value) & bv_mask(*s1_width)
+                } else {
+                    0
+                };
                 return ActiveValue::BVConcrete(value, width);
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_shift(s1, s2, width) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvlshr(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvlshr(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
-            ActiveExpression::BVSllExpression(BVSllExpression {
+            ActiveExpression::BVSrlExpression(BVSrlExpression {
                 s1: s1.get_z3_value(),
                 s2: s2.get_z3_value(),
                 width,
@@ -579,7 +1084,7 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
-    pub fn new_bv_asr(
+    pub fn new_bv_ashr(
         &self,
         s1: &ActiveValue<SC>,
         s2: &ActiveValue<SC>,
@@ -587,28 +1092,51 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         id: Option<u64>,
         fork_sink: &mut Option<SC::ForkSink>,
         comment: Option<ValueComment>,
-    ) -> (ActiveValue<SC>, ActiveValue<SC>) {
+    ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, s1_width) = s1 {
             if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
-                //TODO review
-                //TODO this is extremely ugly, maybe we should push it down to SISAL, let it do two shifts
-                let mut shift_mask = 1 << (width - 1);
-                let mut carry_mask = 1;
-                for i in 1..*s2_value {
-                    shift_mask |= 1 << (width as u64 - i - 1);
-                    carry_mask <<= 1;
-                }
-                let value = (s1_value >> s2_value) | shift_mask;
-                let carry_out = (s1_value & carry_mask) >> (s2_value - 1);
-                return (ActiveValue::BVConcrete(value, width), ActiveValue::BVConcrete(carry_out, 1));
+                let shift = (*s2_value).min(*s1_width as u128 - 1) as u32;
+                let value = (to_signed(*s1_value, *s1_width) >> shift) as u128 & bv_mask(width);
+                return ActiveValue::BVConcrete(value, width);
             }
         };
 
+        if id.is_none() && s2.try_get_concrete_u128() == Some(0) {
+            return s1.clone();
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvashr(&s1.get_z3_ast(), &s2.get_z3_ast());
-        todo!();
+        let z3_ast = || self.z3.new_bvashr(&s1.get_z3_ast(), &s2.get_z3_ast());
+        self.new_active(
+            ActiveExpression::BVAshrExpression(BVAshrExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                width,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
+    /// Shifts `s1` right arithmetically and returns the result and the last bit shifted out, i.e. ARM's `ASR_C`.
+    pub fn new_bv_asr(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        id: Option<u64>,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> (ActiveValue<SC>, ActiveValue<SC>) {
+        let value = self.new_bv_ashr(s1, s2, width, id, fork_sink, comment);
+        let shift_width = self.bv_width(s2);
+        let last_shift = self.new_bv_sub(s2, &ActiveValue::BVConcrete(1, shift_width), shift_width, None, fork_sink, None);
+        let last_shifted = self.new_bv_ashr(s1, &last_shift, width, None, fork_sink, None);
+        let carry = self.new_bv_slice(&last_shifted, 0, 0, None, fork_sink, None);
+        (value, carry)
     }
 
     pub fn new_bv_sub(
@@ -621,19 +1149,21 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
-            if let ActiveValue::BVConcrete(s2_value, s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s2_width).overflowing_sub(1).0;
-                let sum = s1_value.overflowing_sub(*s2_value).0;
-                let value = mask & sum;
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                let value = s1_value.wrapping_sub(*s2_value) & bv_mask(width);
                 return ActiveValue::BVConcrete(value, width);
             }
-        };
+        };
+
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_sub(s1, s2, width, fork_sink) {
+                return value;
+            }
+        }
 
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvsub(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvsub(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVSubExpression(BVSubExpression {
                 s1: s1.get_z3_value(),
@@ -648,9 +1178,44 @@ impl<SC: ScfiaComposition> Scfia<SC> {
     }
 
     pub fn new_bv_symbol(&self, width: u32, id: Option<u64>, fork_sink: &mut Option<SC::ForkSink>, comment: Option<ValueComment>) -> ActiveValue<SC> {
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_fresh_const(width);
-        self.new_active(ActiveExpression::BVSymbol(BVSymbol { width }), z3_ast, id, fork_sink, comment)
+        let z3_ast = || self.z3.new_fresh_const(width);
+        self.new_active(ActiveExpression::BVSymbol(BVSymbol { width, name: None }), z3_ast, id, fork_sink, comment)
+    }
+
+    /// Creates a symbol whose Z3 constant carries the given name.
+    /// Names are unique within a state, a name that is already taken gets a numbered suffix.
+    pub fn new_bv_named_symbol(
+        &self,
+        name: &str,
+        width: u32,
+        id: Option<u64>,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        let mut unique_name = name.to_string();
+        if id.is_none() {
+            let symbol_names = self.symbol_names.try_borrow().unwrap();
+            let mut suffix = 1;
+            while symbol_names.contains(&unique_name) {
+                unique_name = format!("{}!{}", name, suffix);
+                suffix += 1;
+            }
+            if unique_name != name {
+                warn!("symbol name {} is taken, using {}", name, unique_name);
+            }
+        }
+        let z3_ast = self.z3.new_named_const(&unique_name, width);
+        let z3_ast = || z3_ast;
+        self.new_active(
+            ActiveExpression::BVSymbol(BVSymbol {
+                width,
+                name: Some(unique_name),
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
     }
 
     pub fn new_bv_unsigned_remainder(
@@ -663,19 +1228,16 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
-            if let ActiveValue::BVConcrete(s2_value, s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s2_width).overflowing_sub(1).0;
-                let remainder = s1_value % s2_value;
-                let value = mask & remainder;
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                // SMT-LIB defines x % 0 as x
+                let value = s1_value.checked_rem(*s2_value).unwrap_or(*s1_value) & bv_mask(width);
                 return ActiveValue::BVConcrete(value, width);
             }
         };
 
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvurem(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvurem(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVUnsignedRemainderExpression(BVUnsignedRemainderExpression {
                 s1: s1.get_z3_value(),
@@ -699,19 +1261,27 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
         if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
-            if let ActiveValue::BVConcrete(s2_value, s2_width) = s2 {
-                let one: u64 = 1;
-                let mask = one.rotate_left(*s2_width).overflowing_sub(1).0;
-                let remainder = s1_value / s2_value;
-                let value = mask & remainder;
+            if let ActiveValue::BVConcrete(s2_value, _s2_width) = s2 {
+                // SMT-LIB defines x / 0 as all ones
+                let value = s1_value.checked_div(*s2_value).unwrap_or(u128::MAX) & bv_mask(width);
                 return ActiveValue::BVConcrete(value, width);
             }
         };
 
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        todo!()
+        let z3_ast = || self.z3.new_bvudiv(&s1.get_z3_ast(), &s2.get_z3_ast());
+        self.new_active(
+            ActiveExpression::BVUnsignedDivideExpression(BVUnsignedDivideExpression {
+                s1: s1.get_z3_value(),
+                s2: s2.get_z3_value(),
+                width,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
     }
 
     pub fn new_bv_xor(
@@ -730,10 +1300,15 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             }
         };
 
+        if id.is_none() {
+            if let Some(value) = self.simplify_bv_xor(s1, s2, width) {
+                return value;
+            }
+        }
+
         let s1 = s1.into_z3_value(self, fork_sink);
         let s2 = s2.into_z3_value(self, fork_sink);
-        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
-        let z3_ast = self.z3.new_bvxor(&s1.get_z3_ast(), &s2.get_z3_ast());
+        let z3_ast = || self.z3.new_bvxor(&s1.get_z3_ast(), &s2.get_z3_ast());
         self.new_active(
             ActiveExpression::BVXorExpression(BVXorExpression {
                 s1: s1.get_z3_value(),
@@ -747,51 +1322,188 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         )
     }
 
+    /// Returns a bool operand a connective folded to, asserting it if the connective was to be asserted.
+    fn assert_if(&self, value: &ActiveValue<SC>, is_assert: bool) -> ActiveValue<SC> {
+        if is_assert {
+            value.assert(self)
+        }
+        value.clone()
+    }
+
+    pub fn new_bv_zero_extend(
+        &self,
+        s1: &ActiveValue<SC>,
+        input_width: u32,
+        output_width: u32,
+        id: Option<u64>,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        if let ActiveValue::BVConcrete(s1_value, _s1_width) = s1 {
+            return ActiveValue::BVConcrete(s1_value & bv_mask(input_width), output_width);
+        };
+
+        if id.is_none() && input_width == output_width {
+            return s1.clone();
+        }
+
+        let s1 = s1.into_z3_value(self, fork_sink);
+        let z3_ast = || self.z3.new_zero_ext(output_width - input_width, &s1.get_z3_ast());
+        self.new_active(
+            ActiveExpression::BVZeroExtendExpression(BVZeroExtendExpression {
+                s1: s1.get_z3_value(),
+                width: output_width,
+                input_width,
+            }),
+            z3_ast,
+            id,
+            fork_sink,
+            comment,
+        )
+    }
+
     fn next_symbol_id(&self) -> u64 {
         let id = self.next_symbol_id.get();
         self.next_symbol_id.set(id + 1);
         id
     }
 
+    /// Wraps the expression in a new active value, or returns the live value with the same structure.
+    /// The Z3 AST is only built if a new value is needed.
     fn new_active(
         &self,
         expression: ActiveExpression<SC>,
-        z3_ast: Z3Ast<SC>,
-        id: u64,
+        z3_ast: impl FnOnce() -> Z3Ast<SC>,
+        id: Option<u64>,
         fork_sink: &mut Option<SC::ForkSink>,
         comment: Option<ValueComment>,
     ) -> ActiveValue<SC> {
-        let value = ActiveValue::Expression(Rc::new(RefCell::new(ActiveValueZ3 {
+        let key = if self.hash_consing_enabled() { expression.structural_key() } else { None };
+        if let (None, Some(key)) = (id, &key) {
+            let existing = self.structural_cache.try_borrow().unwrap().as_ref().unwrap().get(key).and_then(|e| e.upgrade());
+            if let Some(existing) = existing {
+                let value = ActiveValue::Expression(existing);
+                if let Some(fork_sink) = fork_sink {
+                    fork_sink.push_value(value.clone())
+                }
+                return value;
+            }
+        }
+
+        let z3_ast = z3_ast();
+        let is_new = id.is_none();
+        let id = if let Some(id) = id { id } else { self.next_symbol_id() };
+        let abstraction = abstract_expression(&expression);
+        let is_symbol = matches!(expression, ActiveExpression::BVSymbol(_));
+        if let ActiveExpression::BVSymbol(BVSymbol { name: Some(name), .. }) = &expression {
+            self.symbol_names.try_borrow_mut().unwrap().insert(name.clone());
+        }
+        let value = Rc::new(RefCell::new(ActiveValueZ3 {
             id,
             z3_ast,
             expression,
+            abstraction,
+            depth: 1,
             inherited_asts: BTreeMap::new(),
             discovered_asts: BTreeMap::new(),
             scfia: self.selff.get().unwrap().clone(),
             comment,
             can_inherit: true,
-        })));
+        }));
+        let depth = value.try_borrow().unwrap().compute_depth();
+        value.try_borrow_mut().unwrap().depth = depth;
+        let is_bool = value.try_borrow().unwrap().expression.is_bool();
+        if is_symbol {
+            let symbol = SymbolReference::Active(Rc::downgrade(&value));
+            self.symbols.try_borrow_mut().unwrap().insert(id, symbol);
+        }
+        let policy = match self.depth_limit.get() {
+            Some((max_depth, policy)) if is_new && depth > max_depth && !is_bool => Some(policy),
+            _ => None,
+        };
+        let limited = policy.is_some();
+        if let (Some(key), false) = (key, limited) {
+            self.structural_cache
+                .try_borrow_mut()
+                .unwrap()
+                .as_mut()
+                .unwrap()
+                .insert(key, Rc::downgrade(&value));
+        }
 
+        let value = ActiveValue::Expression(value);
         if let Some(fork_sink) = fork_sink {
             fork_sink.push_value(value.clone())
         }
-        value
+        match policy {
+            Some(policy) => self.limit_depth(value, policy, fork_sink),
+            None => value,
+        }
+    }
+
+    /// Replaces a too deep value with its only possible concrete value and/or a fresh symbol asserted to equal it, as `policy` allows.
+    fn limit_depth(&self, value: ActiveValue<SC>, policy: DepthLimitPolicy, fork_sink: &mut Option<SC::ForkSink>) -> ActiveValue<SC> {
+        let width = self.bv_width(&value);
+        if policy != DepthLimitPolicy::Abstract {
+            if let Some(unique) = self.z3.get_unique(&value.get_z3_ast()) {
+                debug!("concretising {:?} to {:#x}", value, unique);
+                return self.new_bv_concrete(unique, width);
+            }
+            if policy == DepthLimitPolicy::Concretise {
+                return value;
+            }
+        }
+
+        let symbol = self.new_bv_symbol(width, None, fork_sink, None);
+        debug!("abstracting {:?} by {:?}", value, symbol);
+        self.new_bool_eq(&symbol, &value, None, true, fork_sink, None);
+        symbol
     }
 
     pub fn new_inactive(&self, expression: RetiredExpression<SC>, z3_ast: Z3Ast<SC>, id: u64) -> RetiredValue<SC> {
+        let is_symbol = matches!(expression, RetiredExpression::BVSymbol(_));
+        if let RetiredExpression::BVSymbol(RetiredBVSymbol { name: Some(name), .. }) = &expression {
+            self.symbol_names.try_borrow_mut().unwrap().insert(name.clone());
+        }
         let value = Rc::new(RefCell::new(RetiredValueInner {
             id,
             z3_ast,
             expression,
+            inherited_asts: BTreeMap::new(),
             scfia: self.selff.get().unwrap().clone(),
             comment: None,
         }));
+        if is_symbol {
+            let symbol = SymbolReference::Retired(Rc::downgrade(&value));
+            self.symbols.try_borrow_mut().unwrap().insert(id, symbol);
+        }
         value
     }
 
-    pub fn drop_active_expression(&self, value: &ActiveValueZ3<SC>) -> RetiredValue<SC> {
+    pub fn drop_active_expression(&self, value: &mut ActiveValueZ3<SC>) -> RetiredValue<SC> {
         trace!("Dropping {} ({:?})", value.id, value);
+        if let Some(structural_cache) = self.structural_cache.try_borrow_mut().unwrap().as_mut() {
+            if let Some(key) = value.expression.structural_key() {
+                // The entry may have been replaced by a live value with the same structure
+                if structural_cache.get(&key).is_some_and(|e| e.upgrade().is_none()) {
+                    structural_cache.remove(&key);
+                }
+            }
+        }
         let expression = match &value.expression {
+            ActiveExpression::BoolComparisonExpression(e) => RetiredExpression::BoolComparisonExpression(RetiredBoolComparisonExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                predicate: e.predicate,
+                is_assert: e.is_assert,
+                phantom: PhantomData,
+            }),
             ActiveExpression::BoolEqExpression(e) => RetiredExpression::BoolEqExpression(RetiredBoolEqExpression {
                 s1: ParentWeakReference {
                     id: e.s1.try_borrow().unwrap().id,
@@ -836,6 +1548,54 @@ impl<SC: ScfiaComposition> Scfia<SC> {
                 is_assert: e.is_assert,
                 phantom: PhantomData,
             }),
+            ActiveExpression::BoolAndExpression(e) => RetiredExpression::BoolAndExpression(RetiredBoolAndExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                is_assert: e.is_assert,
+                phantom: PhantomData,
+            }),
+            ActiveExpression::BoolImpliesExpression(e) => RetiredExpression::BoolImpliesExpression(RetiredBoolImpliesExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                is_assert: e.is_assert,
+                phantom: PhantomData,
+            }),
+            ActiveExpression::BoolOrExpression(e) => RetiredExpression::BoolOrExpression(RetiredBoolOrExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                is_assert: e.is_assert,
+                phantom: PhantomData,
+            }),
+            ActiveExpression::BoolXorExpression(e) => RetiredExpression::BoolXorExpression(RetiredBoolXorExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                is_assert: e.is_assert,
+                phantom: PhantomData,
+            }),
             ActiveExpression::BVAddExpression(e) => RetiredExpression::BVAddExpression(RetiredBVAddExpression {
                 s1: ParentWeakReference {
                     id: e.s1.try_borrow().unwrap().id,
@@ -860,6 +1620,18 @@ impl<SC: ScfiaComposition> Scfia<SC> {
                 width: e.width,
                 phantom: PhantomData,
             }),
+            ActiveExpression::BVAshrExpression(e) => RetiredExpression::BVAshrExpression(RetiredBVAshrExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                width: e.width,
+                phantom: PhantomData,
+            }),
             ActiveExpression::BVConcatExpression(e) => RetiredExpression::BVConcatExpression(RetiredBVConcatExpression {
                 s1: ParentWeakReference {
                     id: e.s1.try_borrow().unwrap().id,
@@ -892,6 +1664,22 @@ impl<SC: ScfiaComposition> Scfia<SC> {
                 width: e.width,
                 phantom: PhantomData,
             }),
+            ActiveExpression::BVIteExpression(e) => RetiredExpression::BVIteExpression(RetiredBVIteExpression {
+                condition: ParentWeakReference {
+                    id: e.condition.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.condition),
+                },
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                width: e.width,
+                phantom: PhantomData,
+            }),
             ActiveExpression::BVOrExpression(e) => RetiredExpression::BVOrExpression(RetiredBVOrExpression {
                 s1: ParentWeakReference {
                     id: e.s1.try_borrow().unwrap().id,
@@ -904,12 +1692,45 @@ impl<SC: ScfiaComposition> Scfia<SC> {
                 width: e.width,
                 phantom: PhantomData,
             }),
+            ActiveExpression::BVRotateLeftExpression(e) => RetiredExpression::BVRotateLeftExpression(RetiredBVRotateLeftExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                width: e.width,
+                phantom: PhantomData,
+            }),
+            ActiveExpression::BVRotateRightExpression(e) => RetiredExpression::BVRotateRightExpression(RetiredBVRotateRightExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                width: e.width,
+                phantom: PhantomData,
+            }),
             ActiveExpression::BVSignExtendExpression(e) => RetiredExpression::BVSignExtendExpression(RetiredBVSignExtendExpression {
                 s1: ParentWeakReference {
                     id: e.s1.try_borrow().unwrap().id,
                     weak: Rc::downgrade(&e.s1),
                 },
-                input_width: e.width,
+                input_width: e.input_width,
+                width: e.width,
+                phantom: PhantomData,
+            }),
+            ActiveExpression::BVZeroExtendExpression(e) => RetiredExpression::BVZeroExtendExpression(RetiredBVZeroExtendExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                input_width: e.input_width,
                 width: e.width,
                 phantom: PhantomData,
             }),
@@ -944,7 +1765,6 @@ impl<SC: ScfiaComposition> Scfia<SC> {
                     id: e.s2.try_borrow().unwrap().id,
                     weak: Rc::downgrade(&e.s2),
                 },
-                shamt: e.shamt,
                 width: e.width,
                 phantom: PhantomData,
             }),
@@ -960,7 +1780,22 @@ impl<SC: ScfiaComposition> Scfia<SC> {
                 width: e.width,
                 phantom: PhantomData,
             }),
-            ActiveExpression::BVSymbol(e) => RetiredExpression::BVSymbol(RetiredBVSymbol { width: e.width }),
+            ActiveExpression::BVSymbol(e) => RetiredExpression::BVSymbol(RetiredBVSymbol {
+                width: e.width,
+                name: e.name.clone(),
+            }),
+            ActiveExpression::BVUnsignedDivideExpression(e) => RetiredExpression::BVUnsignedDivideExpression(RetiredBVUnsignedDivideExpression {
+                s1: ParentWeakReference {
+                    id: e.s1.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s1),
+                },
+                s2: ParentWeakReference {
+                    id: e.s2.try_borrow().unwrap().id,
+                    weak: Rc::downgrade(&e.s2),
+                },
+                width: e.width,
+                phantom: PhantomData,
+            }),
             ActiveExpression::BVUnsignedRemainderExpression(e) => RetiredExpression::BVUnsignedRemainderExpression(RetiredBVUnsignedRemainderExpression {
                 s1: ParentWeakReference {
                     id: e.s1.try_borrow().unwrap().id,
@@ -992,6 +1827,7 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         };
 
         let inactive = self.new_inactive(expression, value.z3_ast.clone(), value.id);
+        inactive.try_borrow_mut().unwrap().inherited_asts = std::mem::take(&mut value.inherited_asts);
 
         // Heirs are parents and discovered symbols
         let mut heirs = vec![];
@@ -1008,23 +1844,9 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             let mut heir_mut = heir.try_borrow_mut().unwrap();
             trace!("{:?} attempting to inherit {:?}", heir_mut.id, value.id);
 
-            // Inherit
+            // Inherit, which passes on everything the retired value inherited
             if !heir_mut.is_concrete() && heir_mut.can_inherit {
                 heir_mut.inherited_asts.insert(value.id, inactive.clone());
-
-                // Pass on inherited symbols
-                for (inherited_value_id, inherited_value) in &value.inherited_asts {
-                    let old = heir_mut.inherited_asts.insert(*inherited_value_id, inherited_value.clone());
-                    if let Some(old) = old {
-                        if !Rc::ptr_eq(&old, inherited_value) {
-                            error!(
-                                "{:?} tried to pass on {:?}, but {:?} already had {:?} (same id, different thing)",
-                                value, inherited_value, heir_mut, old
-                            );
-                            panic!();
-                        }
-                    }
-                }
             }
 
             // Acquaint all heirs
@@ -1040,11 +1862,76 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         inactive
     }
 
+    /// Releases the parents of a dropped expression.
+    ///
+    /// Dropping a parent may retire it, which releases its own parents in turn.
+    /// Only the outermost call drains the queue, so retiring long dependency chains does not grow the native stack.
+    pub(crate) fn retire_parents(&self, parents: Vec<Rc<RefCell<ActiveValueZ3<SC>>>>) {
+        self.retirement_queue.try_borrow_mut().unwrap().extend(parents);
+        if self.retiring.get() {
+            return;
+        }
+
+        self.retiring.set(true);
+        loop {
+            let parent = self.retirement_queue.try_borrow_mut().unwrap().pop();
+            match parent {
+                Some(parent) => drop(parent),
+                None => break,
+            }
+        }
+        self.retiring.set(false);
+    }
+
     pub fn check_condition(&self, condition: &ActiveValue<SC>, fork_sink: &mut Option<SC::ForkSink>) -> bool {
         self.z3.check_condition(self, condition, fork_sink)
     }
 
-    pub fn monomorphize_active(&self, value: &ActiveValue<SC>, candidates: &mut Vec<u64>) {
+    pub fn to_smtlib(&self, values: &[&ActiveValue<SC>]) -> String {
+        let values: Vec<Z3Ast<SC>> = values
+            .iter()
+            .map(|value| match value {
+                ActiveValue::BoolConcrete(value) => self.z3.new_bool_concrete(*value),
+                ActiveValue::BVConcrete(value, width) => self.z3.new_bv_concrete(*value, *width),
+                ActiveValue::Expression(_) => value.get_z3_ast(),
+            })
+            .collect();
+        self.z3.to_smtlib_script(&values)
+    }
+
+    /// Returns concrete values for all symbols which satisfy the current path condition, or `None` if it is unsatisfiable.
+    pub fn get_counterexample(&self) -> Option<Counterexample<SC>> {
+        let mut symbols = vec![];
+        self.symbols.try_borrow_mut().unwrap().retain(|id, symbol| {
+            let (name, z3_ast) = match symbol {
+                SymbolReference::Active(symbol) => match symbol.upgrade() {
+                    Some(symbol) => {
+                        let symbol = symbol.try_borrow().unwrap();
+                        match &symbol.expression {
+                            ActiveExpression::BVSymbol(e) => (e.name.clone(), symbol.z3_ast.clone()),
+                            _ => unreachable!(),
+                        }
+                    }
+                    None => return false,
+                },
+                SymbolReference::Retired(symbol) => match symbol.upgrade() {
+                    Some(symbol) => {
+                        let symbol = symbol.try_borrow().unwrap();
+                        match &symbol.expression {
+                            RetiredExpression::BVSymbol(e) => (e.name.clone(), symbol.z3_ast.clone()),
+                            _ => unreachable!(),
+                        }
+                    }
+                    None => return false,
+                },
+            };
+            symbols.push((*id, name, z3_ast));
+            true
+        });
+        self.z3.get_counterexample(&symbols)
+    }
+
+    pub fn monomorphize_active(&self, value: &ActiveValue<SC>, candidates: &mut Vec<u128>) {
         self.z3.monomorphize(&value.get_z3_ast(), candidates);
     }
 
@@ -1060,14 +1947,14 @@ impl<SC: ScfiaComposition> Scfia<SC> {
             );
 
             // Assert base_symbol & align == 0
-            let align_bv = ActiveValue::BVConcrete(align, width);
+            let align_bv = ActiveValue::BVConcrete(align.into(), width);
             let align_and = self.new_bv_and(&base_symbol, &align_bv, width, None, &mut None, None);
             let zero = ActiveValue::BVConcrete(0, width);
             // I swear to whichever deity the Drop behaviour is a nightmare
             self.new_bool_eq(&align_and, &zero, None, true, &mut None, None);
 
             // Assert base_symbol < max
-            let limit_bv = ActiveValue::BVConcrete(limit, width);
+            let limit_bv = ActiveValue::BVConcrete(limit.into(), width);
             self.new_bool_unsigned_less_than(&base_symbol, &limit_bv, None, true, &mut None, None);
 
             base_symbol
@@ -1075,6 +1962,28 @@ impl<SC: ScfiaComposition> Scfia<SC> {
         base_symbol.set_can_inherit(false);
         base_symbol
     }
+
+    /// Reverses the byte order of `s1`, e.g. for big-endian data or byte-swapped MMIO registers.
+    pub fn new_bv_byte_reverse(
+        &self,
+        s1: &ActiveValue<SC>,
+        width: u32,
+        fork_sink: &mut Option<SC::ForkSink>,
+        comment: Option<ValueComment>,
+    ) -> ActiveValue<SC> {
+        assert_eq!(width % 8, 0);
+        if let ActiveValue::BVConcrete(value, _) = s1 {
+            return ActiveValue::BVConcrete(value.swap_bytes() >> (128 - width), width);
+        }
+
+        // The least significant byte of s1 ends up as the most significant one
+        let mut value = self.new_bv_slice(s1, 7, 0, None, fork_sink, comment.clone());
+        for byte in 1..width / 8 {
+            let next = self.new_bv_slice(s1, byte * 8 + 7, byte * 8, None, fork_sink, comment.clone());
+            value = self.new_bv_concat(&value, &next, (byte + 1) * 8, None, fork_sink, comment.clone());
+        }
+        value
+    }
 }
 
 /*TODO
@@ -1116,3 +2025,19 @@ mod tests {
     }
 }
 */
+
+/// Masks the low `width <= 128` bits.
+pub(crate) fn bv_mask(width: u32) -> u128 {
+    u128::MAX >> (128 - width)
+}
+
+/// Rotates the low `width` bits of `value` left by `amount < width`.
+fn rotate_left(value: u128, amount: u32, width: u32) -> u128 {
+    let mask = bv_mask(width);
+    let value = value & mask;
+    if amount == 0 {
+        value
+    } else {
+        ((value << amount) | (value >> (width - amount))) & mask
+    }
+}
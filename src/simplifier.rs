@@ -0,0 +1,275 @@
+//! Algebraic rewrite rules applied before a new expression is constructed.
+//!
+//! The rules only ever return existing values or values built from strictly smaller operands, so expression depths stay bounded
+//! across many steps. They are only consulted for fresh values: clones pass an explicit id and must rebuild the exact node.
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    scfia::{bv_mask, Scfia},
+    values::active_value::{ActiveExpression, ActiveValue, ActiveValueZ3},
+    ScfiaComposition,
+};
+
+/// The parts of an expression the rules look into.
+enum Shape<SC: ScfiaComposition> {
+    Add(Rc<RefCell<ActiveValueZ3<SC>>>, Rc<RefCell<ActiveValueZ3<SC>>>),
+    Sub(Rc<RefCell<ActiveValueZ3<SC>>>, Rc<RefCell<ActiveValueZ3<SC>>>),
+    Concat(Rc<RefCell<ActiveValueZ3<SC>>>, Rc<RefCell<ActiveValueZ3<SC>>>),
+    Slice(Rc<RefCell<ActiveValueZ3<SC>>>, u32, u32),
+    ZeroExtend(Rc<RefCell<ActiveValueZ3<SC>>>, u32),
+    Not(Rc<RefCell<ActiveValueZ3<SC>>>),
+    BoolNot(Rc<RefCell<ActiveValueZ3<SC>>>),
+    Other,
+}
+
+fn shape<SC: ScfiaComposition>(value: &ActiveValue<SC>) -> Shape<SC> {
+    let ActiveValue::Expression(e) = value else {
+        return Shape::Other;
+    };
+    match &e.try_borrow().unwrap().expression {
+        ActiveExpression::BVAddExpression(e) => Shape::Add(e.s1.clone(), e.s2.clone()),
+        ActiveExpression::BVSubExpression(e) => Shape::Sub(e.s1.clone(), e.s2.clone()),
+        ActiveExpression::BVConcatExpression(e) => Shape::Concat(e.s1.clone(), e.s2.clone()),
+        ActiveExpression::BVSliceExpression(e) => Shape::Slice(e.s1.clone(), e.high, e.low),
+        ActiveExpression::BVZeroExtendExpression(e) => Shape::ZeroExtend(e.s1.clone(), e.input_width),
+        ActiveExpression::BVNotExpression(e) => Shape::Not(e.s1.clone()),
+        ActiveExpression::BoolNotExpression(e) if !e.is_assert => Shape::BoolNot(e.s1.clone()),
+        _ => Shape::Other,
+    }
+}
+
+/// Splits `s1`, `s2` into the non-concrete operand and the concrete one, if exactly one of them is concrete.
+fn split_concrete<'a, SC: ScfiaComposition>(s1: &'a ActiveValue<SC>, s2: &'a ActiveValue<SC>) -> Option<(&'a ActiveValue<SC>, u128)> {
+    match (s1.try_get_concrete_u128(), s2.try_get_concrete_u128()) {
+        (None, Some(c)) => Some((s1, c)),
+        (Some(c), None) => Some((s2, c)),
+        _ => None,
+    }
+}
+
+impl<SC: ScfiaComposition> Scfia<SC> {
+    pub(crate) fn bv_width(&self, value: &ActiveValue<SC>) -> u32 {
+        match value {
+            ActiveValue::BVConcrete(_, width) => *width,
+            _ => self.z3.get_bv_width(&value.get_z3_ast()),
+        }
+    }
+
+    pub(crate) fn simplify_bv_add(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> Option<ActiveValue<SC>> {
+        let (x, c) = split_concrete(s1, s2)?;
+        if c & bv_mask(width) == 0 {
+            return Some(x.clone());
+        }
+        // (a + c2) + c => a + (c2 + c), (a - c2) + c => a + (c - c2)
+        let (a, c) = match shape(x) {
+            Shape::Add(a, b) => {
+                let (a, b) = (ActiveValue::Expression(a), ActiveValue::Expression(b));
+                let (a, c2) = split_concrete(&a, &b)?;
+                (a.clone(), c.wrapping_add(c2))
+            }
+            Shape::Sub(a, b) => (ActiveValue::Expression(a), c.wrapping_sub(ActiveValue::Expression(b).try_get_concrete_u128()?)),
+            _ => return None,
+        };
+        Some(self.new_bv_add(&a, &ActiveValue::BVConcrete(c & bv_mask(width), width), width, None, fork_sink, None))
+    }
+
+    pub(crate) fn simplify_bv_sub(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> Option<ActiveValue<SC>> {
+        if s1.try_get_concrete_u128().is_none() && s1.is_same_value(s2) {
+            return Some(ActiveValue::BVConcrete(0, width));
+        }
+        let c = s2.try_get_concrete_u128()?;
+        if s1.try_get_concrete_u128().is_some() {
+            return None;
+        }
+        // x - c => x + (-c), which lets the addition rules absorb it
+        Some(self.new_bv_add(
+            s1,
+            &ActiveValue::BVConcrete(c.wrapping_neg() & bv_mask(width), width),
+            width,
+            None,
+            fork_sink,
+            None,
+        ))
+    }
+
+    pub(crate) fn simplify_bv_and(&self, s1: &ActiveValue<SC>, s2: &ActiveValue<SC>, width: u32) -> Option<ActiveValue<SC>> {
+        if s1.is_same_value(s2) {
+            return Some(s1.clone());
+        }
+        let (x, c) = split_concrete(s1, s2)?;
+        match c & bv_mask(width) {
+            0 => Some(ActiveValue::BVConcrete(0, width)),
+            c if c == bv_mask(width) => Some(x.clone()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn simplify_bv_or(&self, s1: &ActiveValue<SC>, s2: &ActiveValue<SC>, width: u32) -> Option<ActiveValue<SC>> {
+        if s1.is_same_value(s2) {
+            return Some(s1.clone());
+        }
+        let (x, c) = split_concrete(s1, s2)?;
+        match c & bv_mask(width) {
+            0 => Some(x.clone()),
+            c if c == bv_mask(width) => Some(ActiveValue::BVConcrete(c, width)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn simplify_bv_xor(&self, s1: &ActiveValue<SC>, s2: &ActiveValue<SC>, width: u32) -> Option<ActiveValue<SC>> {
+        if s1.try_get_concrete_u128().is_none() && s1.is_same_value(s2) {
+            return Some(ActiveValue::BVConcrete(0, width));
+        }
+        let (x, c) = split_concrete(s1, s2)?;
+        if c & bv_mask(width) == 0 {
+            Some(x.clone())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn simplify_bv_multiply(&self, s1: &ActiveValue<SC>, s2: &ActiveValue<SC>, width: u32) -> Option<ActiveValue<SC>> {
+        let (x, c) = split_concrete(s1, s2)?;
+        match c & bv_mask(width) {
+            0 => Some(ActiveValue::BVConcrete(0, width)),
+            1 => Some(x.clone()),
+            _ => None,
+        }
+    }
+
+    /// Shifts by zero are the identity, and logical shifts by at least the width are zero.
+    pub(crate) fn simplify_bv_shift(&self, s1: &ActiveValue<SC>, s2: &ActiveValue<SC>, width: u32) -> Option<ActiveValue<SC>> {
+        match s2.try_get_concrete_u128()? {
+            0 => Some(s1.clone()),
+            shamt if shamt >= width as u128 => Some(ActiveValue::BVConcrete(0, width)),
+            _ => None,
+        }
+    }
+
+    /// Rotations by a multiple of the width are the identity.
+    pub(crate) fn simplify_bv_rotate(&self, s1: &ActiveValue<SC>, s2: &ActiveValue<SC>, width: u32) -> Option<ActiveValue<SC>> {
+        if s2.try_get_concrete_u128()? % width as u128 == 0 {
+            Some(s1.clone())
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn simplify_bv_not(&self, s1: &ActiveValue<SC>) -> Option<ActiveValue<SC>> {
+        match shape(s1) {
+            Shape::Not(x) => Some(ActiveValue::Expression(x)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn simplify_bool_not(&self, s1: &ActiveValue<SC>) -> Option<ActiveValue<SC>> {
+        match shape(s1) {
+            Shape::BoolNot(x) => Some(ActiveValue::Expression(x)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn simplify_bv_slice(&self, s1: &ActiveValue<SC>, high: u32, low: u32, fork_sink: &mut Option<SC::ForkSink>) -> Option<ActiveValue<SC>> {
+        if low == 0 && high + 1 == self.bv_width(s1) {
+            return Some(s1.clone());
+        }
+        match shape(s1) {
+            Shape::Slice(x, _, inner_low) => Some(self.new_bv_slice(&ActiveValue::Expression(x), inner_low + high, inner_low + low, None, fork_sink, None)),
+            Shape::Concat(upper, lower) => {
+                let (upper, lower) = (ActiveValue::Expression(upper), ActiveValue::Expression(lower));
+                let lower_width = self.bv_width(&lower);
+                if high < lower_width {
+                    Some(self.new_bv_slice(&lower, high, low, None, fork_sink, None))
+                } else if low >= lower_width {
+                    Some(self.new_bv_slice(&upper, high - lower_width, low - lower_width, None, fork_sink, None))
+                } else {
+                    None
+                }
+            }
+            Shape::ZeroExtend(x, input_width) => {
+                if high < input_width {
+                    Some(self.new_bv_slice(&ActiveValue::Expression(x), high, low, None, fork_sink, None))
+                } else if low >= input_width {
+                    Some(ActiveValue::BVConcrete(0, high - low + 1))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn simplify_bv_concat(
+        &self,
+        s1: &ActiveValue<SC>,
+        s2: &ActiveValue<SC>,
+        width: u32,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> Option<ActiveValue<SC>> {
+        match (shape(s1), shape(s2)) {
+            // Adjacent slices of the same value, e.g. when bytes written by a wide store are read back
+            (Shape::Slice(x1, high, low1), Shape::Slice(x2, high2, low)) if low1 == high2 + 1 => {
+                let x = ActiveValue::Expression(x1);
+                if x.is_same_value(&ActiveValue::Expression(x2)) {
+                    Some(self.new_bv_slice(&x, high, low, None, fork_sink, None))
+                } else {
+                    None
+                }
+            }
+            // c1 . (c2 . x) => (c1 . c2) . x
+            (_, Shape::Concat(upper, lower)) => {
+                let c1 = s1.try_get_concrete_u128()?;
+                let c2 = ActiveValue::Expression(upper).try_get_concrete_u128()?;
+                let lower = ActiveValue::Expression(lower);
+                let c2_width = width - self.bv_width(s1) - self.bv_width(&lower);
+                let upper_width = width - self.bv_width(&lower);
+                let upper = ActiveValue::BVConcrete(((c1 << c2_width) | c2) & bv_mask(upper_width), upper_width);
+                Some(self.new_bv_concat(&upper, &lower, width, None, fork_sink, None))
+            }
+            _ => None,
+        }
+    }
+
+    /// A sign extension whose sign bit is concrete, e.g. of a zero-extended value, is a concatenation with a constant or a zero extension.
+    pub(crate) fn simplify_bv_sign_extend(
+        &self,
+        s1: &ActiveValue<SC>,
+        input_width: u32,
+        output_width: u32,
+        fork_sink: &mut Option<SC::ForkSink>,
+    ) -> Option<ActiveValue<SC>> {
+        if input_width == output_width {
+            return Some(s1.clone());
+        }
+        let (upper, lower) = match shape(s1) {
+            Shape::Concat(upper, lower) => (upper, lower),
+            Shape::ZeroExtend(x, inner_width) if inner_width < input_width => {
+                return Some(self.new_bv_zero_extend(&ActiveValue::Expression(x), inner_width, output_width, None, fork_sink, None))
+            }
+            _ => return None,
+        };
+        let upper = ActiveValue::Expression(upper);
+        let upper_value = upper.try_get_concrete_u128()?;
+        let lower = ActiveValue::Expression(lower);
+        let upper_width = input_width - self.bv_width(&lower);
+        let extended_width = output_width - self.bv_width(&lower);
+        let extended = if (upper_value >> (upper_width - 1)) & 1 == 1 {
+            (upper_value | !bv_mask(upper_width)) & bv_mask(extended_width)
+        } else {
+            upper_value & bv_mask(upper_width)
+        };
+        Some(self.new_bv_concat(&ActiveValue::BVConcrete(extended, extended_width), &lower, output_width, None, fork_sink, None))
+    }
+}
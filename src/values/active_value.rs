@@ -1,4 +1,3 @@
-use std::cmp::max;
 use std::fmt::Debug;
 use std::{
     cell::RefCell,
@@ -8,29 +7,41 @@ use std::{
 
 use log::{debug, trace};
 
+use crate::abstract_domain::Abstraction;
 use crate::scfia::Scfia;
 use crate::z3_handle::Z3Ast;
 use crate::ScfiaComposition;
 
+use super::bool_and_expression::BoolAndExpression;
+use super::bool_comparison_expression::BoolComparisonExpression;
 use super::bool_eq_expression::BoolEqExpression;
+use super::bool_implies_expression::BoolImpliesExpression;
 use super::bool_not_expresssion::BoolNotExpression;
+use super::bool_or_expression::BoolOrExpression;
 use super::bool_signed_less_than_expression::BoolSignedLessThanExpression;
 use super::bool_unsigned_less_than_expression::BoolUnsignedLessThanExpression;
+use super::bool_xor_expression::BoolXorExpression;
 use super::bv_add_expression::BVAddExpression;
 use super::bv_and_expression::BVAndExpression;
+use super::bv_ashr_expression::BVAshrExpression;
 use super::bv_concat_expression::BVConcatExpression;
 use super::bv_concrete_expression::BVConcreteExpression;
 use super::bv_multiply_expression::BVMultiplyExpression;
 use super::bv_not_expression::BVNotExpression;
+use super::bv_ite_expression::BVIteExpression;
 use super::bv_or_expression::BVOrExpression;
+use super::bv_rotate_left_expression::BVRotateLeftExpression;
+use super::bv_rotate_right_expression::BVRotateRightExpression;
 use super::bv_sign_extend_expression::BVSignExtendExpression;
 use super::bv_slice_expression::BVSliceExpression;
 use super::bv_sll_expression::BVSllExpression;
 use super::bv_srl_expression::BVSrlExpression;
 use super::bv_sub_expression::BVSubExpression;
 use super::bv_symbol::BVSymbol;
+use super::bv_unsigned_divide_expression::BVUnsignedDivideExpression;
 use super::bv_unsigned_remainder_expression::BVUnsignedRemainderExpression;
 use super::bv_xor_expression::BVXorExpression;
+use super::bv_zero_extend_expression::BVZeroExtendExpression;
 
 use super::retired_value::RetiredValue;
 
@@ -48,7 +59,7 @@ impl ValueComment {
 #[derive(Clone, Debug)]
 pub enum ActiveValue<SC: ScfiaComposition> {
     BoolConcrete(bool),
-    BVConcrete(u64, u32),
+    BVConcrete(u128, u32),
     Expression(Rc<RefCell<ActiveValueZ3<SC>>>),
 }
 
@@ -57,6 +68,8 @@ pub struct ActiveValueZ3<SC: ScfiaComposition> {
     pub id: u64,
     pub z3_ast: Z3Ast<SC>,
     pub expression: ActiveExpression<SC>,
+    pub abstraction: Abstraction,
+    pub depth: usize,
     pub inherited_asts: BTreeMap<u64, RetiredValue<SC>>,
     pub discovered_asts: BTreeMap<u64, Weak<RefCell<ActiveValueZ3<SC>>>>,
     pub scfia: Weak<Scfia<SC>>,
@@ -66,23 +79,34 @@ pub struct ActiveValueZ3<SC: ScfiaComposition> {
 
 #[derive(Debug)]
 pub enum ActiveExpression<SC: ScfiaComposition> {
+    BoolComparisonExpression(BoolComparisonExpression<SC>),
     BoolEqExpression(BoolEqExpression<SC>),
     BoolNotExpression(BoolNotExpression<SC>),
     BoolSignedLessThanExpression(BoolSignedLessThanExpression<SC>),
     BoolUnsignedLessThanExpression(BoolUnsignedLessThanExpression<SC>),
+    BoolAndExpression(BoolAndExpression<SC>),
+    BoolImpliesExpression(BoolImpliesExpression<SC>),
+    BoolOrExpression(BoolOrExpression<SC>),
+    BoolXorExpression(BoolXorExpression<SC>),
     BVAddExpression(BVAddExpression<SC>),
     BVAndExpression(BVAndExpression<SC>),
+    BVAshrExpression(BVAshrExpression<SC>),
     BVConcatExpression(BVConcatExpression<SC>),
     BVConcreteExpression(BVConcreteExpression<SC>),
+    BVIteExpression(BVIteExpression<SC>),
     BVMultiplyExpression(BVMultiplyExpression<SC>),
     BVNotExpression(BVNotExpression<SC>),
     BVOrExpression(BVOrExpression<SC>),
+    BVRotateLeftExpression(BVRotateLeftExpression<SC>),
+    BVRotateRightExpression(BVRotateRightExpression<SC>),
     BVSignExtendExpression(BVSignExtendExpression<SC>),
+    BVZeroExtendExpression(BVZeroExtendExpression<SC>),
     BVSliceExpression(BVSliceExpression<SC>),
     BVSllExpression(BVSllExpression<SC>),
     BVSrlExpression(BVSrlExpression<SC>),
     BVSubExpression(BVSubExpression<SC>),
     BVSymbol(BVSymbol),
+    BVUnsignedDivideExpression(BVUnsignedDivideExpression<SC>),
     BVUnsignedRemainderExpression(BVUnsignedRemainderExpression<SC>),
     BVXorExpression(BVXorExpression<SC>),
 }
@@ -95,6 +119,15 @@ impl<SC: ScfiaComposition> ActiveValue<SC> {
         }
     }
 
+    /// Over-approximates the values this value can take on any path.
+    pub fn get_abstraction(&self) -> Abstraction {
+        match self {
+            ActiveValue::BoolConcrete(value) => Abstraction::bool(Some(*value)),
+            ActiveValue::BVConcrete(value, width) => Abstraction::exact(*value, *width),
+            ActiveValue::Expression(e) => e.try_borrow().unwrap().abstraction,
+        }
+    }
+
     pub fn get_z3_ast(&self) -> Z3Ast<SC> {
         match &self {
             ActiveValue::Expression(e) => e.try_borrow().unwrap().z3_ast.clone(),
@@ -126,7 +159,12 @@ impl<SC: ScfiaComposition> ActiveValue<SC> {
         }
     }
 
+    /// Returns the concrete value if it is known and fits into 64 bits.
     pub fn try_get_concrete(&self) -> Option<u64> {
+        self.try_get_concrete_u128().and_then(|value| u64::try_from(value).ok())
+    }
+
+    pub fn try_get_concrete_u128(&self) -> Option<u128> {
         match &self {
             ActiveValue::BVConcrete(value, _) => Some(*value),
             ActiveValue::Expression(e) => {
@@ -139,6 +177,27 @@ impl<SC: ScfiaComposition> ActiveValue<SC> {
         }
     }
 
+    /// Whether both values are the same concrete value or the same expression, e.g. in two clones of a state.
+    pub fn is_same_value(&self, other: &ActiveValue<SC>) -> bool {
+        match (self, other) {
+            (ActiveValue::BoolConcrete(value), ActiveValue::BoolConcrete(other_value)) => value == other_value,
+            (ActiveValue::Expression(value), ActiveValue::Expression(other_value)) if self.try_get_concrete_u128().is_none() => {
+                value.try_borrow().unwrap().id == other_value.try_borrow().unwrap().id
+            }
+            _ => self.try_get_concrete_u128().is_some() && self.try_get_concrete_u128() == other.try_get_concrete_u128(),
+        }
+    }
+
+    pub fn try_get_symbol_name(&self) -> Option<String> {
+        match &self {
+            ActiveValue::Expression(e) => match &e.try_borrow().unwrap().expression {
+                ActiveExpression::BVSymbol(bv_symbol) => bv_symbol.name.clone(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn set_can_inherit(&self, value: bool) {
         if let ActiveValue::Expression(e) = self {
             e.try_borrow_mut().unwrap().can_inherit = value
@@ -168,7 +227,7 @@ impl<SC: ScfiaComposition> ActiveValue<SC> {
         self.get_z3_ast().assert()
     }
 
-    pub(crate) fn clone_to_stdlib(
+    pub fn clone_to_stdlib(
         &self,
         cloned_scfia: &Scfia<SC>,
         cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
@@ -177,19 +236,102 @@ impl<SC: ScfiaComposition> ActiveValue<SC> {
         match self {
             ActiveValue::BoolConcrete(value) => ActiveValue::BoolConcrete(*value),
             ActiveValue::BVConcrete(value, width) => ActiveValue::BVConcrete(*value, *width),
-            ActiveValue::Expression(expression) => expression.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired),
+            ActiveValue::Expression(expression) => clone_active_to_stdlib(expression, cloned_scfia, cloned_actives, cloned_retired),
         }
     }
 }
 
+/// Identifies interchangeable expressions by operator, parent ids and parameters, see `Scfia::enable_hash_consing`.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct StructuralKey {
+    operator: &'static str,
+    parents: Vec<u64>,
+    parameters: Vec<u64>,
+}
+
+impl<SC: ScfiaComposition> ActiveExpression<SC> {
+    /// The key under which this expression may be shared. Symbols and assertions are never shared.
+    pub(crate) fn structural_key(&self) -> Option<StructuralKey> {
+        let id = |parent: &Rc<RefCell<ActiveValueZ3<SC>>>| parent.try_borrow().unwrap().id;
+        let (operator, parents, parameters) = match self {
+            ActiveExpression::BoolComparisonExpression(e) if !e.is_assert => (e.predicate.smtlib_name(), vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolEqExpression(e) if !e.is_assert => ("eq", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolNotExpression(e) if !e.is_assert => ("not", vec![id(&e.s1)], vec![]),
+            ActiveExpression::BoolSignedLessThanExpression(e) if !e.is_assert => ("slt", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolUnsignedLessThanExpression(e) if !e.is_assert => ("ult", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolAndExpression(e) if !e.is_assert => ("and", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolImpliesExpression(e) if !e.is_assert => ("=>", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolOrExpression(e) if !e.is_assert => ("or", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BoolXorExpression(e) if !e.is_assert => ("xor", vec![id(&e.s1), id(&e.s2)], vec![]),
+            ActiveExpression::BVAddExpression(e) => ("bvadd", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVAndExpression(e) => ("bvand", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVAshrExpression(e) => ("bvashr", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVConcatExpression(e) => ("concat", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVConcreteExpression(e) => ("bv", vec![], vec![e.value as u64, (e.value >> 64) as u64, e.width as u64]),
+            ActiveExpression::BVIteExpression(e) => ("ite", vec![id(&e.condition), id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVMultiplyExpression(e) => ("bvmul", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVNotExpression(e) => ("bvnot", vec![id(&e.s1)], vec![e.width as u64]),
+            ActiveExpression::BVOrExpression(e) => ("bvor", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVRotateLeftExpression(e) => ("ext_rotate_left", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVRotateRightExpression(e) => ("ext_rotate_right", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVSignExtendExpression(e) => ("sign_extend", vec![id(&e.s1)], vec![e.width as u64, e.input_width as u64]),
+            ActiveExpression::BVZeroExtendExpression(e) => ("zero_extend", vec![id(&e.s1)], vec![e.width as u64, e.input_width as u64]),
+            ActiveExpression::BVSliceExpression(e) => ("extract", vec![id(&e.s1)], vec![e.high as u64, e.low as u64]),
+            ActiveExpression::BVSllExpression(e) => ("bvshl", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVSrlExpression(e) => ("bvlshr", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVSubExpression(e) => ("bvsub", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVUnsignedDivideExpression(e) => ("bvudiv", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVUnsignedRemainderExpression(e) => ("bvurem", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            ActiveExpression::BVXorExpression(e) => ("bvxor", vec![id(&e.s1), id(&e.s2)], vec![e.width as u64]),
+            _ => return None,
+        };
+        Some(StructuralKey { operator, parents, parameters })
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(
+            self,
+            ActiveExpression::BoolComparisonExpression(_)
+                | ActiveExpression::BoolEqExpression(_)
+                | ActiveExpression::BoolNotExpression(_)
+                | ActiveExpression::BoolSignedLessThanExpression(_)
+                | ActiveExpression::BoolUnsignedLessThanExpression(_)
+                | ActiveExpression::BoolAndExpression(_)
+                | ActiveExpression::BoolImpliesExpression(_)
+                | ActiveExpression::BoolOrExpression(_)
+                | ActiveExpression::BoolXorExpression(_)
+        )
+    }
+}
+
 impl<SC: ScfiaComposition> ActiveValueZ3<SC> {
     pub(crate) fn get_parents(&self, dest: &mut Vec<Rc<RefCell<ActiveValueZ3<SC>>>>) {
         match &self.expression {
             ActiveExpression::BVSymbol(_) => {}
+            ActiveExpression::BoolAndExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
+            ActiveExpression::BoolImpliesExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
+            ActiveExpression::BoolOrExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
+            ActiveExpression::BoolXorExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
             ActiveExpression::BVAddExpression(e) => {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
             }
+            ActiveExpression::BoolComparisonExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
             ActiveExpression::BoolEqExpression(e) => {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
@@ -209,10 +351,19 @@ impl<SC: ScfiaComposition> ActiveValueZ3<SC> {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
             }
+            ActiveExpression::BVAshrExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
             ActiveExpression::BVConcatExpression(e) => {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
             }
+            ActiveExpression::BVIteExpression(e) => {
+                dest.push(e.condition.clone());
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
             ActiveExpression::BVMultiplyExpression(e) => {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
@@ -224,9 +375,20 @@ impl<SC: ScfiaComposition> ActiveValueZ3<SC> {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
             }
+            ActiveExpression::BVRotateLeftExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
+            ActiveExpression::BVRotateRightExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
             ActiveExpression::BVSignExtendExpression(e) => {
                 dest.push(e.s1.clone());
             }
+            ActiveExpression::BVZeroExtendExpression(e) => {
+                dest.push(e.s1.clone());
+            }
             ActiveExpression::BVSliceExpression(e) => {
                 dest.push(e.s1.clone());
             }
@@ -242,6 +404,10 @@ impl<SC: ScfiaComposition> ActiveValueZ3<SC> {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
             }
+            ActiveExpression::BVUnsignedDivideExpression(e) => {
+                dest.push(e.s1.clone());
+                dest.push(e.s2.clone());
+            }
             ActiveExpression::BVUnsignedRemainderExpression(e) => {
                 dest.push(e.s1.clone());
                 dest.push(e.s2.clone());
@@ -255,27 +421,14 @@ impl<SC: ScfiaComposition> ActiveValueZ3<SC> {
     }
 
     pub fn get_depth(&self) -> usize {
-        match &self.expression {
-            ActiveExpression::BoolEqExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BoolNotExpression(e) => 1 + e.s1.try_borrow().unwrap().get_depth(),
-            ActiveExpression::BoolSignedLessThanExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BoolUnsignedLessThanExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVAddExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVAndExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVConcatExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVMultiplyExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVNotExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVOrExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVSignExtendExpression(e) => 1 + e.s1.try_borrow().unwrap().get_depth(),
-            ActiveExpression::BVSliceExpression(e) => 1 + e.s1.try_borrow().unwrap().get_depth(),
-            ActiveExpression::BVSllExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVSrlExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVSubExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVSymbol(_) => 1,
-            ActiveExpression::BVUnsignedRemainderExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVXorExpression(e) => 1 + max(e.s1.try_borrow().unwrap().get_depth(), e.s1.try_borrow().unwrap().get_depth()),
-            ActiveExpression::BVConcreteExpression(_) => 1,
-        }
+        self.depth
+    }
+
+    /// One more than the deepest parent's depth.
+    pub(crate) fn compute_depth(&self) -> usize {
+        let mut parents = vec![];
+        self.get_parents(&mut parents);
+        1 + parents.iter().map(|parent| parent.try_borrow().unwrap().depth).max().unwrap_or(0)
     }
 
     pub fn get_inactives(&self) -> usize {
@@ -286,132 +439,295 @@ impl<SC: ScfiaComposition> ActiveValueZ3<SC> {
         matches!(self.expression, ActiveExpression::BVConcreteExpression(_))
     }
 
-    pub(crate) fn clone_to_stdlib(
-        &self,
-        cloned_scfia: &Scfia<SC>,
-        cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
-        cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
-    ) -> ActiveValue<SC> {
-        if let Some(value) = cloned_actives.get(&self.id) {
-            return value.clone();
-        }
-        trace!("Cloning {:?}", self);
-        let cloned_value = match &self.expression {
+    /// Clones this value's expression into `cloned_scfia`. All parents must already be present in `cloned_actives`.
+    fn clone_expression(&self, cloned_scfia: &Scfia<SC>, cloned_actives: &BTreeMap<u64, ActiveValue<SC>>) -> ActiveValue<SC> {
+        trace!("Cloning {}", self.id);
+        let cloned_parent = |parent: &Rc<RefCell<ActiveValueZ3<SC>>>| cloned_actives.get(&parent.try_borrow().unwrap().id).unwrap().clone();
+        match &self.expression {
+            ActiveExpression::BoolComparisonExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bool_comparison(e.predicate, &s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
+            }
             ActiveExpression::BoolEqExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bool_eq(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
             }
             ActiveExpression::BoolNotExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
                 cloned_scfia.new_bool_not(&s1, Some(self.id), e.is_assert, &mut None, self.comment.clone())
             }
             ActiveExpression::BoolSignedLessThanExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bool_signed_less_than(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
             }
             ActiveExpression::BoolUnsignedLessThanExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bool_unsigned_less_than(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
             }
+            ActiveExpression::BoolAndExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bool_and(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
+            }
+            ActiveExpression::BoolImpliesExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bool_implies(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
+            }
+            ActiveExpression::BoolOrExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bool_or(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
+            }
+            ActiveExpression::BoolXorExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bool_xor(&s1, &s2, Some(self.id), e.is_assert, &mut None, self.comment.clone())
+            }
             ActiveExpression::BVAddExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_add(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVAndExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_and(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
+            ActiveExpression::BVAshrExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bv_ashr(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
+            }
             ActiveExpression::BVConcatExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_concat(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
+            ActiveExpression::BVIteExpression(e) => {
+                let condition = cloned_parent(&e.condition);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bv_ite(&condition, &s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
+            }
             ActiveExpression::BVMultiplyExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_multiply(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVNotExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
                 cloned_scfia.new_bv_not(&s1, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVOrExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_or(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
+            ActiveExpression::BVRotateLeftExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bv_rotate_left(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
+            }
+            ActiveExpression::BVRotateRightExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bv_rotate_right(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
+            }
             ActiveExpression::BVSignExtendExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
                 cloned_scfia.new_bv_sign_extend(&s1, e.input_width, e.width, Some(self.id), &mut None, self.comment.clone())
             }
+            ActiveExpression::BVZeroExtendExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                cloned_scfia.new_bv_zero_extend(&s1, e.input_width, e.width, Some(self.id), &mut None, self.comment.clone())
+            }
             ActiveExpression::BVSliceExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
                 cloned_scfia.new_bv_slice(&s1, e.high, e.low, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVSllExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_sll(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVSrlExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_srl(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVSubExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_sub(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
-            ActiveExpression::BVSymbol(e) => cloned_scfia.new_bv_symbol(e.width, Some(self.id), &mut None, self.comment.clone()),
+            ActiveExpression::BVSymbol(e) => match &e.name {
+                Some(name) => cloned_scfia.new_bv_named_symbol(name, e.width, Some(self.id), &mut None, self.comment.clone()),
+                None => cloned_scfia.new_bv_symbol(e.width, Some(self.id), &mut None, self.comment.clone()),
+            },
+            ActiveExpression::BVUnsignedDivideExpression(e) => {
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
+                cloned_scfia.new_bv_udiv(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
+            }
             ActiveExpression::BVUnsignedRemainderExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_unsigned_remainder(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVXorExpression(e) => {
-                let s1 = e.s1.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-                let s2 = e.s2.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
+                let s1 = cloned_parent(&e.s1);
+                let s2 = cloned_parent(&e.s2);
                 cloned_scfia.new_bv_xor(&s1, &s2, e.width, Some(self.id), &mut None, self.comment.clone())
             }
             ActiveExpression::BVConcreteExpression(e) => cloned_scfia.new_bv_concrete_z3(e.value, e.width, Some(self.id), &mut None, self.comment.clone()),
-        };
+        }
+    }
+}
 
-        cloned_value.set_can_inherit(self.can_inherit);
-        cloned_actives.insert(self.id, cloned_value.clone());
+enum CloneTask<SC: ScfiaComposition> {
+    /// Clone the parents of an active value, then the value itself.
+    Active(Rc<RefCell<ActiveValueZ3<SC>>>),
+    /// Clone an active value whose parents have been cloned, then its inherited and discovered values.
+    ActiveExpression(Rc<RefCell<ActiveValueZ3<SC>>>),
+    /// Give a cloned active value its cloned inherited and discovered values.
+    ActiveRelations(Rc<RefCell<ActiveValueZ3<SC>>>),
+    /// Clone the active parents of a retired value, then the value itself.
+    Retired(RetiredValue<SC>),
+    /// Clone a retired value whose parents have been cloned.
+    RetiredExpression(RetiredValue<SC>),
+    /// Give a cloned retired value its cloned inherited values.
+    RetiredRelations(RetiredValue<SC>),
+}
 
-        // Clone inherited values
-        for (inherited_value_id, inherited_value) in &self.inherited_asts {
-            let inherited_value_ref = inherited_value.try_borrow().unwrap();
-            let cloned_inherited_value = inherited_value_ref.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-            cloned_value.inherit(*inherited_value_id, cloned_inherited_value);
+/// Collects the retired values reachable through `inherited_asts` which have not been cloned yet, ordered by id so that retired parents come first.
+fn collect_inherited<SC: ScfiaComposition>(
+    inherited_asts: &BTreeMap<u64, RetiredValue<SC>>,
+    cloned_retired: &BTreeMap<u64, RetiredValue<SC>>,
+) -> Vec<RetiredValue<SC>> {
+    let mut collected = BTreeMap::new();
+    let mut pending: Vec<RetiredValue<SC>> = inherited_asts.values().cloned().collect();
+    while let Some(value) = pending.pop() {
+        let value_ref = value.try_borrow().unwrap();
+        let id = value_ref.id;
+        if cloned_retired.contains_key(&id) || collected.contains_key(&id) {
+            continue;
         }
+        pending.extend(value_ref.inherited_asts.values().cloned());
+        drop(value_ref);
+        collected.insert(id, value);
+    }
+    collected.into_values().collect()
+}
 
-        // Clone discovered values
-        for (discovered_value_id, discovered_value) in &self.discovered_asts {
-            let cloned_discovered_value =
-                discovered_value
-                    .upgrade()
-                    .unwrap()
-                    .try_borrow()
-                    .unwrap()
-                    .clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-            cloned_value.discover(*discovered_value_id, cloned_discovered_value.get_weak());
+/// Clones `value` and everything it depends on into `cloned_scfia`.
+///
+/// Expression DAGs can be hundreds of thousands of values deep, so this works through an explicit worklist instead of recursing.
+fn clone_active_to_stdlib<SC: ScfiaComposition>(
+    value: &Rc<RefCell<ActiveValueZ3<SC>>>,
+    cloned_scfia: &Scfia<SC>,
+    cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
+    cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
+) -> ActiveValue<SC> {
+    let mut tasks = vec![CloneTask::Active(value.clone())];
+    while let Some(task) = tasks.pop() {
+        match task {
+            CloneTask::Active(value) => {
+                let value_ref = value.try_borrow().unwrap();
+                if cloned_actives.contains_key(&value_ref.id) {
+                    continue;
+                }
+                let mut parents = vec![];
+                value_ref.get_parents(&mut parents);
+                drop(value_ref);
+                tasks.push(CloneTask::ActiveExpression(value));
+                for parent in parents.into_iter().rev() {
+                    tasks.push(CloneTask::Active(parent));
+                }
+            }
+            CloneTask::ActiveExpression(value) => {
+                let value_ref = value.try_borrow().unwrap();
+                if cloned_actives.contains_key(&value_ref.id) {
+                    continue;
+                }
+                let cloned_value = value_ref.clone_expression(cloned_scfia, cloned_actives);
+                cloned_value.set_can_inherit(value_ref.can_inherit);
+                cloned_actives.insert(value_ref.id, cloned_value);
+
+                // Inherited values are cloned before discovered values
+                tasks.push(CloneTask::ActiveRelations(value.clone()));
+                let inherited_values = collect_inherited(&value_ref.inherited_asts, cloned_retired);
+                for inherited_value in &inherited_values {
+                    tasks.push(CloneTask::RetiredRelations(inherited_value.clone()));
+                }
+                for discovered_value in value_ref.discovered_asts.values().rev() {
+                    tasks.push(CloneTask::Active(discovered_value.upgrade().unwrap()));
+                }
+                for inherited_value in inherited_values.into_iter().rev() {
+                    tasks.push(CloneTask::Retired(inherited_value));
+                }
+            }
+            CloneTask::ActiveRelations(value) => {
+                let value_ref = value.try_borrow().unwrap();
+                let cloned_value = cloned_actives.get(&value_ref.id).unwrap();
+                for (inherited_value_id, inherited_value) in &value_ref.inherited_asts {
+                    let cloned_inherited_value = cloned_retired.get(&inherited_value.try_borrow().unwrap().id).unwrap();
+                    cloned_value.inherit(*inherited_value_id, cloned_inherited_value.clone());
+                }
+                for discovered_value_id in value_ref.discovered_asts.keys() {
+                    let cloned_discovered_value = cloned_actives.get(discovered_value_id).unwrap();
+                    cloned_value.discover(*discovered_value_id, cloned_discovered_value.get_weak());
+                }
+            }
+            CloneTask::Retired(value) => {
+                let value_ref = value.try_borrow().unwrap();
+                if cloned_retired.contains_key(&value_ref.id) {
+                    continue;
+                }
+                let mut active_parents = vec![];
+                value_ref.get_active_parents(cloned_actives, &mut active_parents);
+                drop(value_ref);
+                tasks.push(CloneTask::RetiredExpression(value));
+                for parent in active_parents.into_iter().rev() {
+                    tasks.push(CloneTask::Active(parent));
+                }
+            }
+            CloneTask::RetiredExpression(value) => {
+                let value_ref = value.try_borrow().unwrap();
+                if cloned_retired.contains_key(&value_ref.id) {
+                    continue;
+                }
+                let cloned_value = value_ref.clone_expression(cloned_scfia, cloned_actives, cloned_retired);
+                cloned_retired.insert(value_ref.id, cloned_value);
+            }
+            CloneTask::RetiredRelations(value) => {
+                let value_ref = value.try_borrow().unwrap();
+                let mut cloned_value = cloned_retired.get(&value_ref.id).unwrap().try_borrow_mut().unwrap();
+                for inherited_value_id in value_ref.inherited_asts.keys() {
+                    let cloned_inherited_value = cloned_retired.get(inherited_value_id).unwrap();
+                    cloned_value.inherited_asts.insert(*inherited_value_id, cloned_inherited_value.clone());
+                }
+            }
         }
-
-        cloned_value
     }
+
+    cloned_actives.get(&value.try_borrow().unwrap().id).unwrap().clone()
 }
 
 impl<SC: ScfiaComposition> Drop for ActiveValueZ3<SC> {
     fn drop(&mut self) {
         if let Some(scfia) = self.scfia.upgrade() {
             scfia.drop_active_expression(self);
+
+            // Hand the parents to scfia instead of dropping them recursively
+            let mut parents = vec![];
+            self.get_parents(&mut parents);
+            self.expression = ActiveExpression::BVSymbol(BVSymbol { width: 0, name: None });
+            scfia.retire_parents(parents);
         }
     }
 }
@@ -423,7 +739,7 @@ pub trait ActiveValueImpl<SC: ScfiaComposition> {
 impl<SC: ScfiaComposition> ActiveValueImpl<SC> for ActiveValue<SC> {
     fn to_u64(&self) -> u64 {
         if let ActiveValue::BVConcrete(e, _) = &self {
-            *e
+            u64::try_from(*e).unwrap()
         } else {
             panic!()
         }
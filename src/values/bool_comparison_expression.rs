@@ -0,0 +1,65 @@
+use std::{cell::RefCell, fmt::Debug, marker::PhantomData, rc::Rc};
+
+use crate::ScfiaComposition;
+
+use super::{active_value::ActiveValueZ3, retired_value::ParentWeakReference};
+
+/// The comparisons besides `bvslt` and `bvult`, which have their own expressions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparisonPredicate {
+    UnsignedLessEqual,
+    UnsignedGreaterThan,
+    UnsignedGreaterEqual,
+    SignedLessEqual,
+    SignedGreaterThan,
+    SignedGreaterEqual,
+}
+
+impl ComparisonPredicate {
+    /// Compares two `width` bit wide values.
+    pub fn evaluate(&self, s1: u128, s2: u128, width: u32) -> bool {
+        let (s1_signed, s2_signed) = (to_signed(s1, width), to_signed(s2, width));
+        match self {
+            ComparisonPredicate::UnsignedLessEqual => s1 <= s2,
+            ComparisonPredicate::UnsignedGreaterThan => s1 > s2,
+            ComparisonPredicate::UnsignedGreaterEqual => s1 >= s2,
+            ComparisonPredicate::SignedLessEqual => s1_signed <= s2_signed,
+            ComparisonPredicate::SignedGreaterThan => s1_signed > s2_signed,
+            ComparisonPredicate::SignedGreaterEqual => s1_signed >= s2_signed,
+        }
+    }
+
+    pub fn smtlib_name(&self) -> &'static str {
+        match self {
+            ComparisonPredicate::UnsignedLessEqual => "bvule",
+            ComparisonPredicate::UnsignedGreaterThan => "bvugt",
+            ComparisonPredicate::UnsignedGreaterEqual => "bvuge",
+            ComparisonPredicate::SignedLessEqual => "bvsle",
+            ComparisonPredicate::SignedGreaterThan => "bvsgt",
+            ComparisonPredicate::SignedGreaterEqual => "bvsge",
+        }
+    }
+}
+
+/// Interprets the low `width` bits of `value` as a two's complement number.
+pub fn to_signed(value: u128, width: u32) -> i128 {
+    let shift = 128 - width;
+    ((value << shift) as i128) >> shift
+}
+
+#[derive(Debug)]
+pub struct BoolComparisonExpression<SC: ScfiaComposition> {
+    pub s1: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub s2: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub predicate: ComparisonPredicate,
+    pub is_assert: bool,
+}
+
+#[derive(Debug)]
+pub struct RetiredBoolComparisonExpression<SC: ScfiaComposition> {
+    pub s1: ParentWeakReference<SC>,
+    pub s2: ParentWeakReference<SC>,
+    pub predicate: ComparisonPredicate,
+    pub is_assert: bool,
+    pub phantom: PhantomData<SC>,
+}
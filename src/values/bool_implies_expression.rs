@@ -0,0 +1,21 @@
+use std::{cell::RefCell, fmt::Debug, marker::PhantomData, rc::Rc};
+
+use crate::ScfiaComposition;
+
+use super::{active_value::ActiveValueZ3, retired_value::ParentWeakReference};
+
+/// `s1` implies `s2`.
+#[derive(Debug)]
+pub struct BoolImpliesExpression<SC: ScfiaComposition> {
+    pub s1: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub s2: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub is_assert: bool,
+}
+
+#[derive(Debug)]
+pub struct RetiredBoolImpliesExpression<SC: ScfiaComposition> {
+    pub s1: ParentWeakReference<SC>,
+    pub s2: ParentWeakReference<SC>,
+    pub is_assert: bool,
+    pub phantom: PhantomData<SC>,
+}
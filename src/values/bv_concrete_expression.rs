@@ -4,13 +4,13 @@ use crate::ScfiaComposition;
 
 #[derive(Debug)]
 pub struct BVConcreteExpression<SC: ScfiaComposition> {
-    pub value: u64,
+    pub value: u128,
     pub width: u32,
     pub phantom: PhantomData<SC>,
 }
 
 #[derive(Debug)]
 pub struct RetiredBVConcreteExpression {
-    pub value: u64,
+    pub value: u128,
     pub width: u32,
 }
@@ -0,0 +1,23 @@
+use std::{cell::RefCell, fmt::Debug, marker::PhantomData, rc::Rc};
+
+use crate::ScfiaComposition;
+
+use super::{active_value::ActiveValueZ3, retired_value::ParentWeakReference};
+
+/// `s1` if `condition` holds, `s2` otherwise.
+#[derive(Debug)]
+pub struct BVIteExpression<SC: ScfiaComposition> {
+    pub condition: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub s1: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub s2: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub width: u32,
+}
+
+#[derive(Debug)]
+pub struct RetiredBVIteExpression<SC: ScfiaComposition> {
+    pub condition: ParentWeakReference<SC>,
+    pub s1: ParentWeakReference<SC>,
+    pub s2: ParentWeakReference<SC>,
+    pub width: u32,
+    pub phantom: PhantomData<SC>,
+}
@@ -0,0 +1,21 @@
+use std::{cell::RefCell, fmt::Debug, marker::PhantomData, rc::Rc};
+
+use crate::ScfiaComposition;
+
+use super::{active_value::ActiveValueZ3, retired_value::ParentWeakReference};
+
+/// `s1` rotated left by `s2` modulo `width` bits.
+#[derive(Debug)]
+pub struct BVRotateLeftExpression<SC: ScfiaComposition> {
+    pub s1: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub s2: Rc<RefCell<ActiveValueZ3<SC>>>,
+    pub width: u32,
+}
+
+#[derive(Debug)]
+pub struct RetiredBVRotateLeftExpression<SC: ScfiaComposition> {
+    pub s1: ParentWeakReference<SC>,
+    pub s2: ParentWeakReference<SC>,
+    pub width: u32,
+    pub phantom: PhantomData<SC>,
+}
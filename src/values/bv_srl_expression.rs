@@ -9,7 +9,6 @@ pub struct BVSrlExpression<SC: ScfiaComposition> {
     pub s1: Rc<RefCell<ActiveValueZ3<SC>>>,
     pub s2: Rc<RefCell<ActiveValueZ3<SC>>>,
     pub width: u32,
-    pub shamt: u32,
 }
 
 #[derive(Debug)]
@@ -17,7 +16,6 @@ pub struct RetiredBVSrlExpression<SC: ScfiaComposition> {
     pub s1: ParentWeakReference<SC>,
     pub s2: ParentWeakReference<SC>,
     pub width: u32,
-    pub shamt: u32,
     pub phantom: PhantomData<SC>,
 }
 
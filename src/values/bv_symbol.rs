@@ -3,9 +3,11 @@ use std::fmt::Debug;
 #[derive(Debug)]
 pub struct BVSymbol {
     pub width: u32,
+    pub name: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct RetiredBVSymbol {
     pub width: u32,
+    pub name: Option<String>,
 }
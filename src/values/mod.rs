@@ -1,21 +1,32 @@
 pub mod active_value;
+pub mod bool_and_expression;
+pub mod bool_comparison_expression;
 pub mod bool_eq_expression;
+pub mod bool_implies_expression;
 pub mod bool_not_expresssion;
+pub mod bool_or_expression;
 pub mod bool_signed_less_than_expression;
 pub mod bool_unsigned_less_than_expression;
+pub mod bool_xor_expression;
 pub mod bv_add_expression;
 pub mod bv_and_expression;
+pub mod bv_ashr_expression;
 pub mod bv_concat_expression;
 pub mod bv_concrete_expression;
+pub mod bv_ite_expression;
 pub mod bv_multiply_expression;
 pub mod bv_not_expression;
 pub mod bv_or_expression;
+pub mod bv_rotate_left_expression;
+pub mod bv_rotate_right_expression;
 pub mod bv_sign_extend_expression;
 pub mod bv_slice_expression;
 pub mod bv_sll_expression;
 pub mod bv_srl_expression;
 pub mod bv_sub_expression;
 pub mod bv_symbol;
+pub mod bv_unsigned_divide_expression;
 pub mod bv_unsigned_remainder_expression;
 pub mod bv_xor_expression;
+pub mod bv_zero_extend_expression;
 pub mod retired_value;
@@ -12,24 +12,35 @@ use crate::{scfia::Scfia, z3_handle::Z3Ast, ScfiaComposition};
 
 use super::{
     active_value::{ActiveValue, ActiveValueZ3, ValueComment},
+    bool_and_expression::RetiredBoolAndExpression,
+    bool_comparison_expression::RetiredBoolComparisonExpression,
     bool_eq_expression::RetiredBoolEqExpression,
+    bool_implies_expression::RetiredBoolImpliesExpression,
     bool_not_expresssion::RetiredBoolNotExpression,
+    bool_or_expression::RetiredBoolOrExpression,
     bool_signed_less_than_expression::RetiredBoolSignedLessThanExpression,
     bool_unsigned_less_than_expression::RetiredBoolUnsignedLessThanExpression,
+    bool_xor_expression::RetiredBoolXorExpression,
     bv_add_expression::RetiredBVAddExpression,
     bv_and_expression::RetiredBVAndExpression,
+    bv_ashr_expression::RetiredBVAshrExpression,
     bv_concat_expression::RetiredBVConcatExpression,
     bv_concrete_expression::RetiredBVConcreteExpression,
+    bv_ite_expression::RetiredBVIteExpression,
     bv_multiply_expression::RetiredBVMultiplyExpression,
     bv_or_expression::RetiredBVOrExpression,
+    bv_rotate_left_expression::RetiredBVRotateLeftExpression,
+    bv_rotate_right_expression::RetiredBVRotateRightExpression,
     bv_sign_extend_expression::RetiredBVSignExtendExpression,
     bv_slice_expression::RetiredBVSliceExpression,
     bv_sll_expression::RetiredBVSllExpression,
     bv_srl_expression::RetiredBVSrlExpression,
     bv_sub_expression::RetiredBVSubExpression,
     bv_symbol::RetiredBVSymbol,
+    bv_unsigned_divide_expression::RetiredBVUnsignedDivideExpression,
     bv_unsigned_remainder_expression::RetiredBVUnsignedRemainderExpression,
     bv_xor_expression::RetiredBVXorExpression, bv_not_expression::RetiredBVNotExpression,
+    bv_zero_extend_expression::RetiredBVZeroExtendExpression,
 };
 
 pub type RetiredValue<Model> = Rc<RefCell<RetiredValueInner<Model>>>;
@@ -39,6 +50,8 @@ pub struct RetiredValueInner<SC: ScfiaComposition> {
     pub id: u64,
     pub z3_ast: Z3Ast<SC>,
     pub expression: RetiredExpression<SC>,
+    /// The values the active value had inherited when it retired. Heirs hold on to them through this value instead of copies.
+    pub inherited_asts: BTreeMap<u64, RetiredValue<SC>>,
     pub scfia: Weak<Scfia<SC>>,
     pub comment: Option<ValueComment>,
 }
@@ -50,32 +63,42 @@ pub struct ParentWeakReference<SC: ScfiaComposition> {
 }
 
 pub enum RetiredExpression<SC: ScfiaComposition> {
+    BoolComparisonExpression(RetiredBoolComparisonExpression<SC>),
     BoolEqExpression(RetiredBoolEqExpression<SC>),
     BoolNotExpression(RetiredBoolNotExpression<SC>),
     BoolSignedLessThanExpression(RetiredBoolSignedLessThanExpression<SC>),
     BoolUnsignedLessThanExpression(RetiredBoolUnsignedLessThanExpression<SC>),
+    BoolAndExpression(RetiredBoolAndExpression<SC>),
+    BoolImpliesExpression(RetiredBoolImpliesExpression<SC>),
+    BoolOrExpression(RetiredBoolOrExpression<SC>),
+    BoolXorExpression(RetiredBoolXorExpression<SC>),
     BVAddExpression(RetiredBVAddExpression<SC>),
     BVAndExpression(RetiredBVAndExpression<SC>),
+    BVAshrExpression(RetiredBVAshrExpression<SC>),
     BVConcatExpression(RetiredBVConcatExpression<SC>),
     BVConcreteExpression(RetiredBVConcreteExpression),
+    BVIteExpression(RetiredBVIteExpression<SC>),
     BVMultiplyExpression(RetiredBVMultiplyExpression<SC>),
     BVNotExpression(RetiredBVNotExpression<SC>),
     BVOrExpression(RetiredBVOrExpression<SC>),
+    BVRotateLeftExpression(RetiredBVRotateLeftExpression<SC>),
+    BVRotateRightExpression(RetiredBVRotateRightExpression<SC>),
     BVSignExtendExpression(RetiredBVSignExtendExpression<SC>),
+    BVZeroExtendExpression(RetiredBVZeroExtendExpression<SC>),
     BVSliceExpression(RetiredBVSliceExpression<SC>),
     BVSllExpression(RetiredBVSllExpression<SC>),
     BVSrlExpression(RetiredBVSrlExpression<SC>),
     BVSubExpression(RetiredBVSubExpression<SC>),
     BVSymbol(RetiredBVSymbol),
+    BVUnsignedDivideExpression(RetiredBVUnsignedDivideExpression<SC>),
     BVUnsignedRemainderExpression(RetiredBVUnsignedRemainderExpression<SC>),
     BVXorExpression(RetiredBVXorExpression<SC>),
 }
 
 fn get_cloned_parent<SC: ScfiaComposition>(
     parent_ref: &ParentWeakReference<SC>,
-    cloned_scfia: &Scfia<SC>,
-    cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
-    cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
+    cloned_actives: &BTreeMap<u64, ActiveValue<SC>>,
+    cloned_retired: &BTreeMap<u64, RetiredValue<SC>>,
 ) -> (ParentWeakReference<SC>, Z3Ast<SC>) {
     if let Some(cloned_active) = cloned_actives.get(&parent_ref.id) {
         // Parent is active and cloned
@@ -86,16 +109,6 @@ fn get_cloned_parent<SC: ScfiaComposition>(
             },
             cloned_active.get_z3_ast(),
         )
-    } else if let Some(v) = parent_ref.weak.upgrade() {
-        // Parent is active and not yet cloned
-        let cloned_parent = v.try_borrow().unwrap().clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired);
-        (
-            ParentWeakReference {
-                id: parent_ref.id,
-                weak: cloned_parent.get_weak(),
-            },
-            cloned_parent.get_z3_ast(),
-        )
     } else if let Some(cloned_retired) = cloned_retired.get(&parent_ref.id) {
         // Parent is retired and already cloned
         (
@@ -112,24 +125,47 @@ fn get_cloned_parent<SC: ScfiaComposition>(
 }
 
 impl<SC: ScfiaComposition> RetiredValueInner<SC> {
-    pub(crate) fn clone_to_stdlib(
+    /// Collects the parents of this value that are still active but have not been cloned yet.
+    pub(crate) fn get_active_parents(&self, cloned_actives: &BTreeMap<u64, ActiveValue<SC>>, dest: &mut Vec<Rc<RefCell<ActiveValueZ3<SC>>>>) {
+        let mut parents = vec![];
+        self.expression.get_parents(&mut parents);
+        for parent in parents {
+            if !cloned_actives.contains_key(&parent.id) {
+                if let Some(parent) = parent.weak.upgrade() {
+                    dest.push(parent);
+                }
+            }
+        }
+    }
+
+    /// Clones this value's expression into `cloned_scfia`. All parents must already be present in `cloned_actives` or `cloned_retired`.
+    pub(crate) fn clone_expression(
         &self,
         cloned_scfia: &Scfia<SC>,
-        cloned_actives: &mut BTreeMap<u64, ActiveValue<SC>>,
-        cloned_retired: &mut BTreeMap<u64, RetiredValue<SC>>,
+        cloned_actives: &BTreeMap<u64, ActiveValue<SC>>,
+        cloned_retired: &BTreeMap<u64, RetiredValue<SC>>,
     ) -> RetiredValue<SC> {
-        if let Some(value) = cloned_retired.get(&self.id) {
-            return value.clone();
-        }
-
         trace!("Cloning {:?}", self);
-        let cloned_inactive: RetiredValue<SC> = match &self.expression {
+        match &self.expression {
+            RetiredExpression::BoolComparisonExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_comparison(e.predicate, &s1_ast, &s2_ast, e.is_assert);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BoolComparisonExpression(RetiredBoolComparisonExpression {
+                        s1,
+                        s2,
+                        predicate: e.predicate,
+                        is_assert: e.is_assert,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
             RetiredExpression::BoolEqExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_eq(&s1_ast, &s2_ast, e.is_assert);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BoolEqExpression(RetiredBoolEqExpression {
@@ -143,10 +179,7 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BoolNotExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_not(&s1_ast, e.is_assert);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BoolNotExpression(RetiredBoolNotExpression {
@@ -159,11 +192,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BoolSignedLessThanExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvslt(&s1_ast, &s2_ast, e.is_assert);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BoolSignedLessThanExpression(RetiredBoolSignedLessThanExpression {
@@ -177,11 +207,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BoolUnsignedLessThanExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvult(&s1_ast, &s2_ast, e.is_assert);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BoolUnsignedLessThanExpression(RetiredBoolUnsignedLessThanExpression {
@@ -194,12 +221,69 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                     self.id,
                 )
             }
+            RetiredExpression::BoolAndExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_and(&s1_ast, &s2_ast, e.is_assert);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BoolAndExpression(RetiredBoolAndExpression {
+                        s1,
+                        s2,
+                        is_assert: e.is_assert,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
+            RetiredExpression::BoolImpliesExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_implies(&s1_ast, &s2_ast, e.is_assert);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BoolImpliesExpression(RetiredBoolImpliesExpression {
+                        s1,
+                        s2,
+                        is_assert: e.is_assert,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
+            RetiredExpression::BoolOrExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_or(&s1_ast, &s2_ast, e.is_assert);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BoolOrExpression(RetiredBoolOrExpression {
+                        s1,
+                        s2,
+                        is_assert: e.is_assert,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
+            RetiredExpression::BoolXorExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_xor(&s1_ast, &s2_ast, e.is_assert);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BoolXorExpression(RetiredBoolXorExpression {
+                        s1,
+                        s2,
+                        is_assert: e.is_assert,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
             RetiredExpression::BVAddExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvadd(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVAddExpression(RetiredBVAddExpression {
@@ -213,11 +297,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVAndExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvand(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVAndExpression(RetiredBVAndExpression {
@@ -230,12 +311,24 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                     self.id,
                 )
             }
+            RetiredExpression::BVAshrExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_bvashr(&s1_ast, &s2_ast);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVAshrExpression(RetiredBVAshrExpression {
+                        s1,
+                        s2,
+                        phantom: PhantomData,
+                        width: e.width,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
             RetiredExpression::BVConcatExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvconcat(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVConcatExpression(RetiredBVConcatExpression {
@@ -249,11 +342,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVMultiplyExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvmul(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVMultiplyExpression(RetiredBVMultiplyExpression {
@@ -267,10 +357,7 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVNotExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvnot(&s1_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVNotExpression(RetiredBVNotExpression {
@@ -282,12 +369,26 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                     self.id,
                 )
             }
+            RetiredExpression::BVIteExpression(e) => {
+                let (condition, condition_ast) = get_cloned_parent(&e.condition, cloned_actives, cloned_retired);
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_ite(&condition_ast, &s1_ast, &s2_ast);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVIteExpression(RetiredBVIteExpression {
+                        condition,
+                        s1,
+                        s2,
+                        phantom: PhantomData,
+                        width: e.width,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
             RetiredExpression::BVOrExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvor(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVOrExpression(RetiredBVOrExpression {
@@ -300,11 +401,38 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                     self.id,
                 )
             }
+            RetiredExpression::BVRotateLeftExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_ext_rotate_left(&s1_ast, &s2_ast);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVRotateLeftExpression(RetiredBVRotateLeftExpression {
+                        s1,
+                        s2,
+                        width: e.width,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
+            RetiredExpression::BVRotateRightExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_ext_rotate_right(&s1_ast, &s2_ast);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVRotateRightExpression(RetiredBVRotateRightExpression {
+                        s1,
+                        s2,
+                        width: e.width,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
             RetiredExpression::BVSignExtendExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_sign_ext(e.width - e.input_width, &s1_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVSignExtendExpression(RetiredBVSignExtendExpression {
@@ -317,11 +445,22 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                     self.id,
                 )
             }
+            RetiredExpression::BVZeroExtendExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_zero_ext(e.width - e.input_width, &s1_ast);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVZeroExtendExpression(RetiredBVZeroExtendExpression {
+                        s1,
+                        width: e.width,
+                        input_width: e.input_width,
+                        phantom: PhantomData,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
             RetiredExpression::BVSliceExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_extract(e.high, e.low, &s1_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVSliceExpression(RetiredBVSliceExpression {
@@ -336,11 +475,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVSllExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvshl(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVSllExpression(RetiredBVSllExpression {
@@ -354,17 +490,13 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVSrlExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvlshr(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVSrlExpression(RetiredBVSrlExpression {
                         s1,
                         s2,
-                        shamt: e.shamt,
                         phantom: PhantomData,
                         width: e.width,
                     }),
@@ -373,11 +505,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVSubExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvsub(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVSubExpression(RetiredBVSubExpression {
@@ -391,15 +520,37 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVSymbol(e) => {
-                let z3_ast = cloned_scfia.z3.new_fresh_const(e.width);
-                cloned_scfia.new_inactive(RetiredExpression::BVSymbol(RetiredBVSymbol { width: e.width }), z3_ast, self.id)
+                let z3_ast = match &e.name {
+                    Some(name) => cloned_scfia.z3.new_named_const(name, e.width),
+                    None => cloned_scfia.z3.new_fresh_const(e.width),
+                };
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVSymbol(RetiredBVSymbol {
+                        width: e.width,
+                        name: e.name.clone(),
+                    }),
+                    z3_ast,
+                    self.id,
+                )
+            }
+            RetiredExpression::BVUnsignedDivideExpression(e) => {
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
+                let z3_ast = cloned_scfia.z3.new_bvudiv(&s1_ast, &s2_ast);
+                cloned_scfia.new_inactive(
+                    RetiredExpression::BVUnsignedDivideExpression(RetiredBVUnsignedDivideExpression {
+                        s1,
+                        s2,
+                        phantom: PhantomData,
+                        width: e.width,
+                    }),
+                    z3_ast,
+                    self.id,
+                )
             }
             RetiredExpression::BVUnsignedRemainderExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvurem(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVUnsignedRemainderExpression(RetiredBVUnsignedRemainderExpression {
@@ -413,11 +564,8 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                 )
             }
             RetiredExpression::BVXorExpression(e) => {
-                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_scfia, cloned_actives, cloned_retired);
-                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_scfia, cloned_actives, cloned_retired);
-                if let Some(value) = cloned_retired.get(&self.id) {
-                    return value.clone();
-                }
+                let (s1, s1_ast) = get_cloned_parent(&e.s1, cloned_actives, cloned_retired);
+                let (s2, s2_ast) = get_cloned_parent(&e.s2, cloned_actives, cloned_retired);
                 let z3_ast = cloned_scfia.z3.new_bvxor(&s1_ast, &s2_ast);
                 cloned_scfia.new_inactive(
                     RetiredExpression::BVXorExpression(RetiredBVXorExpression {
@@ -441,14 +589,137 @@ impl<SC: ScfiaComposition> RetiredValueInner<SC> {
                     self.id,
                 )
             }
-        };
+        }
+    }
+}
 
-        let old = cloned_retired.insert(self.id, cloned_inactive.clone());
-        if old.is_some() {
-            error!("clone_to_stdlib {} twice for scfia {:?}", self.id, Rc::as_ptr(&cloned_inactive));
-            panic!();
+impl<SC: ScfiaComposition> RetiredExpression<SC> {
+    fn get_parents<'a>(&'a self, dest: &mut Vec<&'a ParentWeakReference<SC>>) {
+        match self {
+            RetiredExpression::BoolComparisonExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolEqExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolNotExpression(e) => {
+                dest.push(&e.s1);
+            }
+            RetiredExpression::BoolSignedLessThanExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolUnsignedLessThanExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolAndExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolImpliesExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolOrExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BoolXorExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVAddExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVAndExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVAshrExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVConcatExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVConcreteExpression(_) => {}
+            RetiredExpression::BVIteExpression(e) => {
+                dest.push(&e.condition);
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVMultiplyExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVNotExpression(e) => {
+                dest.push(&e.s1);
+            }
+            RetiredExpression::BVOrExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVRotateLeftExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVRotateRightExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVSignExtendExpression(e) => {
+                dest.push(&e.s1);
+            }
+            RetiredExpression::BVZeroExtendExpression(e) => {
+                dest.push(&e.s1);
+            }
+            RetiredExpression::BVSliceExpression(e) => {
+                dest.push(&e.s1);
+            }
+            RetiredExpression::BVSllExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVSrlExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVSubExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVSymbol(_) => {}
+            RetiredExpression::BVUnsignedDivideExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVUnsignedRemainderExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+            RetiredExpression::BVXorExpression(e) => {
+                dest.push(&e.s1);
+                dest.push(&e.s2);
+            }
+        }
+    }
+}
+
+impl<SC: ScfiaComposition> Drop for RetiredValueInner<SC> {
+    fn drop(&mut self) {
+        // Release chains of inherited values iteratively, they can be longer than the native stack permits
+        let mut released: Vec<RetiredValue<SC>> = std::mem::take(&mut self.inherited_asts).into_values().collect();
+        while let Some(value) = released.pop() {
+            if let Ok(value) = Rc::try_unwrap(value) {
+                let mut value = value.into_inner();
+                released.extend(std::mem::take(&mut value.inherited_asts).into_values());
+            }
         }
-        cloned_inactive
     }
 }
 
@@ -463,21 +734,32 @@ impl<SC: ScfiaComposition> Debug for RetiredExpression<SC> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RetiredExpression::BVSymbol(e) => e.fmt(f),
+            RetiredExpression::BoolAndExpression(e) => e.fmt(f),
+            RetiredExpression::BoolImpliesExpression(e) => e.fmt(f),
+            RetiredExpression::BoolOrExpression(e) => e.fmt(f),
+            RetiredExpression::BoolXorExpression(e) => e.fmt(f),
             RetiredExpression::BVAddExpression(e) => e.fmt(f),
+            RetiredExpression::BoolComparisonExpression(e) => e.fmt(f),
             RetiredExpression::BoolEqExpression(e) => e.fmt(f),
             RetiredExpression::BoolNotExpression(e) => e.fmt(f),
             RetiredExpression::BoolSignedLessThanExpression(e) => e.fmt(f),
             RetiredExpression::BoolUnsignedLessThanExpression(e) => e.fmt(f),
             RetiredExpression::BVAndExpression(e) => e.fmt(f),
+            RetiredExpression::BVAshrExpression(e) => e.fmt(f),
             RetiredExpression::BVConcatExpression(e) => e.fmt(f),
+            RetiredExpression::BVIteExpression(e) => e.fmt(f),
             RetiredExpression::BVMultiplyExpression(e) => e.fmt(f),
             RetiredExpression::BVNotExpression(e) => e.fmt(f),
             RetiredExpression::BVOrExpression(e) => e.fmt(f),
+            RetiredExpression::BVRotateLeftExpression(e) => e.fmt(f),
+            RetiredExpression::BVRotateRightExpression(e) => e.fmt(f),
             RetiredExpression::BVSignExtendExpression(e) => e.fmt(f),
+            RetiredExpression::BVZeroExtendExpression(e) => e.fmt(f),
             RetiredExpression::BVSliceExpression(e) => e.fmt(f),
             RetiredExpression::BVSllExpression(e) => e.fmt(f),
             RetiredExpression::BVSrlExpression(e) => e.fmt(f),
             RetiredExpression::BVSubExpression(e) => e.fmt(f),
+            RetiredExpression::BVUnsignedDivideExpression(e) => e.fmt(f),
             RetiredExpression::BVUnsignedRemainderExpression(e) => e.fmt(f),
             RetiredExpression::BVXorExpression(e) => e.fmt(f),
             RetiredExpression::BVConcreteExpression(e) => e.fmt(f),
@@ -1,5 +1,8 @@
 use std::{
-    cell::{Cell, OnceCell},
+    cell::{Cell, OnceCell, RefCell},
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    ffi::{CStr, CString},
+    hash::{Hash, Hasher},
     ptr,
     rc::{Rc, Weak},
     time::Instant,
@@ -7,22 +10,57 @@ use std::{
 
 use log::{debug, error, info, warn};
 use z3_sys::{
-    Z3_ast, Z3_context, Z3_dec_ref, Z3_del_config, Z3_del_context, Z3_get_numeral_uint64, Z3_inc_ref, Z3_lbool, Z3_mk_bv_sort, Z3_mk_bvadd, Z3_mk_bvand,
-    Z3_mk_bvlshr, Z3_mk_bvmul, Z3_mk_bvor, Z3_mk_bvshl, Z3_mk_bvslt, Z3_mk_bvsub, Z3_mk_bvuge, Z3_mk_bvult, Z3_mk_bvurem, Z3_mk_bvxor, Z3_mk_concat,
-    Z3_mk_config, Z3_mk_context_rc, Z3_mk_eq, Z3_mk_extract, Z3_mk_false, Z3_mk_fresh_const, Z3_mk_not, Z3_mk_or, Z3_mk_sign_ext, Z3_mk_solver, Z3_mk_true,
-    Z3_mk_unsigned_int64, Z3_model_eval, Z3_solver, Z3_solver_assert, Z3_solver_check, Z3_solver_check_assumptions, Z3_solver_get_model, Z3_solver_inc_ref,
-    Z3_string, Z3_L_FALSE, Z3_L_TRUE, Z3_mk_bvnot, Z3_mk_bvashr,
+    Z3_ast, Z3_context, Z3_dec_ref, Z3_del_config, Z3_del_context, Z3_inc_ref, Z3_lbool, Z3_mk_and, Z3_mk_bv_sort, Z3_mk_bvadd, Z3_mk_bvand,
+    Z3_mk_bvlshr, Z3_mk_bvmul, Z3_mk_bvor, Z3_mk_bvshl, Z3_mk_bvsge, Z3_mk_bvsgt, Z3_mk_bvsle, Z3_mk_bvslt, Z3_mk_bvsub, Z3_mk_bvuge, Z3_mk_bvugt, Z3_mk_bvule, Z3_mk_bvult, Z3_mk_bvudiv, Z3_mk_bvurem, Z3_mk_bvxor, Z3_mk_concat,
+    Z3_mk_config, Z3_mk_context_rc, Z3_mk_eq, Z3_mk_ext_rotate_left, Z3_mk_ext_rotate_right, Z3_mk_extract, Z3_mk_false, Z3_mk_fresh_const, Z3_mk_implies, Z3_mk_ite, Z3_mk_not, Z3_mk_or, Z3_mk_sign_ext, Z3_mk_solver, Z3_mk_true,
+    Z3_mk_unsigned_int64, Z3_mk_xor, Z3_mk_zero_ext, Z3_model_eval, Z3_solver, Z3_solver_assert, Z3_solver_check, Z3_solver_check_assumptions, Z3_solver_get_model, Z3_solver_inc_ref,
+    Z3_string, Z3_L_FALSE, Z3_L_TRUE, Z3_mk_bvnot, Z3_mk_bvashr, Z3_ast_to_string, Z3_ast_vector_dec_ref, Z3_ast_vector_inc_ref, Z3_ast_vector_size,
+    Z3_solver_get_assertions, Z3_ast_vector_get, Z3_func_decl_to_string, Z3_get_app_arg, Z3_get_app_decl, Z3_get_app_num_args, Z3_get_ast_id,
+    Z3_get_bool_value, Z3_is_app, Z3_is_numeral_ast, Z3_to_app, Z3_L_UNDEF, Z3_model, Z3_model_dec_ref, Z3_model_inc_ref, Z3_mk_const,
+    Z3_mk_string_symbol,
+    Z3_get_bv_sort_size, Z3_get_sort, Z3_get_numeral_string,
 };
 
-use crate::{scfia::Scfia, values::active_value::ActiveValue, GenericForkSink, ScfiaComposition};
+use crate::{
+    scfia::Scfia,
+    values::{active_value::ActiveValue, bool_comparison_expression::ComparisonPredicate},
+    GenericForkSink, ScfiaComposition,
+};
 
 pub const PREFIX: [i8; 4] = ['p' as i8, 'r' as i8, 'e' as i8, 0];
 
+/// The most results each query cache keeps before evicting the least recently used one.
+pub const QUERY_CACHE_CAPACITY: usize = 4096;
+
+/// Solver results keyed by the assertion fingerprint and the queried ASTs' ids.
+/// Entries hold on to their ASTs so that the ids cannot be reused while cached.
+/// AST ids are only meaningful within one context, so a cloned handle starts with empty caches.
+type AssumptionsCache<SC> = QueryCache<(u64, Vec<u32>), (Vec<Z3Ast<SC>>, Z3_lbool)>;
+type CandidatesCache<SC> = QueryCache<(u64, u32, Vec<u128>), (Z3Ast<SC>, Vec<u128>)>;
+type UniqueCache<SC> = QueryCache<(u64, u32), (Z3Ast<SC>, Option<u128>)>;
+
+/// A map which evicts its least recently used entry once it holds more than `QUERY_CACHE_CAPACITY` entries.
+#[derive(Debug)]
+struct QueryCache<K: Ord + Clone, V> {
+    entries: BTreeMap<K, (V, u64)>,
+    recency: BTreeMap<u64, K>,
+    clock: u64,
+}
+
 #[derive(Debug)]
 pub struct Z3Handle<SC: ScfiaComposition> {
     pub context: Z3_context,
     pub solver: Z3_solver,
     pub ast_refs: Cell<i64>,
+    /// Conditions decided without the solver, see [`crate::abstract_domain`].
+    pub decided_by_abstraction: Cell<u64>,
+    /// Changes with every assertion, so query results of other assertion sets are not reused.
+    pub assertions_fingerprint: Cell<u64>,
+    assumptions_cache: RefCell<AssumptionsCache<SC>>,
+    candidates_cache: RefCell<CandidatesCache<SC>>,
+    unique_cache: RefCell<UniqueCache<SC>>,
+    pub query_cache_hits: Cell<u64>,
+    pub query_cache_misses: Cell<u64>,
     pub selff: OnceCell<Weak<Self>>,
 }
 
@@ -32,6 +70,17 @@ pub struct Z3Ast<SC: ScfiaComposition> {
     pub z3: Weak<Z3Handle<SC>>,
 }
 
+/// A satisfying assignment of the solver's assertions.
+#[derive(Debug)]
+pub struct Counterexample<SC: ScfiaComposition> {
+    model: Z3_model,
+    /// The value of every symbol of the state by id, including symbols the solver did not constrain.
+    pub symbols: BTreeMap<u64, u128>,
+    /// The ids of the named symbols.
+    pub names: BTreeMap<String, u64>,
+    pub z3: Weak<Z3Handle<SC>>,
+}
+
 impl<SC: ScfiaComposition> Z3Handle<SC> {
     pub fn new() -> Rc<Self> {
         unsafe {
@@ -44,6 +93,13 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
                 context,
                 solver,
                 ast_refs: Cell::new(0),
+                decided_by_abstraction: Cell::new(0),
+                assertions_fingerprint: Cell::new(0),
+                assumptions_cache: RefCell::new(QueryCache::new()),
+                candidates_cache: RefCell::new(QueryCache::new()),
+                unique_cache: RefCell::new(QueryCache::new()),
+                query_cache_hits: Cell::new(0),
+                query_cache_misses: Cell::new(0),
                 selff: OnceCell::new(),
             });
             selff.selff.set(Rc::downgrade(&selff)).unwrap();
@@ -57,6 +113,86 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
+    fn solver_assert(&self, ast: Z3_ast) {
+        unsafe {
+            Z3_solver_assert(self.context, self.solver, ast);
+            let mut hasher = DefaultHasher::new();
+            (self.assertions_fingerprint.get(), Z3_get_ast_id(self.context, ast)).hash(&mut hasher);
+            self.assertions_fingerprint.set(hasher.finish());
+        }
+    }
+
+    fn record_query_cache_hit(&self, hit: bool) {
+        if hit {
+            self.query_cache_hits.set(self.query_cache_hits.get() + 1);
+        } else {
+            self.query_cache_misses.set(self.query_cache_misses.get() + 1);
+        }
+    }
+
+    pub fn get_assertions_count(&self) -> u32 {
+        unsafe {
+            let assertions = Z3_solver_get_assertions(self.context, self.solver);
+            Z3_ast_vector_inc_ref(self.context, assertions);
+            let count = Z3_ast_vector_size(self.context, assertions);
+            Z3_ast_vector_dec_ref(self.context, assertions);
+            count
+        }
+    }
+
+    /// Renders the solver's assertions as a standalone SMT-LIB2 script which queries the given values.
+    pub fn to_smtlib_script(&self, values: &[Z3Ast<SC>]) -> String {
+        unsafe {
+            let assertions = Z3_solver_get_assertions(self.context, self.solver);
+            Z3_ast_vector_inc_ref(self.context, assertions);
+            let mut asserted = vec![];
+            for i in 0..Z3_ast_vector_size(self.context, assertions) {
+                asserted.push(Z3_ast_vector_get(self.context, assertions, i));
+            }
+
+            // Collect the declarations of all uninterpreted constants
+            let mut declarations = BTreeMap::new();
+            let mut visited = BTreeSet::new();
+            let mut worklist: Vec<Z3_ast> = asserted.iter().copied().chain(values.iter().map(|e| e.ast)).collect();
+            while let Some(ast) = worklist.pop() {
+                let id = Z3_get_ast_id(self.context, ast);
+                if !visited.insert(id) || !Z3_is_app(self.context, ast) || Z3_is_numeral_ast(self.context, ast) {
+                    continue;
+                }
+                let app = Z3_to_app(self.context, ast);
+                let num_args = Z3_get_app_num_args(self.context, app);
+                if num_args == 0 && Z3_get_bool_value(self.context, ast) == Z3_L_UNDEF {
+                    let declaration = Z3_func_decl_to_string(self.context, Z3_get_app_decl(self.context, app));
+                    declarations.insert(id, z3_string_to_string(declaration));
+                }
+                for i in 0..num_args {
+                    worklist.push(Z3_get_app_arg(self.context, app, i));
+                }
+            }
+
+            let mut script = String::new();
+            script.push_str("(set-option :produce-models true)\n");
+            script.push_str("(set-logic QF_BV)\n");
+            for declaration in declarations.values() {
+                script.push_str(&format!("{}\n", declaration));
+            }
+            for assertion in &asserted {
+                script.push_str(&format!("(assert {})\n", z3_string_to_string(Z3_ast_to_string(self.context, *assertion))));
+            }
+            script.push_str("(check-sat)\n");
+            if !values.is_empty() {
+                let values: Vec<String> = values.iter().map(|e| e.to_smtlib()).collect();
+                script.push_str(&format!("(get-value ({}))\n", values.join(" ")));
+            }
+            Z3_ast_vector_dec_ref(self.context, assertions);
+            script
+        }
+    }
+
+    pub fn get_bv_width(&self, value: &Z3Ast<SC>) -> u32 {
+        unsafe { Z3_get_bv_sort_size(self.context, Z3_get_sort(self.context, value.ast)) }
+    }
+
     pub fn new_bool_concrete(&self, value: bool) -> Z3Ast<SC> {
         unsafe {
             let ast = if value { Z3_mk_true(self.context) } else { Z3_mk_false(self.context) };
@@ -75,7 +211,7 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
             if is_assert {
-                Z3_solver_assert(self.context, self.solver, ast);
+                self.solver_assert(ast);
             }
             Z3Ast {
                 ast,
@@ -90,7 +226,7 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
             if is_assert {
-                Z3_solver_assert(self.context, self.solver, ast);
+                self.solver_assert(ast);
             }
             Z3Ast {
                 ast,
@@ -105,7 +241,7 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
             if is_assert {
-                Z3_solver_assert(self.context, self.solver, ast);
+                self.solver_assert(ast);
             }
             Z3Ast {
                 ast,
@@ -120,7 +256,29 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
             if is_assert {
-                Z3_solver_assert(self.context, self.solver, ast);
+                self.solver_assert(ast);
+            }
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
+    pub fn new_comparison(&self, predicate: ComparisonPredicate, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>, is_assert: bool) -> Z3Ast<SC> {
+        unsafe {
+            let ast = match predicate {
+                ComparisonPredicate::UnsignedLessEqual => Z3_mk_bvule(self.context, s1.ast, s2.ast),
+                ComparisonPredicate::UnsignedGreaterThan => Z3_mk_bvugt(self.context, s1.ast, s2.ast),
+                ComparisonPredicate::UnsignedGreaterEqual => Z3_mk_bvuge(self.context, s1.ast, s2.ast),
+                ComparisonPredicate::SignedLessEqual => Z3_mk_bvsle(self.context, s1.ast, s2.ast),
+                ComparisonPredicate::SignedGreaterThan => Z3_mk_bvsgt(self.context, s1.ast, s2.ast),
+                ComparisonPredicate::SignedGreaterEqual => Z3_mk_bvsge(self.context, s1.ast, s2.ast),
+            };
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            if is_assert {
+                self.solver_assert(ast);
             }
             Z3Ast {
                 ast,
@@ -135,7 +293,7 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
             if is_assert {
-                Z3_solver_assert(self.context, self.solver, ast);
+                self.solver_assert(ast);
             }
             Z3Ast {
                 ast,
@@ -180,10 +338,16 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
-    pub fn new_bv_concrete(&self, value: u64, width: u32) -> Z3Ast<SC> {
+    pub fn new_bv_concrete(&self, value: u128, width: u32) -> Z3Ast<SC> {
+        if width > 64 {
+            // Z3 has no 128 bit numeral constructor, so wide values are assembled from two halves
+            let high = self.new_bv_concrete(value >> 64, width - 64);
+            let low = self.new_bv_concrete(value & u64::MAX as u128, 64);
+            return self.new_bvconcat(&high, &low);
+        }
         unsafe {
             let sort = Z3_mk_bv_sort(self.context, width);
-            let ast = Z3_mk_unsigned_int64(self.context, value, sort);
+            let ast = Z3_mk_unsigned_int64(self.context, value as u64, sort);
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
             Z3Ast {
@@ -229,6 +393,18 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
+    pub fn new_ite(&self, condition: &Z3Ast<SC>, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_ite(self.context, condition.ast, s1.ast, s2.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
     pub fn new_sign_ext(&self, extension_width: u32, s1: &Z3Ast<SC>) -> Z3Ast<SC> {
         unsafe {
             let ast = Z3_mk_sign_ext(self.context, extension_width, s1.ast);
@@ -241,6 +417,42 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
+    pub fn new_zero_ext(&self, extension_width: u32, s1: &Z3Ast<SC>) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_zero_ext(self.context, extension_width, s1.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
+    pub fn new_ext_rotate_left(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_ext_rotate_left(self.context, s1.ast, s2.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
+    pub fn new_ext_rotate_right(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_ext_rotate_right(self.context, s1.ast, s2.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
     pub fn new_extract(&self, high: u32, low: u32, s1: &Z3Ast<SC>) -> Z3Ast<SC> {
         unsafe {
             let ast = Z3_mk_extract(self.context, high, low, s1.ast);
@@ -301,6 +513,20 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
+    pub fn new_named_const(&self, name: &str, width: u32) -> Z3Ast<SC> {
+        unsafe {
+            let name = CString::new(name).unwrap();
+            let symbol = Z3_mk_string_symbol(self.context, name.as_ptr());
+            let ast = Z3_mk_const(self.context, symbol, Z3_mk_bv_sort(self.context, width));
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
     pub fn new_fresh_const(&self, width: u32) -> Z3Ast<SC> {
         unsafe {
             let ast = Z3_mk_fresh_const(self.context, 0 as Z3_string, Z3_mk_bv_sort(self.context, width));
@@ -313,6 +539,18 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
+    pub fn new_bvudiv(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_bvudiv(self.context, s1.ast, s2.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
     pub fn new_bvurem(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>) -> Z3Ast<SC> {
         unsafe {
             let ast = Z3_mk_bvurem(self.context, s1.ast, s2.ast);
@@ -337,11 +575,59 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
         }
     }
 
-    pub fn new_or(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>) -> Z3Ast<SC> {
+    pub fn new_and(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>, is_assert: bool) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_and(self.context, 2, [s1.ast, s2.ast].as_ptr());
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            if is_assert {
+                self.solver_assert(ast);
+            }
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
+    pub fn new_or(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>, is_assert: bool) -> Z3Ast<SC> {
         unsafe {
             let ast = Z3_mk_or(self.context, 2, [s1.ast, s2.ast].as_ptr());
             Z3_inc_ref(self.context, ast);
             self.ast_refs.set(self.ast_refs.get() + 1);
+            if is_assert {
+                self.solver_assert(ast);
+            }
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
+    pub fn new_xor(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>, is_assert: bool) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_xor(self.context, s1.ast, s2.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            if is_assert {
+                self.solver_assert(ast);
+            }
+            Z3Ast {
+                ast,
+                z3: self.selff.get().unwrap().clone(),
+            }
+        }
+    }
+
+    pub fn new_implies(&self, s1: &Z3Ast<SC>, s2: &Z3Ast<SC>, is_assert: bool) -> Z3Ast<SC> {
+        unsafe {
+            let ast = Z3_mk_implies(self.context, s1.ast, s2.ast);
+            Z3_inc_ref(self.context, ast);
+            self.ast_refs.set(self.ast_refs.get() + 1);
+            if is_assert {
+                self.solver_assert(ast);
+            }
             Z3Ast {
                 ast,
                 z3: self.selff.get().unwrap().clone(),
@@ -352,68 +638,163 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
     pub fn check_assumptions(&self, assumptions: &[&Z3Ast<SC>]) -> Z3_lbool {
         unsafe {
             let mut assumptions_asts = Vec::with_capacity(assumptions.len());
+            let mut assumptions_ids = Vec::with_capacity(assumptions.len());
             for assumption in assumptions {
-                assumptions_asts.push(assumption.ast)
+                assumptions_asts.push(assumption.ast);
+                assumptions_ids.push(Z3_get_ast_id(self.context, assumption.ast));
             }
-            Z3_solver_check_assumptions(self.context, self.solver, assumptions_asts.len().try_into().unwrap(), assumptions_asts.as_ptr())
+            let key = (self.assertions_fingerprint.get(), assumptions_ids);
+            let cached = self.assumptions_cache.borrow_mut().get(&key).map(|(_, result)| *result);
+            if let Some(result) = cached {
+                self.record_query_cache_hit(true);
+                return result;
+            }
+            self.record_query_cache_hit(false);
+
+            let result = Z3_solver_check_assumptions(self.context, self.solver, assumptions_asts.len().try_into().unwrap(), assumptions_asts.as_ptr());
+            let retained = assumptions.iter().map(|assumption| (*assumption).clone()).collect();
+            self.assumptions_cache.borrow_mut().insert(key, (retained, result));
+            result
         }
     }
 
     pub fn check_condition(&self, scfia: &Scfia<SC>, condition: &ActiveValue<SC>, fork_sink: &mut Option<SC::ForkSink>) -> bool {
-        unsafe {
-            match condition {
-                ActiveValue::BoolConcrete(e) => *e,
-                ActiveValue::BVConcrete(_, _) => panic!("condition was bitvector"),
-                ActiveValue::Expression(expression) => {
-                    let mut can_be_true = false;
-                    let mut can_be_false = false;
+        match condition {
+            ActiveValue::BoolConcrete(e) => *e,
+            ActiveValue::BVConcrete(_, _) => panic!("condition was bitvector"),
+            ActiveValue::Expression(expression) => {
+                if let Some(value) = expression.try_borrow().unwrap().abstraction.get_bool() {
+                    debug!("condition {:?} decided by its abstraction", expression);
+                    self.decided_by_abstraction.set(self.decided_by_abstraction.get() + 1);
+                    return value;
+                }
 
-                    let condition_ast = condition.get_z3_ast().ast;
-                    debug!("building neg_condition_symbol");
-                    let neg_condition_symbol = scfia.new_bool_not(condition, None, false, fork_sink, None);
-                    let neg_condition_ast = neg_condition_symbol.get_z3_ast().ast;
+                let mut can_be_true = false;
+                let mut can_be_false = false;
 
-                    if Z3_solver_check_assumptions(self.context, self.solver, 1, &condition_ast) != Z3_L_FALSE {
-                        can_be_true = true
-                    }
+                let condition_ast = condition.get_z3_ast();
+                debug!("building neg_condition_symbol");
+                let neg_condition_symbol = scfia.new_bool_not(condition, None, false, fork_sink, None);
+                let neg_condition_ast = neg_condition_symbol.get_z3_ast();
 
-                    if Z3_solver_check_assumptions(self.context, self.solver, 1, &neg_condition_ast) != Z3_L_FALSE {
-                        can_be_false = true
-                    }
+                if self.check_assumptions(&[&condition_ast]) != Z3_L_FALSE {
+                    can_be_true = true
+                }
 
-                    if can_be_true && can_be_false {
-                        if let Some(fork_sink) = fork_sink {
-                            info!("Forking over {:?}", expression);
-                            fork_sink.fork(neg_condition_symbol);
-                            debug!("asserting condition in current branch");
-                            condition.assert(scfia);
-                            true
-                        } else {
-                            error!("unexpected fork");
-                            panic!("unexpected fork")
-                        }
-                    } else if can_be_true {
+                if self.check_assumptions(&[&neg_condition_ast]) != Z3_L_FALSE {
+                    can_be_false = true
+                }
+
+                if can_be_true && can_be_false {
+                    if let Some(fork_sink) = fork_sink {
+                        info!("Forking over {:?}", expression);
+                        fork_sink.fork(neg_condition_symbol);
+                        debug!("asserting condition in current branch");
+                        condition.assert(scfia);
                         true
-                    } else if can_be_false {
-                        false
                     } else {
-                        unreachable!()
+                        error!("unexpected fork");
+                        panic!("unexpected fork")
                     }
+                } else if can_be_true {
+                    true
+                } else if can_be_false {
+                    false
+                } else {
+                    unreachable!()
+                }
+            }
+        }
+    }
+
+    /// Finds a model of the assertions and evaluates the given symbols, which are (id, name, ast) triples, under it.
+    pub fn get_counterexample(&self, symbols: &[(u64, Option<String>, Z3Ast<SC>)]) -> Option<Counterexample<SC>> {
+        unsafe {
+            if Z3_solver_check(self.context, self.solver) != Z3_L_TRUE {
+                return None;
+            }
+
+            let model = Z3_solver_get_model(self.context, self.solver);
+            Z3_model_inc_ref(self.context, model);
+            let mut counterexample = Counterexample {
+                model,
+                symbols: BTreeMap::new(),
+                names: BTreeMap::new(),
+                z3: self.selff.get().unwrap().clone(),
+            };
+            for (id, name, ast) in symbols {
+                let value = counterexample.eval_ast(ast);
+                counterexample.symbols.insert(*id, value);
+                if let Some(name) = name {
+                    counterexample.names.insert(name.clone(), *id);
                 }
             }
+            Some(counterexample)
+        }
+    }
+
+    /// Returns the only value the bitvector can take under the assertions, using one model and one query which rules out any other value.
+    pub fn get_unique(&self, value: &Z3Ast<SC>) -> Option<u128> {
+        unsafe {
+            let key = (self.assertions_fingerprint.get(), Z3_get_ast_id(self.context, value.ast));
+            let cached = self.unique_cache.borrow_mut().get(&key).map(|(_, unique)| *unique);
+            if let Some(unique) = cached {
+                self.record_query_cache_hit(true);
+                return unique;
+            }
+            self.record_query_cache_hit(false);
+
+            let unique = self.find_unique(value);
+            self.unique_cache.borrow_mut().insert(key, (value.clone(), unique));
+            unique
         }
     }
 
-    pub fn monomorphize(&self, value: &Z3Ast<SC>, candidates: &mut Vec<u64>) {
+    fn find_unique(&self, value: &Z3Ast<SC>) -> Option<u128> {
+        unsafe {
+            if Z3_solver_check(self.context, self.solver) != Z3_L_TRUE {
+                return None;
+            }
+
+            let model = Z3_solver_get_model(self.context, self.solver);
+            Z3_model_inc_ref(self.context, model);
+            let mut z3_ast_result: Z3_ast = ptr::null_mut();
+            assert!(Z3_model_eval(self.context, model, value.ast, true, &mut z3_ast_result));
+            Z3_inc_ref(self.context, z3_ast_result);
+            let candidate = z3_numeral_to_u128(self.context, z3_ast_result).unwrap();
+            Z3_dec_ref(self.context, z3_ast_result);
+            Z3_model_dec_ref(self.context, model);
+
+            let candidate_ast = self.new_bv_concrete(candidate, self.get_bv_width(value));
+            let eq = self.new_eq(&candidate_ast, value, false);
+            let other = self.new_not(&eq, false);
+            if self.check_assumptions(&[&other]) == Z3_L_FALSE {
+                Some(candidate)
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn monomorphize(&self, value: &Z3Ast<SC>, candidates: &mut Vec<u128>) {
         unsafe {
             let begin = Instant::now();
             debug!("monomorphize");
+            let key = (self.assertions_fingerprint.get(), Z3_get_ast_id(self.context, value.ast), candidates.clone());
+            let cached = self.candidates_cache.borrow_mut().get(&key).map(|(_, cached)| cached.clone());
+            if let Some(cached) = cached {
+                self.record_query_cache_hit(true);
+                *candidates = cached;
+                return;
+            }
+            self.record_query_cache_hit(false);
 
             // Fill assumptions with known candidates
+            let width = self.get_bv_width(value);
             let mut assumptions = Vec::with_capacity(candidates.len());
             let mut assumptions_asts = Vec::with_capacity(candidates.len());
             for candidate in candidates.iter() {
-                let candidate_ast = self.new_bv_concrete(*candidate, 32); // TODO don't hardcode width
+                let candidate_ast = self.new_bv_concrete(*candidate, width);
                 let eq = self.new_eq(&candidate_ast, value, false); // TODO this is unsafe - we need to ensure these are not freed
                 let assumption = self.new_not(&eq, false);
                 assumptions_asts.push(assumption.ast);
@@ -432,34 +813,124 @@ impl<SC: ScfiaComposition> Z3Handle<SC> {
                 let mut z3_ast_result: Z3_ast = ptr::null_mut();
                 assert!(Z3_model_eval(self.context, model, value.ast, true, &mut z3_ast_result));
                 Z3_inc_ref(self.context, z3_ast_result);
-                let mut candidate: u64 = 0;
-                assert!(Z3_get_numeral_uint64(self.context, z3_ast_result, &mut candidate));
+                let candidate = z3_numeral_to_u128(self.context, z3_ast_result).unwrap();
                 Z3_dec_ref(self.context, z3_ast_result);
 
                 warn!("Unpredicted monomorphization candidate 0x{:x} ", candidate);
                 candidates.push(candidate);
 
-                let candidate_ast = self.new_bv_concrete(candidate, 32); // TODO don't hardcode width
+                let candidate_ast = self.new_bv_concrete(candidate, width);
                 let eq = self.new_eq(&candidate_ast, value, false); // TODO this is unsafe - we need to ensure these are not freed
                 let assumption = self.new_not(&eq, false);
                 assumptions_asts.push(assumption.ast);
                 assumptions.push(assumption);
             }
 
+            self.candidates_cache.borrow_mut().insert(key, (value.clone(), candidates.clone()));
             debug!("monomorphize_active done after {} ms", begin.elapsed().as_millis());
         }
     }
 }
 
+impl<K: Ord + Clone, V> QueryCache<K, V> {
+    fn new() -> Self {
+        QueryCache {
+            entries: BTreeMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let (value, last_used) = self.entries.get_mut(key)?;
+        self.clock += 1;
+        self.recency.remove(last_used);
+        self.recency.insert(self.clock, key.clone());
+        *last_used = self.clock;
+        Some(value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.clock += 1;
+        if let Some((_, last_used)) = self.entries.insert(key.clone(), (value, self.clock)) {
+            self.recency.remove(&last_used);
+        }
+        self.recency.insert(self.clock, key);
+        if self.entries.len() > QUERY_CACHE_CAPACITY {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
 impl<SC: ScfiaComposition> Z3Ast<SC> {
     pub fn assert(&self) {
+        let z3 = self.z3.upgrade().unwrap();
+        z3.solver_assert(self.ast);
+    }
+
+    pub fn to_smtlib(&self) -> String {
+        unsafe {
+            let z3 = self.z3.upgrade().unwrap();
+            z3_string_to_string(Z3_ast_to_string(z3.context, self.ast))
+        }
+    }
+}
+
+impl<SC: ScfiaComposition> Counterexample<SC> {
+    /// Evaluates the value under this assignment, completing it for symbols the solver did not constrain.
+    pub fn eval(&self, value: &ActiveValue<SC>) -> u128 {
+        match value {
+            ActiveValue::BoolConcrete(value) => *value as u128,
+            ActiveValue::BVConcrete(value, _) => *value,
+            ActiveValue::Expression(_) => self.eval_ast(&value.get_z3_ast()),
+        }
+    }
+
+    /// Looks up the value of the named symbol.
+    pub fn get_named(&self, name: &str) -> Option<u128> {
+        self.names.get(name).map(|id| self.symbols[id])
+    }
+
+    fn eval_ast(&self, value: &Z3Ast<SC>) -> u128 {
         unsafe {
             let z3 = self.z3.upgrade().unwrap();
-            Z3_solver_assert(z3.context, z3.solver, self.ast);
+            let mut result: Z3_ast = ptr::null_mut();
+            assert!(Z3_model_eval(z3.context, self.model, value.ast, true, &mut result));
+            Z3_inc_ref(z3.context, result);
+            let concrete = match Z3_get_bool_value(z3.context, result) {
+                Z3_L_TRUE => 1,
+                Z3_L_FALSE => 0,
+                _ => z3_numeral_to_u128(z3.context, result).unwrap(),
+            };
+            Z3_dec_ref(z3.context, result);
+            concrete
         }
     }
 }
 
+impl<SC: ScfiaComposition> Drop for Counterexample<SC> {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(z3) = self.z3.upgrade() {
+                Z3_model_dec_ref(z3.context, self.model);
+            }
+        }
+    }
+}
+
+unsafe fn z3_string_to_string(value: Z3_string) -> String {
+    CStr::from_ptr(value).to_str().unwrap().to_string()
+}
+
+/// Reads a numeral of up to 128 bits, which `Z3_get_numeral_uint64` cannot.
+unsafe fn z3_numeral_to_u128(context: Z3_context, ast: Z3_ast) -> Option<u128> {
+    if !Z3_is_numeral_ast(context, ast) {
+        return None;
+    }
+    z3_string_to_string(Z3_get_numeral_string(context, ast)).parse().ok()
+}
+
 impl<SC: ScfiaComposition> Drop for Z3Handle<SC> {
     fn drop(&mut self) {
         unsafe { Z3_del_context(self.context) }
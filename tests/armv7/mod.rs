@@ -5,7 +5,7 @@ use scfia_lib::{
     scfia::Scfia,
     values::active_value::ActiveValueImpl,
 };
-use std::{fs, rc::Rc, thread, time::Instant};
+use std::{fs, rc::Rc, time::Instant};
 use xmas_elf::program::ProgramHeader::Ph32;
 use xmas_elf::{program, ElfFile};
 
@@ -31,9 +31,7 @@ fn step_until(state: &mut ARMv7M, address: u64, begin: &Instant) {
 
 #[test]
 fn test_armv7m_system_state() {
-    let builder = thread::Builder::new().stack_size(4 * 1024 * 1024 * 1024);
-    let handler = builder.spawn(test_system_state_inner).unwrap();
-    handler.join().unwrap();
+    test_system_state_inner();
 }
 
 fn _dump_regs(state: &ARMv7M) {
@@ -63,12 +61,12 @@ fn test_system_state_inner() {
     let mut memory = Memory::default();
 
     let code = StableMemoryRegion::new(0, 0x2000_0000);
-    memory.stables.push(code);
+    memory.stables.insert(code);
 
     for (i, b) in binary_blob.iter().enumerate() {
         memory.write(
-            &scfia.new_bv_concrete(0x8000000 + i as u64, 8),
-            &scfia.new_bv_concrete(*b as u64, 8),
+            &scfia.new_bv_concrete(0x8000000 + i as u128, 8),
+            &scfia.new_bv_concrete(*b as u128, 8),
             8,
             &scfia,
             &mut None,
@@ -77,19 +75,13 @@ fn test_system_state_inner() {
     }
 
     let sram = StableMemoryRegion::new(0x2000_0000, 0x2000_0000);
-    memory.stables.push(sram);
+    memory.stables.insert(sram);
 
     // Peripheral
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x40000000,
-        length: 0x20000000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x40000000, 0x20000000));
 
     // Private peripheral bus
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0xe0000000,
-        length: 0xe0100000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0xe0000000, 0xe0100000));
 
     let mut system_state: ARMv7M = ARMv7M {
         state: armv7m::SystemState {
@@ -1,2 +1,3 @@
 mod armv7;
 mod rv32im;
+mod scfia;
@@ -1,12 +1,11 @@
 mod constants;
 
-use std::ffi::CStr;
 use std::rc::Rc;
 use std::time::Instant;
-use std::{fs, thread};
+use std::fs;
 
 use log::{debug, info, trace, warn, LevelFilter};
-use scfia_lib::memory::regions::{StableMemoryRegion, SymbolicVolatileMemoryRegion, VolatileMemoryRegion};
+use scfia_lib::memory::regions::{StableMemoryRegion, SymbolicVolatileMemoryRegion, SymbolicVolatilePolicy, VolatileMemoryRegion};
 use scfia_lib::memory::Memory;
 use scfia_lib::models::riscv::rv32i::{self, RV32i, RV32iScfiaComposition};
 use scfia_lib::scfia::Scfia;
@@ -14,7 +13,6 @@ use scfia_lib::values::active_value::ActiveValueImpl;
 use scfia_lib::SymbolicHints;
 use xmas_elf::program::ProgramHeader::Ph32;
 use xmas_elf::{program, ElfFile};
-use z3_sys::Z3_ast_to_string;
 
 use crate::rv32im::constants::{
     COPY_FROM_3, EGRESS_RECEIVEQUEUE_DESCRIPTOR_ADDRESS_HIGHER_U32, EGRESS_RECEIVEQUEUE_DESCRIPTOR_ADDRESS_LOWER_U32, EGRESS_RECEIVEQUEUE_DRIVER_POSITIONS,
@@ -49,12 +47,8 @@ fn step_until_hinted(rv32i_system_state: &mut RV32i, address: u64, begin: &Insta
             rv32i_system_state.scfia.z3.ast_refs.get()
         );
         if pc == 0x72c {
-            unsafe {
-                let ptr = Z3_ast_to_string(rv32i_system_state.scfia.z3.context, rv32i_system_state.state.x10.get_z3_ast().ast);
-                let str = CStr::from_ptr(ptr);
-                info!("Z3_ast_to_string={}", str.to_str().unwrap());
-                rv32i_system_state.debug();
-            }
+            info!("x10={}", rv32i_system_state.scfia.to_smtlib(&[&rv32i_system_state.state.x10]));
+            rv32i_system_state.debug();
         }
 
         let mut found_hint = None;
@@ -75,9 +69,7 @@ fn step_until_hinted(rv32i_system_state: &mut RV32i, address: u64, begin: &Insta
 
 #[test]
 fn test_rv32i_system_state() {
-    let builder = thread::Builder::new().stack_size(4 * 1024 * 1024 * 1024);
-    let handler = builder.spawn(test_system_state_inner).unwrap();
-    handler.join().unwrap();
+    test_system_state_inner();
 }
 
 fn _dump_regs(state: &RV32i) {
@@ -128,12 +120,12 @@ fn test_system_state_inner() {
                 program::Type::Load => {
                     trace!("{:?}", program_header);
                     let stable_region = StableMemoryRegion::new(ph32.virtual_addr as u64, ph32.mem_size as u64);
-                    memory.stables.push(stable_region);
+                    memory.stables.insert(stable_region);
 
                     for (i, b) in ph32.raw_data(&elf).iter().enumerate() {
                         memory.write(
-                            &scfia.new_bv_concrete(ph32.virtual_addr as u64 + i as u64, 8),
-                            &scfia.new_bv_concrete(*b as u64, 8),
+                            &scfia.new_bv_concrete(ph32.virtual_addr as u128 + i as u128, 8),
+                            &scfia.new_bv_concrete(*b as u128, 8),
                             8,
                             &scfia,
                             &mut None,
@@ -147,88 +139,46 @@ fn test_system_state_inner() {
     }
 
     // ingress nic mmio register
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x0a003e00,
-        length: 200,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x0a003e00, 200));
 
     // egress nic mmio register
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x0a003c00,
-        length: 200,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x0a003c00, 200));
 
     // ingress nic receivequeue driver area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46004000,
-        length: 0x1000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46004000, 0x1000));
 
     // ingress nic receivequeue device area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46005000,
-        length: 0x3000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46005000, 0x3000));
 
     // ingress nic receivequeue buffers
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46008000,
-        length: 0x400000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46008000, 0x400000));
 
     // ingress nic sendqueue driver area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x4640c000,
-        length: 0x1000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x4640c000, 0x1000));
 
     // ingress nic sendqueue device area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x4640d000,
-        length: 0x3000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x4640d000, 0x3000));
 
     // ingress nic sendqueue buffers
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46410000,
-        length: 0x400000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46410000, 0x400000));
 
     // egress nic receivequeue driver area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46814000,
-        length: 0x1000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46814000, 0x1000));
 
     // egress nic receivequeue device area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46815000,
-        length: 0x3000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46815000, 0x3000));
 
     // egress nic receivequeue buffers
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46818000,
-        length: 0x400000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46818000, 0x400000));
 
     // egress nic sendqueue driver area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46c1c000,
-        length: 0x1000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46c1c000, 0x1000));
 
     // egress nic sendqueue device area
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46c1d000,
-        length: 0x3000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46c1d000, 0x3000));
 
     // egress nic sendqueue buffers
-    memory.volatiles.push(VolatileMemoryRegion {
-        start_address: 0x46c20000,
-        length: 0x400000,
-    });
+    memory.volatiles.insert(VolatileMemoryRegion::new(0x46c20000, 0x400000));
 
     let mut rv32i_system_state = RV32i {
         state: rv32i::SystemState {
@@ -455,7 +405,8 @@ fn test_system_state_inner() {
 
     info!("({}ms) Creating symbolic volatile memory regions", begin.elapsed().as_millis());
     let base_symbol = continuing.scfia.new_bv_constrained(32, 0xff, 0xffff0000);
-    let sym_region = SymbolicVolatileMemoryRegion { base_symbol, length: 4096 };
+    let length = continuing.scfia.new_bv_concrete(4096, 32);
+    let sym_region = SymbolicVolatileMemoryRegion::new(base_symbol, length, SymbolicVolatilePolicy::FreshSymbol, &continuing.scfia);
 
     info!("({}ms) Writing symbolic pointers to descriptor table", begin.elapsed().as_millis());
     // TODO ensure is valid generalization
@@ -465,7 +416,7 @@ fn test_system_state_inner() {
         debug!("({}ms) overwriting 0x{:x}", begin.elapsed().as_millis(), ingress_receive_queue_pointer_address);
         continuing.memory.write_concrete(
             ingress_receive_queue_pointer_address as u64,
-            &sym_region.base_symbol,
+            &sym_region.base,
             32,
             &continuing.scfia,
             &mut None,
@@ -475,7 +426,7 @@ fn test_system_state_inner() {
         debug!("({}ms) overwriting 0x{:x}", begin.elapsed().as_millis(), egress_send_queue_pointer_address);
         continuing.memory.write_concrete(
             egress_send_queue_pointer_address as u64,
-            &sym_region.base_symbol,
+            &sym_region.base,
             32,
             &continuing.scfia,
             &mut None,
@@ -0,0 +1,1368 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use scfia_lib::memory::bulk::MAX_BULK_LENGTH;
+use scfia_lib::memory::findings::{MemoryAccess, MemoryFinding, MemoryFindingKind};
+use scfia_lib::memory::region_map::{Region, RegionMap};
+use scfia_lib::memory::regions::{
+    Endianness, PeripheralRegion, Permissions, StableMemoryRegion, SymbolicVolatileMemoryRegion, SymbolicVolatilePolicy, UninitializedReadPolicy,
+    VolatileMemoryRegion,
+};
+use scfia_lib::memory::Memory;
+use scfia_lib::models::riscv::rv32i::{self, RV32i, RV32iForkSink, RV32iScfiaComposition};
+use scfia_lib::report::{ExplorationReport, RegisterValue, TerminationReason};
+use scfia_lib::scfia::{DepthLimitPolicy, Scfia};
+use scfia_lib::values::active_value::ActiveValue;
+use scfia_lib::values::retired_value::RetiredValue;
+use scfia_lib::z3_handle::QUERY_CACHE_CAPACITY;
+
+fn new_rv32i(scfia: Rc<Scfia<RV32iScfiaComposition>>) -> RV32i {
+    RV32i {
+        state: rv32i::SystemState {
+            x0: scfia.new_bv_concrete(0b0, 32),
+            x1: scfia.new_bv_concrete(0b0, 32),
+            x2: scfia.new_bv_concrete(0b0, 32),
+            x3: scfia.new_bv_concrete(0b0, 32),
+            x4: scfia.new_bv_concrete(0b0, 32),
+            x5: scfia.new_bv_concrete(0b0, 32),
+            x6: scfia.new_bv_concrete(0b0, 32),
+            x7: scfia.new_bv_concrete(0b0, 32),
+            x8: scfia.new_bv_concrete(0b0, 32),
+            x9: scfia.new_bv_concrete(0b0, 32),
+            x10: scfia.new_bv_concrete(0b0, 32),
+            x11: scfia.new_bv_concrete(0b0, 32),
+            x12: scfia.new_bv_concrete(0b0, 32),
+            x13: scfia.new_bv_concrete(0b0, 32),
+            x14: scfia.new_bv_concrete(0b0, 32),
+            x15: scfia.new_bv_concrete(0b0, 32),
+            x16: scfia.new_bv_concrete(0b0, 32),
+            x17: scfia.new_bv_concrete(0b0, 32),
+            x18: scfia.new_bv_concrete(0b0, 32),
+            x19: scfia.new_bv_concrete(0b0, 32),
+            x20: scfia.new_bv_concrete(0b0, 32),
+            x21: scfia.new_bv_concrete(0b0, 32),
+            x22: scfia.new_bv_concrete(0b0, 32),
+            x23: scfia.new_bv_concrete(0b0, 32),
+            x24: scfia.new_bv_concrete(0b0, 32),
+            x25: scfia.new_bv_concrete(0b0, 32),
+            x26: scfia.new_bv_concrete(0b0, 32),
+            x27: scfia.new_bv_concrete(0b0, 32),
+            x28: scfia.new_bv_concrete(0b0, 32),
+            x29: scfia.new_bv_concrete(0b0, 32),
+            x30: scfia.new_bv_concrete(0b0, 32),
+            x31: scfia.new_bv_concrete(0b0, 32),
+            pc: scfia.new_bv_concrete(0b0, 32),
+        },
+        memory: Memory::default(),
+        scfia,
+    }
+}
+
+fn new_deep_chain(scfia: &Scfia<RV32iScfiaComposition>, step: &ActiveValue<RV32iScfiaComposition>, depth: usize) -> ActiveValue<RV32iScfiaComposition> {
+    let mut value = scfia.new_bv_symbol(32, None, &mut None, None);
+    for _ in 0..depth {
+        value = scfia.new_bv_add(&value, step, 32, None, &mut None, None);
+    }
+    value
+}
+
+#[test]
+fn test_deep_chain_on_default_stack() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    // A symbolic step, so constant reassociation does not flatten the chain
+    state.state.x2 = scfia.new_bv_symbol(32, None, &mut None, None);
+    state.state.x1 = new_deep_chain(&scfia, &state.state.x2, 100_000);
+    assert_eq!(state.state.x1.get_depth(), 100_001);
+
+    let (clone, _, _) = state.clone_model();
+    assert_eq!(clone.state.x1.get_depth(), 100_001);
+    drop(clone);
+
+    // The step inherits the retired chain, which must clone and drop just the same
+    state.state.x1 = scfia.new_bv_concrete(0, 32);
+    let (clone, _, _) = state.clone_model();
+    drop(clone);
+
+    state.state.x2 = scfia.new_bv_concrete(0, 32);
+    assert_eq!(scfia.z3.ast_refs.get(), 0);
+}
+
+#[test]
+fn test_exploration_report() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0, 4));
+    // beq x1, x0, 8
+    for (i, b) in [0x63, 0x84, 0x00, 0x00].iter().enumerate() {
+        state
+            .memory
+            .write(&scfia.new_bv_concrete(i as u128, 32), &scfia.new_bv_concrete(*b, 8), 8, &scfia, &mut None, &mut None);
+    }
+    state.state.x1 = scfia.new_bv_symbol(32, None, &mut None, None);
+
+    let mut report = ExplorationReport::default();
+    for successor in state.step_forking(None) {
+        report.push(successor.report(TerminationReason::StepLimit));
+    }
+    let json = report.to_json();
+    let states = json["states"].as_array().unwrap();
+    assert_eq!(states.len(), 2);
+
+    assert_eq!(states[0]["pc"], 8);
+    assert_eq!(states[0]["registers"]["x0"], 0);
+    assert!(states[0]["registers"]["x1"].is_string());
+    assert_eq!(states[0]["fork_history"][0]["pc"], 0);
+    assert_eq!(states[0]["fork_history"][0]["taken"], true);
+    assert_eq!(states[0]["termination"], "step_limit");
+
+    assert_eq!(states[1]["pc"], 4);
+    assert_eq!(states[1]["fork_history"][0]["pc"], 0);
+    assert_eq!(states[1]["fork_history"][0]["taken"], false);
+    assert!(states[1]["path_condition_size"].as_u64().unwrap() >= 1);
+}
+
+#[test]
+fn test_smtlib_export() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let symbol = scfia.new_bv_symbol(32, None, &mut None, None);
+    let limit = scfia.new_bv_concrete(16, 32);
+    let _assertion = scfia.new_bool_unsigned_less_than(&symbol, &limit, None, true, &mut None, None);
+    let one = scfia.new_bv_concrete(1, 32);
+    let value = scfia.new_bv_add(&symbol, &one, 32, None, &mut None, None);
+
+    let script = scfia.to_smtlib(&[&value, &limit]);
+    assert_eq!(script.matches("(declare-fun").count(), 1);
+    assert_eq!(script.matches("(assert").count(), 1);
+    assert!(script.contains("(check-sat)"));
+    assert!(script.contains("(get-value ((bvadd"));
+}
+
+#[test]
+fn test_counterexample() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let symbol = scfia.new_bv_symbol(32, None, &mut None, None);
+    let expected = scfia.new_bv_concrete(0x1234, 32);
+    let _assertion = scfia.new_bool_eq(&symbol, &expected, None, true, &mut None, None);
+    let one = scfia.new_bv_concrete(1, 32);
+    let value = scfia.new_bv_add(&symbol, &one, 32, None, &mut None, None);
+    let unconstrained = scfia.new_bv_symbol(32, None, &mut None, None);
+
+    let counterexample = scfia.get_counterexample().unwrap();
+    let symbol_id = symbol.get_z3_value().try_borrow().unwrap().id;
+    let unconstrained_id = unconstrained.get_z3_value().try_borrow().unwrap().id;
+    assert_eq!(counterexample.symbols.keys().copied().collect::<Vec<u64>>(), vec![symbol_id, unconstrained_id]);
+    assert_eq!(counterexample.symbols[&symbol_id], 0x1234);
+    assert_eq!(counterexample.eval(&symbol), 0x1234);
+    assert_eq!(counterexample.eval(&value), 0x1235);
+    assert_eq!(counterexample.eval(&one), 1);
+    // Symbols the solver leaves unconstrained are completed consistently
+    assert_eq!(counterexample.eval(&unconstrained), counterexample.symbols[&unconstrained_id]);
+
+    let impossible = scfia.new_bv_concrete(0x4321, 32);
+    let _contradiction = scfia.new_bool_eq(&symbol, &impossible, None, true, &mut None, None);
+    assert!(scfia.get_counterexample().is_none());
+}
+
+#[test]
+fn test_named_symbols() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.state.x1 = scfia.new_bv_named_symbol("packet[3]", 32, None, &mut None, None);
+    assert_eq!(state.state.x1.try_get_symbol_name().as_deref(), Some("packet[3]"));
+
+    // Retire the symbol while it is still referenced by the path condition
+    let expected = scfia.new_bv_concrete(7, 32);
+    let _assertion = scfia.new_bool_eq(&state.state.x1, &expected, None, true, &mut None, None);
+    let one = scfia.new_bv_concrete(1, 32);
+    state.state.x2 = scfia.new_bv_add(&state.state.x1, &one, 32, None, &mut None, None);
+    state.state.x1 = scfia.new_bv_concrete(0, 32);
+    let counterexample = scfia.get_counterexample().unwrap();
+    assert_eq!(counterexample.get_named("packet[3]"), Some(7));
+    assert_eq!(counterexample.eval(&state.state.x2), 8);
+
+    let (clone, _, _) = state.clone_model();
+    assert!(clone.scfia.to_smtlib(&[&clone.state.x2]).contains("(declare-fun |packet[3]| () (_ BitVec 32))"));
+
+    // Reusing a name, even in a clone, yields a distinct symbol
+    let byte = clone.scfia.new_bv_named_symbol("packet[3]", 8, None, &mut None, None);
+    assert_eq!(byte.try_get_symbol_name().as_deref(), Some("packet[3]!1"));
+    let script = clone.scfia.to_smtlib(&[&clone.state.x2, &byte]);
+    assert_eq!(script.matches("(declare-fun |packet[3]|").count(), 1);
+    assert_eq!(script.matches("(declare-fun |packet[3]!1|").count(), 1);
+}
+
+struct Uart {
+    base_address: u64,
+    received: u64,
+    sent: Vec<ActiveValue<RV32iScfiaComposition>>,
+}
+
+impl PeripheralRegion<RV32iScfiaComposition> for Uart {
+    fn start_address(&self) -> u64 {
+        self.base_address
+    }
+
+    fn length(&self) -> u64 {
+        12
+    }
+
+    fn read(
+        &mut self,
+        address: u64,
+        width: u32,
+        scfia: &Scfia<RV32iScfiaComposition>,
+        fork_sink: &mut Option<RV32iForkSink>,
+    ) -> ActiveValue<RV32iScfiaComposition> {
+        match address - self.base_address {
+            // Status register, the ready bit is always set
+            0 => {
+                let status = scfia.new_bv_symbol(width, None, fork_sink, None);
+                let ready_mask = scfia.new_bv_concrete(1, width);
+                let ready = scfia.new_bv_and(&status, &ready_mask, width, None, fork_sink, None);
+                scfia.new_bool_eq(&ready, &ready_mask, None, true, fork_sink, None);
+                status
+            }
+            // Receive FIFO
+            4 => {
+                let name = format!("uart_rx#{}", self.received);
+                self.received += 1;
+                scfia.new_bv_named_symbol(&name, width, None, fork_sink, None)
+            }
+            _ => scfia.new_bv_concrete(0, width),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _address: u64,
+        value: &ActiveValue<RV32iScfiaComposition>,
+        _width: u32,
+        _scfia: &Scfia<RV32iScfiaComposition>,
+        _fork_sink: &mut Option<RV32iForkSink>,
+    ) {
+        self.sent.push(value.clone())
+    }
+
+    fn clone_to_stdlib(
+        &self,
+        cloned_scfia: &Scfia<RV32iScfiaComposition>,
+        cloned_actives: &mut BTreeMap<u64, ActiveValue<RV32iScfiaComposition>>,
+        cloned_retired: &mut BTreeMap<u64, RetiredValue<RV32iScfiaComposition>>,
+    ) -> Box<dyn PeripheralRegion<RV32iScfiaComposition>> {
+        Box::new(Uart {
+            base_address: self.base_address,
+            received: self.received,
+            sent: self.sent.iter().map(|e| e.clone_to_stdlib(cloned_scfia, cloned_actives, cloned_retired)).collect(),
+        })
+    }
+}
+
+#[test]
+fn test_peripheral_region() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    // The peripheral shadows the stable region it is mapped into
+    state.memory.stables.insert(StableMemoryRegion::new(0x1000, 0x1000));
+    state.memory.peripherals.insert(Box::new(Uart {
+        base_address: 0x1000,
+        received: 0,
+        sent: vec![],
+    }));
+
+    let status_address = scfia.new_bv_concrete(0x1000, 32);
+    let rx_address = scfia.new_bv_concrete(0x1004, 32);
+    let tx_address = scfia.new_bv_concrete(0x1008, 32);
+    let status = state.memory.read(&status_address, 32, &scfia, &mut None, &mut None);
+    let first = state.memory.read(&rx_address, 8, &scfia, &mut None, &mut None);
+    let second = state.memory.read(&rx_address, 8, &scfia, &mut None, &mut None);
+    assert_eq!(first.try_get_symbol_name().as_deref(), Some("uart_rx#0"));
+    assert_eq!(second.try_get_symbol_name().as_deref(), Some("uart_rx#1"));
+    state.memory.write(&tx_address, &first, 8, &scfia, &mut None, &mut None);
+
+    let one = scfia.new_bv_concrete(1, 32);
+    let ready = scfia.new_bv_and(&status, &one, 32, None, &mut None, None);
+    let zero = scfia.new_bv_concrete(0, 32);
+    let not_ready = scfia.new_bool_eq(&ready, &zero, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&not_ready, &mut None));
+
+    let (mut clone, _, _) = state.clone_model();
+    let third = clone.memory.read(&rx_address, 8, &clone.scfia, &mut None, &mut None);
+    assert_eq!(third.try_get_symbol_name().as_deref(), Some("uart_rx#2"));
+}
+
+#[test]
+fn test_memory_permissions() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new_with_permissions(0x0, 0x100, Permissions::RX));
+    state.memory.stables.insert(StableMemoryRegion::new_with_permissions(0x1000, 0x100, Permissions::RW));
+    for address in 0x1000..0x1100 {
+        state
+            .memory
+            .write(&scfia.new_bv_concrete(address, 32), &scfia.new_bv_concrete(0, 8), 8, &scfia, &mut None, &mut None);
+    }
+    // addi x0, x0, 0
+    let nop = scfia.new_bv_concrete(0x00000013, 32);
+    state.memory.write(&scfia.new_bv_concrete(0x1000, 32), &nop, 32, &scfia, &mut None, &mut None);
+    assert!(state.memory.findings.is_empty());
+
+    // Writing into .text is reported, but still performed
+    state.memory.write(&scfia.new_bv_concrete(0x10, 32), &nop, 32, &scfia, &mut None, &mut None);
+    assert!(matches!(
+        state.memory.findings[..],
+        [MemoryFinding {
+            address: Some(0x10),
+            width: 32,
+            kind: MemoryFindingKind::PermissionViolation {
+                access: MemoryAccess::Write,
+                region_start_address: 0x0,
+            },
+            ..
+        }]
+    ));
+    let text = state.memory.read(&scfia.new_bv_concrete(0x10, 32), 32, &scfia, &mut None, &mut None);
+    assert_eq!(text.try_get_concrete(), Some(0x13));
+    assert_eq!(state.memory.findings.len(), 1);
+
+    // Executing from the stack is reported
+    state.state.pc = scfia.new_bv_concrete(0x1000, 32);
+    state.step(None);
+    assert_eq!(state.state.pc.try_get_concrete(), Some(0x1004));
+    assert!(matches!(
+        state.memory.findings[1],
+        MemoryFinding {
+            pc: Some(0x1000),
+            address: Some(0x1000),
+            kind: MemoryFindingKind::PermissionViolation {
+                access: MemoryAccess::Execute,
+                ..
+            },
+            ..
+        }
+    ));
+
+    // Symbolic addresses which may hit the stack are reported as well
+    let offset = scfia.new_bv_symbol(32, None, &mut None, None);
+    let mask = scfia.new_bv_concrete(0xc, 32);
+    let offset = scfia.new_bv_and(&offset, &mask, 32, None, &mut None, None);
+    let base = scfia.new_bv_concrete(0x1010, 32);
+    let address = scfia.new_bv_add(&base, &offset, 32, None, &mut None, None);
+    state.memory.fetch(&address, 32, &scfia, &mut None, &mut None);
+    assert!(matches!(
+        state.memory.findings[2],
+        MemoryFinding {
+            address: None,
+            kind: MemoryFindingKind::PermissionViolation {
+                access: MemoryAccess::Execute,
+                region_start_address: 0x1000,
+            },
+            ..
+        }
+    ));
+    assert_eq!(state.memory.findings.len(), 3);
+
+    // An access which starts just before a region still overlaps it
+    let read_only = StableMemoryRegion::new_with_permissions(0x1100, 0x100, Permissions::R);
+    state.memory.stables.insert(read_only);
+    state.memory.write(&scfia.new_bv_concrete(0x10fe, 32), &nop, 32, &scfia, &mut None, &mut None);
+    assert!(matches!(
+        state.memory.findings[3],
+        MemoryFinding {
+            address: Some(0x10fe),
+            kind: MemoryFindingKind::PermissionViolation {
+                access: MemoryAccess::Write,
+                region_start_address: 0x1100,
+            },
+            ..
+        }
+    ));
+
+    // Regions at the very top of the address space do not overflow
+    let top = StableMemoryRegion::new_with_permissions(0xffff_ff00, 0x100, Permissions::R);
+    state.memory.stables.insert(top);
+    let address = scfia.new_bv_concrete(0xffff_fffc, 32);
+    state.memory.write(&address, &nop, 32, &scfia, &mut None, &mut None);
+    assert_eq!(state.memory.findings.len(), 5);
+
+    // Volatile and symbolic volatile regions carry permissions as well
+    let read_only = VolatileMemoryRegion::new_with_permissions(0x2000, 0x100, Permissions::R);
+    state.memory.volatiles.insert(read_only);
+    state.memory.write(&scfia.new_bv_concrete(0x2010, 32), &nop, 32, &scfia, &mut None, &mut None);
+    let base = scfia.new_bv_concrete(0x3000, 32);
+    let mut dma = SymbolicVolatileMemoryRegion::new(base, scfia.new_bv_concrete(0x100, 32), SymbolicVolatilePolicy::Shadow, &scfia);
+    dma.permissions = Permissions::R;
+    state.memory.symbolic_volatiles.push(dma);
+    let selector = scfia.new_bv_symbol(32, None, &mut None, None);
+    let offset = scfia.new_bv_and(&selector, &scfia.new_bv_concrete(0xf0, 32), 32, None, &mut None, None);
+    let address = scfia.new_bv_add(&offset, &scfia.new_bv_concrete(0x3000, 32), 32, None, &mut None, None);
+    state.memory.write(&address, &nop, 32, &scfia, &mut None, &mut None);
+    let starts: Vec<_> = state.memory.findings[5..]
+        .iter()
+        .map(|finding| match finding.kind {
+            MemoryFindingKind::PermissionViolation { region_start_address, .. } => region_start_address,
+            _ => panic!(),
+        })
+        .collect();
+    assert_eq!(starts, vec![0x2000, 0x3000]);
+}
+
+#[test]
+fn test_uninitialized_reads() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x100));
+    state.memory.stables.insert(StableMemoryRegion::new(0x1000, 0x100));
+    // lw x2, 0(x1)
+    let lw = scfia.new_bv_concrete(0x0000a103, 32);
+    state.memory.write(&scfia.new_bv_concrete(0x0, 32), &lw, 32, &scfia, &mut None, &mut None);
+    state.state.x1 = scfia.new_bv_concrete(0x1000, 32);
+
+    let (mut zero, _, _) = state.clone_model();
+    zero.memory.uninitialized_read_policy = UninitializedReadPolicy::Zero;
+    zero.step(None);
+    assert_eq!(zero.state.x2.try_get_concrete(), Some(0));
+    assert!(zero.memory.findings.is_empty());
+
+    state.memory.uninitialized_read_policy = UninitializedReadPolicy::Finding;
+    state.step(None);
+    assert!(state.state.x2.try_get_concrete().is_none());
+    assert!(matches!(
+        state.memory.findings[..],
+        [MemoryFinding {
+            pc: Some(0x0),
+            address: Some(0x1000),
+            width: 32,
+            kind: MemoryFindingKind::UninitializedRead,
+        }]
+    ));
+
+    let json = state.report(TerminationReason::StepLimit).to_json();
+    assert_eq!(json["findings"][0]["kind"], "uninitialized_read");
+    assert_eq!(json["findings"][0]["pc"], 0);
+    assert_eq!(json["findings"][0]["address"], 0x1000);
+}
+
+#[test]
+fn test_region_map_lookup() {
+    let mut volatiles = RegionMap::new();
+    volatiles.insert(VolatileMemoryRegion::new(0x2000, 0x100));
+    volatiles.insert(VolatileMemoryRegion::new(0x1000, 0x1000));
+    assert_eq!(volatiles.len(), 2);
+    assert_eq!(volatiles.get(0x1000).map(|e| e.start_address()), Some(0x1000));
+    assert_eq!(volatiles.get(0x1fff).map(|e| e.start_address()), Some(0x1000));
+    assert_eq!(volatiles.get(0x2000).map(|e| e.start_address()), Some(0x2000));
+    assert!(volatiles.get(0xfff).is_none());
+    assert!(volatiles.get(0x2100).is_none());
+    assert_eq!(volatiles.iter().map(|e| e.start_address).collect::<Vec<_>>(), vec![0x1000, 0x2000]);
+}
+
+#[test]
+fn test_volatile_inside_stable() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x2000));
+    state.memory.volatiles.insert(VolatileMemoryRegion::new(0x1000, 0x100));
+
+    // Reads see the volatile region, writes still reach the stable region underneath
+    let address = scfia.new_bv_concrete(0x1000, 32);
+    state.memory.write(&address, &scfia.new_bv_concrete(0x42, 32), 32, &scfia, &mut None, &mut None);
+    assert!(state.memory.read(&address, 32, &scfia, &mut None, &mut None).try_get_concrete().is_none());
+    state.memory.volatiles = RegionMap::new();
+    assert_eq!(state.memory.read(&address, 32, &scfia, &mut None, &mut None).try_get_concrete(), Some(0x42));
+}
+
+#[test]
+fn test_region_map_top_of_address_space() {
+    let mut volatiles = RegionMap::new();
+    volatiles.insert(VolatileMemoryRegion::new(0xffff_ffff_ffff_f000, 0x1000));
+    assert_eq!(volatiles.get(u64::MAX).map(|e| e.start_address()), Some(0xffff_ffff_ffff_f000));
+    assert!(volatiles.get(0xffff_ffff_ffff_efff).is_none());
+}
+
+#[test]
+#[should_panic]
+fn test_region_map_rejects_overflowing_end() {
+    let mut volatiles = RegionMap::new();
+    volatiles.insert(VolatileMemoryRegion::new(0xffff_ffff_ffff_f000, 0x1001));
+}
+
+#[test]
+#[should_panic]
+fn test_region_map_rejects_overlap() {
+    let mut stables: RegionMap<StableMemoryRegion<RV32iScfiaComposition>> = RegionMap::new();
+    stables.insert(StableMemoryRegion::new(0x1000, 0x100));
+    stables.insert(StableMemoryRegion::new(0x10ff, 0x100));
+}
+
+#[test]
+fn test_copy_on_write_pages() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x4000));
+    for address in (0..0x4000).step_by(4) {
+        state.memory.write(&scfia.new_bv_concrete(address, 32), &scfia.new_bv_concrete(address, 32), 32, &scfia, &mut None, &mut None);
+    }
+    let symbol = scfia.new_bv_symbol(32, None, &mut None, None);
+    state.memory.write(&scfia.new_bv_concrete(0x3000, 32), &symbol, 32, &scfia, &mut None, &mut None);
+
+    let (mut clone, _, _) = state.clone_model();
+    assert_eq!(state.memory.stables.get(0).unwrap().memory.shared_pages(), 4);
+
+    // Writing to a shared page copies it, leaving the other state untouched
+    let address = scfia.new_bv_concrete(0x1000, 32);
+    clone.memory.write(&address, &clone.scfia.new_bv_concrete(0xdeadbeef, 32), 32, &clone.scfia, &mut None, &mut None);
+    assert_eq!(state.memory.stables.get(0).unwrap().memory.shared_pages(), 3);
+    let original = state.memory.read(&address, 32, &scfia, &mut None, &mut None);
+    let cloned = clone.memory.read(&address, 32, &clone.scfia, &mut None, &mut None);
+    assert_eq!(original.try_get_concrete(), Some(0x1000));
+    assert_eq!(cloned.try_get_concrete(), Some(0xdeadbeef));
+
+    // Symbolic bytes are translated into the clone's Scfia
+    let cloned_symbol = clone.memory.read(&clone.scfia.new_bv_concrete(0x3000, 32), 32, &clone.scfia, &mut None, &mut None);
+    assert!(cloned_symbol.try_get_concrete().is_none());
+    assert_eq!(state.memory.stables.get(0).unwrap().memory.len(), 0x4000);
+}
+
+#[test]
+fn test_concrete_read_fast_path() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x2000));
+    state.memory.write(&scfia.new_bv_concrete(0xffe, 32), &scfia.new_bv_concrete(0x11223344, 32), 32, &scfia, &mut None, &mut None);
+
+    // Unaligned across a page boundary, without building any expressions
+    let value = state.memory.read(&scfia.new_bv_concrete(0xffe, 32), 32, &scfia, &mut None, &mut None);
+    assert!(matches!(value, ActiveValue::BVConcrete(0x11223344, 32)));
+    let value = state.memory.read(&scfia.new_bv_concrete(0xfff, 32), 16, &scfia, &mut None, &mut None);
+    assert!(matches!(value, ActiveValue::BVConcrete(0x2233, 16)));
+
+    // Overwriting a byte with a symbol falls back to concatenation
+    let symbol = scfia.new_bv_symbol(8, None, &mut None, None);
+    state.memory.write(&scfia.new_bv_concrete(0x1000, 32), &symbol, 8, &scfia, &mut None, &mut None);
+    let value = state.memory.read(&scfia.new_bv_concrete(0xffe, 32), 32, &scfia, &mut None, &mut None);
+    assert!(matches!(value, ActiveValue::Expression(_)));
+    let low = scfia.new_bv_slice(&value, 15, 0, None, &mut None, None);
+    let low_matches = scfia.new_bool_eq(&low, &scfia.new_bv_concrete(0x3344, 16), None, false, &mut None, None);
+    let low_differs = scfia.new_bool_not(&low_matches, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&low_differs, &mut None));
+    assert_eq!(state.memory.stables.get(0).unwrap().memory.len(), 4);
+}
+
+#[test]
+fn test_big_endian_region() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    let mut region = StableMemoryRegion::new(0x0, 0x100);
+    region.endianness = Endianness::Big;
+    state.memory.stables.insert(region);
+    let address = scfia.new_bv_concrete(0x10, 32);
+    state.memory.write(&address, &scfia.new_bv_concrete(0x11223344, 32), 32, &scfia, &mut None, &mut None);
+    let first = state.memory.read(&address, 8, &scfia, &mut None, &mut None);
+    assert_eq!(first.try_get_concrete(), Some(0x11));
+    let word = state.memory.read(&address, 32, &scfia, &mut None, &mut None);
+    assert_eq!(word.try_get_concrete(), Some(0x11223344));
+    let swapped = scfia.new_bv_byte_reverse(&word, 32, &mut None, None);
+    assert_eq!(swapped.try_get_concrete(), Some(0x44332211));
+
+    // Symbolic values are stored most significant byte first, too
+    let symbol = scfia.new_bv_symbol(16, None, &mut None, None);
+    let address = scfia.new_bv_concrete(0x20, 32);
+    state.memory.write(&address, &symbol, 16, &scfia, &mut None, &mut None);
+    let high = state.memory.read(&address, 8, &scfia, &mut None, &mut None);
+    let expected_high = scfia.new_bv_slice(&symbol, 15, 8, None, &mut None, None);
+    let high_matches = scfia.new_bool_eq(&high, &expected_high, None, false, &mut None, None);
+    let high_differs = scfia.new_bool_not(&high_matches, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&high_differs, &mut None));
+
+    let reversed = scfia.new_bv_byte_reverse(&symbol, 16, &mut None, None);
+    let twice = scfia.new_bv_byte_reverse(&reversed, 16, &mut None, None);
+    let twice_matches = scfia.new_bool_eq(&twice, &symbol, None, false, &mut None, None);
+    let twice_differs = scfia.new_bool_not(&twice_matches, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&twice_differs, &mut None));
+}
+
+/// Summarises `memcpy(a0, a1, a2)` and returns to `ra`.
+fn memcpy_hook(state: &mut RV32i) {
+    let (destination, source, length) = (state.state.x10.clone(), state.state.x11.clone(), state.state.x12.clone());
+    state.memory.copy(&destination, &source, &length, &state.scfia, &mut None);
+    state.state.pc = state.state.x1.clone();
+}
+
+/// Summarises `memcmp(a0, a1, a2)` and returns to `ra`.
+fn memcmp_hook(state: &mut RV32i) {
+    let (lhs, rhs, length) = (state.state.x10.clone(), state.state.x11.clone(), state.state.x12.clone());
+    state.state.x10 = state.memory.compare(&lhs, &rhs, &length, 32, &state.scfia, &mut None);
+    state.state.pc = state.state.x1.clone();
+}
+
+fn assert_forced(scfia: &Scfia<RV32iScfiaComposition>, value: &ActiveValue<RV32iScfiaComposition>, expected: u128, width: u32) {
+    let matches = scfia.new_bool_eq(value, &scfia.new_bv_concrete(expected, width), None, false, &mut None, None);
+    let differs = scfia.new_bool_not(&matches, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&differs, &mut None));
+}
+
+#[test]
+fn test_symbolic_length_bulk_operations() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x1000));
+    state.memory.write(&scfia.new_bv_concrete(0x100, 32), &scfia.new_bv_concrete(0x44332211, 32), 32, &scfia, &mut None, &mut None);
+    state.memory.fill(&scfia.new_bv_concrete(0x200, 32), &scfia.new_bv_concrete(0xaa, 8), &scfia.new_bv_concrete(4, 32), &scfia, &mut None);
+
+    // length < 5
+    let length = scfia.new_bv_symbol(32, None, &mut None, None);
+    scfia.new_bool_unsigned_less_than(&length, &scfia.new_bv_concrete(5, 32), None, true, &mut None, None);
+    state.state.x1 = scfia.new_bv_concrete(0x40, 32);
+    state.state.x10 = scfia.new_bv_concrete(0x200, 32);
+    state.state.x11 = scfia.new_bv_concrete(0x100, 32);
+    state.state.x12 = length.clone();
+    memcpy_hook(&mut state);
+    assert_eq!(state.state.pc.try_get_concrete(), Some(0x40));
+    let copied = state.memory.read(&scfia.new_bv_concrete(0x200, 32), 32, &scfia, &mut None, &mut None);
+    assert!(copied.try_get_concrete().is_none());
+
+    state.state.x10 = scfia.new_bv_concrete(0x200, 32);
+    state.state.x11 = scfia.new_bv_concrete(0x100, 32);
+    memcmp_hook(&mut state);
+    let compared = state.state.x10.clone();
+
+    // Both summaries stay consistent with the length chosen later on
+    let (mut clone, _, _) = state.clone_model();
+    scfia.new_bool_eq(&length, &scfia.new_bv_concrete(2, 32), None, true, &mut None, None);
+    assert_forced(&scfia, &copied, 0xaaaa2211, 32);
+    assert_forced(&scfia, &compared, 0, 32);
+
+    // memcmp on uninitialized bytes yields a symbolic result, but with length 0 it is always equal
+    let uninitialized = clone.memory.compare(
+        &clone.scfia.new_bv_concrete(0x300, 32),
+        &clone.scfia.new_bv_concrete(0x400, 32),
+        &clone.scfia.new_bv_concrete(0, 32),
+        32,
+        &clone.scfia,
+        &mut None,
+    );
+    assert_eq!(uninitialized.try_get_concrete(), Some(0));
+    let cloned_length = clone.state.x12.clone();
+    clone.scfia.new_bool_eq(&cloned_length, &clone.scfia.new_bv_concrete(4, 32), None, true, &mut None, None);
+    let copied = clone.memory.read(&clone.scfia.new_bv_concrete(0x200, 32), 32, &clone.scfia, &mut None, &mut None);
+    assert_forced(&clone.scfia, &copied, 0x44332211, 32);
+    clone.state.x10 = clone.scfia.new_bv_concrete(0x100, 32);
+    clone.state.x11 = clone.scfia.new_bv_concrete(0x200, 32);
+    clone.state.x12 = clone.scfia.new_bv_concrete(4, 32);
+    clone.memory.fill(&clone.scfia.new_bv_concrete(0x202, 32), &clone.scfia.new_bv_concrete(0x00, 8), &cloned_length, &clone.scfia, &mut None);
+    memcmp_hook(&mut clone);
+    assert_forced(&clone.scfia, &clone.state.x10, 1, 32);
+}
+
+#[test]
+fn test_bulk_operation_findings() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x20000));
+    let byte = scfia.new_bv_concrete(0xaa, 8);
+
+    // An address with two interpretations is reported instead of resolved
+    let selector = scfia.new_bv_symbol(32, None, &mut None, None);
+    let masked = scfia.new_bv_and(&selector, &scfia.new_bv_concrete(0x10, 32), 32, None, &mut None, None);
+    let ambiguous = scfia.new_bv_add(&masked, &scfia.new_bv_concrete(0x100, 32), 32, None, &mut None, None);
+    let four = scfia.new_bv_concrete(4, 32);
+    state.memory.fill(&ambiguous, &byte, &four, &scfia, &mut None);
+    let other = scfia.new_bv_concrete(0x200, 32);
+    let compared = state.memory.compare(&ambiguous, &other, &four, 32, &scfia, &mut None);
+    assert!(compared.try_get_concrete().is_none());
+    let findings = &state.memory.findings;
+    assert_eq!(findings.len(), 2);
+    assert!(findings.iter().all(|finding| finding.kind == MemoryFindingKind::AmbiguousBulkAddress));
+
+    // A length which may exceed the cap only touches the first MAX_BULK_LENGTH bytes
+    let length = scfia.new_bv_symbol(64, None, &mut None, None);
+    state.memory.fill(&scfia.new_bv_concrete(0x0, 32), &byte, &length, &scfia, &mut None);
+    assert!(matches!(
+        state.memory.findings[2],
+        MemoryFinding {
+            address: Some(0x0),
+            kind: MemoryFindingKind::BulkLengthCapped { cap: MAX_BULK_LENGTH },
+            ..
+        }
+    ));
+    let after_cap = scfia.new_bv_concrete(MAX_BULK_LENGTH.into(), 32);
+    let after_cap = state.memory.read(&after_cap, 8, &scfia, &mut None, &mut None);
+    assert!(after_cap.try_get_concrete().is_none());
+
+    // Concrete lengths are capped as well, even if they do not fit in 64 bits
+    let length = scfia.new_bv_concrete(1 << 64, 72);
+    state.memory.fill(&scfia.new_bv_concrete(0x10000, 32), &byte, &length, &scfia, &mut None);
+    assert_eq!(state.memory.findings.len(), 4);
+    let capped = &state.memory.findings[3];
+    assert_eq!(capped.kind, MemoryFindingKind::BulkLengthCapped { cap: MAX_BULK_LENGTH });
+
+    // Bytes which may lie past a symbolic length are not reported as uninitialized reads
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x100));
+    state.memory.uninitialized_read_policy = UninitializedReadPolicy::Finding;
+    let length = scfia.new_bv_symbol(32, None, &mut None, None);
+    scfia.new_bool_unsigned_less_than(&length, &scfia.new_bv_concrete(8, 32), None, true, &mut None, None);
+    let (destination, source) = (scfia.new_bv_concrete(0x20, 32), scfia.new_bv_concrete(0x40, 32));
+    state.memory.fill(&destination, &byte, &length, &scfia, &mut None);
+    state.memory.copy(&destination, &source, &length, &scfia, &mut None);
+    assert!(state.memory.findings.is_empty());
+    state.memory.copy(&destination, &source, &four, &scfia, &mut None);
+    assert_eq!(state.memory.findings.len(), 4);
+    assert!(state.memory.findings.iter().all(|finding| finding.kind == MemoryFindingKind::UninitializedRead));
+}
+
+#[test]
+fn test_symbolic_volatile_policies() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x10000));
+
+    // A DMA buffer at a concrete base inside SRAM, whose length comes from a device register
+    let length = scfia.new_bv_symbol(32, None, &mut None, None);
+    scfia.new_bool_unsigned_less_than(&scfia.new_bv_concrete(0x10, 32), &length, None, true, &mut None, None);
+    let base = scfia.new_bv_concrete(0x1000, 32);
+    let region = SymbolicVolatileMemoryRegion::new(base, length, SymbolicVolatilePolicy::ConstantSymbol, &scfia);
+    assert_eq!(region.min_length, 0x11);
+    state.memory.symbolic_volatiles.push(region);
+    let address = scfia.new_bv_concrete(0x1004, 32);
+    let first = state.memory.read(&address, 32, &scfia, &mut None, &mut None);
+    state.memory.write(&address, &scfia.new_bv_concrete(0, 32), 32, &scfia, &mut None, &mut None);
+    let second = state.memory.read(&address, 32, &scfia, &mut None, &mut None);
+    assert!(first.try_get_concrete().is_none());
+    let same = scfia.new_bool_eq(&first, &second, None, false, &mut None, None);
+    let differs = scfia.new_bool_not(&same, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&differs, &mut None));
+
+    // A shadowed buffer at a symbolic base keeps what was written through symbolic addresses
+    let base = scfia.new_bv_constrained(32, 0xff, 0x8000);
+    let length = scfia.new_bv_concrete(0x100, 32);
+    let region = SymbolicVolatileMemoryRegion::new(base.clone(), length, SymbolicVolatilePolicy::Shadow, &scfia);
+    state.memory.symbolic_volatiles.push(region);
+    let address = scfia.new_bv_add(&base, &scfia.new_bv_concrete(8, 32), 32, None, &mut None, None);
+    state.memory.write(&address, &scfia.new_bv_concrete(0xcafe, 16), 16, &scfia, &mut None, &mut None);
+    let (mut clone, _, _) = state.clone_model();
+    let cloned_base = clone.memory.symbolic_volatiles[1].base.clone();
+    let address = clone.scfia.new_bv_add(&cloned_base, &clone.scfia.new_bv_concrete(8, 32), 32, None, &mut None, None);
+    let value = clone.memory.read(&address, 16, &clone.scfia, &mut None, &mut None);
+    assert_eq!(value.try_get_concrete(), Some(0xcafe));
+
+    // A write to an ambiguous offset may have hit any byte, so the shadow forgets what it held
+    let selector = scfia.new_bv_symbol(32, None, &mut None, None);
+    let offset = scfia.new_bv_and(&selector, &scfia.new_bv_concrete(0xc, 32), 32, None, &mut None, None);
+    let ambiguous = scfia.new_bv_add(&base, &offset, 32, None, &mut None, None);
+    state.memory.write(&ambiguous, &scfia.new_bv_concrete(0, 16), 16, &scfia, &mut None, &mut None);
+    let address = scfia.new_bv_add(&base, &scfia.new_bv_concrete(8, 32), 32, None, &mut None, None);
+    let value = state.memory.read(&address, 16, &scfia, &mut None, &mut None);
+    assert!(value.try_get_concrete().is_none());
+
+    // Reads from fresh-symbol regions yield a new symbol every time
+    let (base, length) = (scfia.new_bv_concrete(0x2000, 32), scfia.new_bv_concrete(0x10, 32));
+    let region = SymbolicVolatileMemoryRegion::new(base, length, SymbolicVolatilePolicy::FreshSymbol, &scfia);
+    state.memory.symbolic_volatiles.push(region);
+    let address = scfia.new_bv_concrete(0x2000, 32);
+    let first = state.memory.read(&address, 8, &scfia, &mut None, &mut None);
+    let second = state.memory.read(&address, 8, &scfia, &mut None, &mut None);
+    assert!(first.try_get_concrete().is_none());
+    assert_ne!(first.get_z3_value().try_borrow().unwrap().id, second.get_z3_value().try_borrow().unwrap().id);
+
+    // A region may wrap around the end of the address space
+    let (base, length) = (scfia.new_bv_concrete(0xffff_fff0, 32), scfia.new_bv_concrete(0x20, 32));
+    let region = SymbolicVolatileMemoryRegion::new(base, length, SymbolicVolatilePolicy::ConstantSymbol, &scfia);
+    state.memory.symbolic_volatiles.push(region);
+    let address = scfia.new_bv_concrete(0x5, 32);
+    let first = state.memory.read(&address, 8, &scfia, &mut None, &mut None);
+    let second = state.memory.read(&address, 8, &scfia, &mut None, &mut None);
+    assert_eq!(first.get_z3_value().try_borrow().unwrap().id, second.get_z3_value().try_borrow().unwrap().id);
+
+    // Bases and lengths of another width than the addresses are resized, and wide lengths are not truncated
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x10000));
+    let base = scfia.new_bv_symbol(64, None, &mut None, None);
+    scfia.new_bool_eq(&base, &scfia.new_bv_concrete(0x3000, 64), None, true, &mut None, None);
+    let length = scfia.new_bv_symbol(64, None, &mut None, None);
+    scfia.new_bool_unsigned_less_than(&scfia.new_bv_concrete(1 << 32, 64), &length, None, true, &mut None, None);
+    let region = SymbolicVolatileMemoryRegion::new(base, length, SymbolicVolatilePolicy::Shadow, &scfia);
+    state.memory.symbolic_volatiles.push(region);
+    let address = scfia.new_bv_symbol(32, None, &mut None, None);
+    scfia.new_bool_eq(&address, &scfia.new_bv_concrete(0x3004, 32), None, true, &mut None, None);
+    let cafe = scfia.new_bv_concrete(0xcafe, 16);
+    state.memory.write(&address, &cafe, 16, &scfia, &mut None, &mut None);
+    let value = state.memory.read(&address, 16, &scfia, &mut None, &mut None);
+    assert_eq!(value.try_get_concrete(), Some(0xcafe));
+    let stable = state.memory.read(&scfia.new_bv_concrete(0x3004, 32), 16, &scfia, &mut None, &mut None);
+    assert!(stable.try_get_concrete().is_none());
+}
+
+#[test]
+fn test_write_log_and_diff() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let mut state = new_rv32i(scfia.clone());
+    state.memory.stables.insert(StableMemoryRegion::new(0x0, 0x100));
+    state.memory.stables.insert(StableMemoryRegion::new(0x1000, 0x100));
+    // sw x2, 0(x1)
+    let sw = scfia.new_bv_concrete(0x0020a023, 32);
+    state.memory.write(&scfia.new_bv_concrete(0x0, 32), &sw, 32, &scfia, &mut None, &mut None);
+    state.memory.write(&scfia.new_bv_concrete(0x1000, 32), &scfia.new_bv_concrete(0x11223344, 32), 32, &scfia, &mut None, &mut None);
+    state.state.x1 = scfia.new_bv_concrete(0x1002, 32);
+    state.state.x2 = scfia.new_bv_symbol(32, None, &mut None, None);
+    state.memory.write_log = Some(vec![]);
+
+    let (before, _, _) = state.clone_model();
+    state.step(None);
+    let write_log = state.memory.write_log.as_ref().unwrap();
+    assert_eq!(write_log.len(), 1);
+    assert_eq!(write_log[0].pc, Some(0x0));
+    assert_eq!(write_log[0].address.try_get_concrete(), Some(0x1002));
+    assert_eq!(write_log[0].width, 32);
+
+    let diff = before.diff(&state);
+    assert_eq!(diff.registers.len(), 1);
+    assert_eq!(diff.registers[0].0, "pc");
+    assert_eq!(diff.memory.iter().map(|(address, _)| *address).collect::<Vec<_>>(), vec![0x1002, 0x1003, 0x1004, 0x1005]);
+    let json = diff.to_json();
+    assert_eq!(json["registers"]["pc"]["before"], 0);
+    assert_eq!(json["registers"]["pc"]["after"], 4);
+    assert_eq!(json["memory"][0]["before"], 0x22);
+    assert_eq!(json["memory"][2]["before"], serde_json::Value::Null);
+    assert!(json["memory"][0]["after"].is_string());
+}
+
+#[test]
+fn test_algebraic_rewrites() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let x = scfia.new_bv_symbol(32, None, &mut None, None);
+
+    assert!(scfia.new_bv_add(&x, &scfia.new_bv_concrete(0, 32), 32, None, &mut None, None).is_same_value(&x));
+    assert!(scfia.new_bv_and(&x, &scfia.new_bv_concrete(0xffffffff, 32), 32, None, &mut None, None).is_same_value(&x));
+    let not = scfia.new_bv_not(&x, 32, None, &mut None, None);
+    assert!(scfia.new_bv_not(&not, 32, None, &mut None, None).is_same_value(&x));
+
+    // Constant reassociation keeps a counter at depth 2
+    let mut counter = x.clone();
+    for _ in 0..100 {
+        counter = scfia.new_bv_add(&counter, &scfia.new_bv_concrete(1, 32), 32, None, &mut None, None);
+    }
+    assert_eq!(counter.get_depth(), x.get_depth() + 1);
+    let counter = scfia.new_bv_sub(&counter, &scfia.new_bv_concrete(100, 32), 32, None, &mut None, None);
+    assert!(counter.is_same_value(&x));
+
+    // Slices of concatenations and concatenations of adjacent slices
+    let y = scfia.new_bv_symbol(8, None, &mut None, None);
+    let zero_extended = scfia.new_bv_concat(&scfia.new_bv_concrete(0, 24), &y, 32, None, &mut None, None);
+    assert!(scfia.new_bv_slice(&zero_extended, 7, 0, None, &mut None, None).is_same_value(&y));
+    let mut memory = Memory::new();
+    memory.stables.insert(StableMemoryRegion::new(0x1000, 0x100));
+    memory.write(&scfia.new_bv_concrete(0x1000, 32), &x, 32, &scfia, &mut None, &mut None);
+    let read = memory.read(&scfia.new_bv_concrete(0x1000, 32), 32, &scfia, &mut None, &mut None);
+    assert!(read.is_same_value(&x));
+
+    // The sign bit of a zero extension is concrete, so sign extending it is a zero extension
+    let sign_extended = scfia.new_bv_sign_extend(&zero_extended, 32, 64, None, &mut None, None);
+    assert_eq!(sign_extended.get_depth(), zero_extended.get_depth());
+    let differs = scfia.new_bool_eq(
+        &scfia.new_bv_slice(&sign_extended, 63, 8, None, &mut None, None),
+        &scfia.new_bv_concrete(0, 56),
+        None,
+        false,
+        &mut None,
+        None,
+    );
+    let differs = scfia.new_bool_not(&differs, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&differs, &mut None));
+}
+
+#[test]
+fn test_hash_consing() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    scfia.enable_hash_consing();
+    let x = scfia.new_bv_symbol(32, None, &mut None, None);
+    let y = scfia.new_bv_symbol(32, None, &mut None, None);
+    let one = scfia.new_bv_concrete(1, 32);
+
+    let sum = scfia.new_bv_add(&x, &y, 32, None, &mut None, None);
+    let incremented = scfia.new_bv_add(&x, &one, 32, None, &mut None, None);
+    assert!(!scfia.new_bv_add(&y, &x, 32, None, &mut None, None).is_same_value(&sum));
+    let ast_refs = scfia.z3.ast_refs.get();
+    let same_sum = scfia.new_bv_add(&x, &y, 32, None, &mut None, None);
+    let same_incremented = scfia.new_bv_add(&x, &one, 32, None, &mut None, None);
+    assert!(same_sum.is_same_value(&sum));
+    assert!(same_incremented.is_same_value(&incremented));
+    assert_eq!(scfia.z3.ast_refs.get(), ast_refs);
+
+    // Clones keep sharing, and cache entries die with their values
+    let mut state = new_rv32i(scfia.clone());
+    state.state.x1 = sum.clone();
+    let (clone, _, _) = state.clone_model();
+    assert!(clone.scfia.hash_consing_enabled());
+    assert!(clone.scfia.new_bv_add(&clone.state.x1, &clone.state.x1, 32, None, &mut None, None).get_depth() > 1);
+    drop(clone);
+    drop((state, sum, incremented, same_sum, same_incremented));
+    assert_eq!(scfia.structural_cache_len(), 0);
+    drop((x, y));
+    assert_eq!(scfia.z3.ast_refs.get(), 0);
+}
+
+#[test]
+fn test_bool_connectives() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let x = scfia.new_bv_symbol(32, None, &mut None, None);
+    let below_5 = scfia.new_bool_unsigned_less_than(&x, &scfia.new_bv_concrete(5, 32), None, false, &mut None, None);
+    let below_10 = scfia.new_bool_unsigned_less_than(&x, &scfia.new_bv_concrete(10, 32), None, false, &mut None, None);
+
+    // Concrete operands fold
+    let t = ActiveValue::BoolConcrete(true);
+    let f = ActiveValue::BoolConcrete(false);
+    assert!(scfia.new_bool_and(&t, &below_5, None, false, &mut None, None).is_same_value(&below_5));
+    assert!(scfia.new_bool_and(&below_5, &f, None, false, &mut None, None).is_same_value(&f));
+    assert!(scfia.new_bool_or(&t, &below_5, None, false, &mut None, None).is_same_value(&t));
+    assert!(scfia.new_bool_implies(&f, &below_5, None, false, &mut None, None).is_same_value(&t));
+    assert!(scfia.new_bool_xor(&t, &f, None, false, &mut None, None).is_same_value(&t));
+
+    // Symbolic operands keep their semantics
+    let implies = scfia.new_bool_implies(&below_5, &below_10, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&scfia.new_bool_not(&implies, None, false, &mut None, None), &mut None));
+    let and = scfia.new_bool_and(&below_5, &below_10, None, false, &mut None, None);
+    let xor = scfia.new_bool_xor(&below_5, &below_10, None, false, &mut None, None);
+    let or = scfia.new_bool_or(&and, &xor, None, false, &mut None, None);
+    let or_differs = scfia.new_bool_xor(&or, &below_10, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&or_differs, &mut None));
+
+    // Compound conditions survive cloning and retirement
+    let mut state = new_rv32i(scfia.clone());
+    state.state.x1 = scfia.new_bv_ite(&or, &scfia.new_bv_concrete(1, 32), &scfia.new_bv_concrete(0, 32), 32, None, &mut None, None);
+    drop((and, xor, or, or_differs, implies, below_5, below_10));
+    let (clone, _, _) = state.clone_model();
+    scfia.new_bool_eq(&x, &scfia.new_bv_concrete(7, 32), None, true, &mut None, None);
+    assert_eq!(state.state.x1.get_depth(), clone.state.x1.get_depth());
+    let mut candidates = vec![];
+    scfia.z3.monomorphize(&state.state.x1.get_z3_ast(), &mut candidates);
+    assert_eq!(candidates, vec![1]);
+}
+
+#[test]
+fn test_comparisons() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let t = ActiveValue::BoolConcrete(true);
+    let f = ActiveValue::BoolConcrete(false);
+
+    // Signed folding honours the width of the operands
+    let (min, one, max) = (scfia.new_bv_concrete(0x80, 8), scfia.new_bv_concrete(1, 8), scfia.new_bv_concrete(0xff, 8));
+    assert!(scfia.new_bool_signed_less_than(&min, &one, None, false, &mut None, None).is_same_value(&t));
+    assert!(scfia.new_bool_signed_greater_equal(&max, &one, None, false, &mut None, None).is_same_value(&f));
+    assert!(scfia.new_bool_signed_less_equal(&max, &max, None, false, &mut None, None).is_same_value(&t));
+    assert!(scfia.new_bool_unsigned_greater_than(&max, &min, None, false, &mut None, None).is_same_value(&t));
+    let (min, zero) = (scfia.new_bv_concrete(1 << 63, 64), scfia.new_bv_concrete(0, 64));
+    assert!(scfia.new_bool_signed_greater_than(&zero, &min, None, false, &mut None, None).is_same_value(&t));
+
+    // Symbolic comparisons constrain like their smtlib counterparts
+    let x = scfia.new_bv_symbol(8, None, &mut None, None);
+    scfia.new_bool_signed_greater_than(&x, &scfia.new_bv_concrete(0x7d, 8), None, true, &mut None, None);
+    scfia.new_bool_unsigned_less_equal(&x, &scfia.new_bv_concrete(0x7f, 8), None, true, &mut None, None);
+    let at_least = scfia.new_bool_unsigned_greater_equal(&x, &scfia.new_bv_concrete(0x7e, 8), None, false, &mut None, None);
+    assert!(!scfia.check_condition(&scfia.new_bool_not(&at_least, None, false, &mut None, None), &mut None));
+    let zero_extended = scfia.new_bv_concat(&scfia.new_bv_concrete(0, 24), &x, 32, None, &mut None, None);
+    let mut candidates = vec![];
+    scfia.z3.monomorphize(&zero_extended.get_z3_ast(), &mut candidates);
+    candidates.sort();
+    assert_eq!(candidates, vec![0x7e, 0x7f]);
+
+    let mut state = new_rv32i(scfia.clone());
+    state.state.x1 = scfia.new_bv_symbol(32, None, &mut None, None);
+    let x = scfia.new_bv_slice(&state.state.x1, 7, 0, None, &mut None, None);
+    let negative = scfia.new_bool_signed_less_equal(&x, &scfia.new_bv_concrete(0xff, 8), None, false, &mut None, None);
+    let (taken, not_taken) = (scfia.new_bv_concrete(1, 32), scfia.new_bv_concrete(0, 32));
+    state.state.x2 = scfia.new_bv_ite(&negative, &taken, &not_taken, 32, None, &mut None, None);
+    drop(negative);
+    let (clone, _, _) = state.clone_model();
+    assert!(clone.state.x2.get_depth() > 1);
+    assert_eq!(clone.state.x2.get_depth(), state.state.x2.get_depth());
+}
+
+#[test]
+fn test_zero_extend_and_rotate() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let concrete = |value, width| scfia.new_bv_concrete(value, width);
+
+    // Concrete operands fold within their width
+    let rotated = scfia.new_bv_rotate_left(&concrete(0x80000001, 32), &concrete(1, 32), 32, None, &mut None, None);
+    assert_eq!(rotated.try_get_concrete(), Some(3));
+    let rotated = scfia.new_bv_rotate_right(&concrete(0x01, 8), &concrete(9, 8), 8, None, &mut None, None);
+    assert_eq!(rotated.try_get_concrete(), Some(0x80));
+    let extended = scfia.new_bv_zero_extend(&concrete(0xff, 8), 8, 32, None, &mut None, None);
+    assert_eq!(extended.try_get_concrete(), Some(0xff));
+
+    // Symbolic rotations undo each other and take the amount modulo the width
+    let x = scfia.new_bv_symbol(32, None, &mut None, None);
+    let amount = scfia.new_bv_symbol(32, None, &mut None, None);
+    let rotated = scfia.new_bv_rotate_left(&x, &amount, 32, None, &mut None, None);
+    let restored = scfia.new_bv_rotate_right(&rotated, &amount, 32, None, &mut None, None);
+    let differs = scfia.new_bool_eq(&restored, &x, None, false, &mut None, None);
+    assert!(!scfia.check_condition(&scfia.new_bool_not(&differs, None, false, &mut None, None), &mut None));
+    assert!(scfia.new_bv_rotate_left(&x, &concrete(64, 32), 32, None, &mut None, None).is_same_value(&x));
+    scfia.new_bool_eq(&x, &concrete(0x80000001, 32), None, true, &mut None, None);
+    scfia.new_bool_eq(&amount, &concrete(33, 32), None, true, &mut None, None);
+    let mut candidates = vec![];
+    scfia.z3.monomorphize(&rotated.get_z3_ast(), &mut candidates);
+    assert_eq!(candidates, vec![3]);
+
+    // Extensions of zero extensions stay flat
+    let byte = scfia.new_bv_symbol(8, None, &mut None, None);
+    let half = scfia.new_bv_zero_extend(&byte, 8, 16, None, &mut None, None);
+    let word = scfia.new_bv_sign_extend(&half, 16, 32, None, &mut None, None);
+    assert_eq!(word.get_depth(), half.get_depth());
+    assert!(scfia.new_bv_slice(&word, 7, 0, None, &mut None, None).is_same_value(&byte));
+    assert_eq!(scfia.new_bv_slice(&word, 31, 8, None, &mut None, None).try_get_concrete(), Some(0));
+
+    let mut state = new_rv32i(scfia.clone());
+    state.state.x1 = word;
+    state.state.x2 = rotated;
+    let (clone, _, _) = state.clone_model();
+    assert_eq!(clone.state.x1.get_depth(), state.state.x1.get_depth());
+    assert_eq!(clone.state.x2.get_depth(), state.state.x2.get_depth());
+}
+
+#[test]
+fn test_wide_bitvectors() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let concrete = |value, width| scfia.new_bv_concrete(value, width);
+
+    // Folding works beyond 64 bits and wraps at the full width
+    let descriptor = scfia.new_bv_concat(&concrete(0x0123456789abcdef, 64), &concrete(0xfedcba9876543210, 64), 128, None, &mut None, None);
+    assert_eq!(descriptor.try_get_concrete_u128(), Some(0x0123456789abcdef_fedcba9876543210));
+    assert_eq!(descriptor.try_get_concrete(), None);
+    let max = scfia.new_bv_zero_extend(&concrete(u64::MAX.into(), 64), 64, 128, None, &mut None, None);
+    let product = scfia.new_bv_multiply(&max, &max, 128, None, &mut None, None);
+    assert_eq!(product.try_get_concrete_u128(), Some(0xfffffffffffffffe_0000000000000001));
+    let sum = scfia.new_bv_add(&concrete(u128::MAX, 128), &concrete(1, 128), 128, None, &mut None, None);
+    assert_eq!(sum.try_get_concrete_u128(), Some(0));
+    let high = scfia.new_bv_slice(&descriptor, 127, 64, None, &mut None, None);
+    assert_eq!(high.try_get_concrete(), Some(0x0123456789abcdef));
+
+    // Wide literals reach the solver and come back out of its models
+    let x = scfia.new_bv_symbol(128, None, &mut None, None);
+    scfia.new_bool_eq(&x, &descriptor, None, true, &mut None, None);
+    let next = scfia.new_bv_add(&x, &concrete(1, 128), 128, None, &mut None, None);
+    let counterexample = scfia.get_counterexample().unwrap();
+    assert_eq!(counterexample.eval(&x), 0x0123456789abcdef_fedcba9876543210);
+    assert_eq!(counterexample.eval(&next), 0x0123456789abcdef_fedcba9876543211);
+    let symbols: Vec<u128> = counterexample.symbols.values().copied().collect();
+    assert_eq!(symbols, vec![0x0123456789abcdef_fedcba9876543210]);
+    drop(counterexample);
+
+    // Monomorphization finds candidates at the value's full width
+    let flag = scfia.new_bool_eq(&scfia.new_bv_symbol(1, None, &mut None, None), &concrete(1, 1), None, false, &mut None, None);
+    let choice = scfia.new_bv_ite(&flag, &next, &concrete(u64::MAX.into(), 128), 128, None, &mut None, None);
+    let mut candidates = vec![];
+    scfia.monomorphize_active(&choice, &mut candidates);
+    candidates.sort();
+    assert_eq!(candidates, vec![u64::MAX.into(), 0x0123456789abcdef_fedcba9876543211]);
+
+    // Reports write values which do not fit a JSON number as hex strings
+    assert_eq!(RegisterValue::new(&descriptor).to_json(), "0x123456789abcdeffedcba9876543210");
+    assert_eq!(RegisterValue::new(&concrete(u64::MAX.into(), 128)).to_json(), u64::MAX);
+}
+
+type Value = ActiveValue<RV32iScfiaComposition>;
+type BinaryConstructor = fn(&Scfia<RV32iScfiaComposition>, &Value, &Value, u32) -> Value;
+type UnaryConstructor = fn(&Scfia<RV32iScfiaComposition>, &Value, u32) -> Value;
+type BoolConstructor = fn(&Scfia<RV32iScfiaComposition>, &Value, &Value) -> Value;
+
+/// Every binary constructor, applied to two operands of the given width.
+const BINARY_CONSTRUCTORS: [(&str, BinaryConstructor); 27] = [
+    ("bvadd", |s, a, b, w| s.new_bv_add(a, b, w, None, &mut None, None)),
+    ("bvsub", |s, a, b, w| s.new_bv_sub(a, b, w, None, &mut None, None)),
+    ("bvmul", |s, a, b, w| s.new_bv_multiply(a, b, w, None, &mut None, None)),
+    ("bvudiv", |s, a, b, w| s.new_bv_udiv(a, b, w, None, &mut None, None)),
+    ("bvurem", |s, a, b, w| s.new_bv_unsigned_remainder(a, b, w, None, &mut None, None)),
+    ("bvand", |s, a, b, w| s.new_bv_and(a, b, w, None, &mut None, None)),
+    ("bvor", |s, a, b, w| s.new_bv_or(a, b, w, None, &mut None, None)),
+    ("bvxor", |s, a, b, w| s.new_bv_xor(a, b, w, None, &mut None, None)),
+    ("bvshl", |s, a, b, w| s.new_bv_sll(a, b, w, None, &mut None, None)),
+    ("bvlshr", |s, a, b, w| s.new_bv_srl(a, b, w, None, &mut None, None)),
+    ("bvashr", |s, a, b, w| s.new_bv_ashr(a, b, w, None, &mut None, None)),
+    ("asr carry", |s, a, b, w| s.new_bv_asr(a, b, w, None, &mut None, None).1),
+    ("ext_rotate_left", |s, a, b, w| s.new_bv_rotate_left(a, b, w, None, &mut None, None)),
+    ("ext_rotate_right", |s, a, b, w| s.new_bv_rotate_right(a, b, w, None, &mut None, None)),
+    ("concat", |s, a, b, w| s.new_bv_concat(a, b, 2 * w, None, &mut None, None)),
+    ("=", |s, a, b, _| s.new_bool_eq(a, b, None, false, &mut None, None)),
+    ("bvult", |s, a, b, _| s.new_bool_unsigned_less_than(a, b, None, false, &mut None, None)),
+    ("bvule", |s, a, b, _| s.new_bool_unsigned_less_equal(a, b, None, false, &mut None, None)),
+    ("bvugt", |s, a, b, _| s.new_bool_unsigned_greater_than(a, b, None, false, &mut None, None)),
+    ("bvuge", |s, a, b, _| s.new_bool_unsigned_greater_equal(a, b, None, false, &mut None, None)),
+    ("bvslt", |s, a, b, _| s.new_bool_signed_less_than(a, b, None, false, &mut None, None)),
+    ("bvsle", |s, a, b, _| s.new_bool_signed_less_equal(a, b, None, false, &mut None, None)),
+    ("bvsgt", |s, a, b, _| s.new_bool_signed_greater_than(a, b, None, false, &mut None, None)),
+    ("bvsge", |s, a, b, _| s.new_bool_signed_greater_equal(a, b, None, false, &mut None, None)),
+    ("ite", |s, a, b, w| {
+        let condition = s.new_bool_unsigned_less_than(a, b, None, false, &mut None, None);
+        s.new_bv_ite(&condition, a, b, w, None, &mut None, None)
+    }),
+    ("bvnot", |s, a, _, w| s.new_bv_not(a, w, None, &mut None, None)),
+    ("extract", |s, a, b, w| {
+        let (high, low) = (b.try_get_concrete().unwrap_or(w as u64 - 1) as u32 % w, 0);
+        s.new_bv_slice(a, high, low, None, &mut None, None)
+    }),
+];
+
+/// Every unary constructor, applied to an operand of the given width.
+const UNARY_CONSTRUCTORS: [(&str, UnaryConstructor); 6] = [
+    ("sign_extend 64", |s, a, w| s.new_bv_sign_extend(a, w, 64, None, &mut None, None)),
+    ("sign_extend 128", |s, a, w| s.new_bv_sign_extend(a, w, 128, None, &mut None, None)),
+    ("zero_extend 64", |s, a, w| s.new_bv_zero_extend(a, w, 64, None, &mut None, None)),
+    ("zero_extend 128", |s, a, w| s.new_bv_zero_extend(a, w, 128, None, &mut None, None)),
+    ("extract upper half", |s, a, w| s.new_bv_slice(a, w - 1, w / 2, None, &mut None, None)),
+    ("byte reverse", |s, a, w| {
+        if w % 8 == 0 {
+            s.new_bv_byte_reverse(a, w, &mut None, None)
+        } else {
+            s.new_bv_not(a, w, None, &mut None, None)
+        }
+    }),
+];
+
+#[test]
+fn test_concrete_folding_matches_z3() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    // Nothing is asserted, so the model only serves to evaluate ground terms
+    let counterexample = scfia.get_counterexample().unwrap();
+    let folded = |value: &Value, description: &str| match value {
+        ActiveValue::BoolConcrete(value) => *value as u128,
+        ActiveValue::BVConcrete(value, _) => *value,
+        ActiveValue::Expression(_) => panic!("{} was not folded", description),
+    };
+
+    let mut random: u64 = 0x9e3779b97f4a7c15;
+    for width in 1..=64 {
+        let mask = u64::MAX >> (64 - width);
+        let sign = 1 << (width - 1);
+        let mut inputs = vec![0, 1, mask, sign, sign - 1, width as u64 - 1, width as u64];
+        for _ in 0..3 {
+            random ^= random << 13;
+            random ^= random >> 7;
+            random ^= random << 17;
+            inputs.push(random);
+        }
+        let mut inputs: Vec<u128> = inputs.iter().map(|input| (input & mask) as u128).collect();
+        inputs.sort();
+        inputs.dedup();
+
+        for &a in &inputs {
+            let (concrete_a, z3_a) = (scfia.new_bv_concrete(a, width), scfia.new_bv_concrete_z3(a, width, None, &mut None, None));
+            for (name, constructor) in UNARY_CONSTRUCTORS {
+                let description = format!("{}({:#x}) at width {}", name, a, width);
+                let expected = counterexample.eval(&constructor(&scfia, &z3_a, width));
+                let actual = folded(&constructor(&scfia, &concrete_a, width), &description);
+                assert_eq!(actual, expected, "{}", description);
+            }
+            for &b in &inputs {
+                let (concrete_b, z3_b) = (scfia.new_bv_concrete(b, width), scfia.new_bv_concrete_z3(b, width, None, &mut None, None));
+                for (name, constructor) in BINARY_CONSTRUCTORS {
+                    let description = format!("{}({:#x}, {:#x}) at width {}", name, a, b, width);
+                    let expected = counterexample.eval(&constructor(&scfia, &z3_a, &z3_b, width));
+                    let actual = folded(&constructor(&scfia, &concrete_a, &concrete_b, width), &description);
+                    assert_eq!(actual, expected, "{}", description);
+                }
+            }
+        }
+    }
+
+    let bool_z3 = |value: bool| {
+        let bit = scfia.new_bv_concrete_z3(value as u128, 1, None, &mut None, None);
+        scfia.new_bool_eq(&bit, &scfia.new_bv_concrete(1, 1), None, false, &mut None, None)
+    };
+    for a in [false, true] {
+        assert_eq!(
+            folded(&scfia.new_bool_not(&ActiveValue::BoolConcrete(a), None, false, &mut None, None), "not"),
+            counterexample.eval(&scfia.new_bool_not(&bool_z3(a), None, false, &mut None, None))
+        );
+        for b in [false, true] {
+            let (concrete_a, concrete_b) = (ActiveValue::BoolConcrete(a), ActiveValue::BoolConcrete(b));
+            let connectives: [BoolConstructor; 4] = [
+                |s, a, b| s.new_bool_and(a, b, None, false, &mut None, None),
+                |s, a, b| s.new_bool_or(a, b, None, false, &mut None, None),
+                |s, a, b| s.new_bool_xor(a, b, None, false, &mut None, None),
+                |s, a, b| s.new_bool_implies(a, b, None, false, &mut None, None),
+            ];
+            for connective in connectives {
+                let expected = counterexample.eval(&connective(&scfia, &bool_z3(a), &bool_z3(b)));
+                assert_eq!(folded(&connective(&scfia, &concrete_a, &concrete_b), "connective"), expected, "{} {}", a, b);
+                // Partially concrete operands must agree as well
+                assert_eq!(counterexample.eval(&connective(&scfia, &concrete_a, &bool_z3(b))), expected, "{} {}", a, b);
+                assert_eq!(counterexample.eval(&connective(&scfia, &bool_z3(a), &concrete_b)), expected, "{} {}", a, b);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_abstract_domain() {
+    // Operands with some known bits and a bounded range stay sound under every constructor
+    let mut random: u64 = 0x2545f4914f6cdd1d;
+    let mut next = move || {
+        random ^= random << 13;
+        random ^= random >> 7;
+        random ^= random << 17;
+        random
+    };
+    for trial in 0..200 {
+        let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+        let width = [1, 5, 8, 16, 31, 32, 64][trial % 7];
+        let mask = (u64::MAX >> (64 - width)) as u128;
+        let operand = |random: u64, value: u64| {
+            let symbol = scfia.new_bv_symbol(width, None, &mut None, None);
+            scfia.new_bool_eq(&symbol, &scfia.new_bv_concrete(value as u128 & mask, width), None, true, &mut None, None);
+            let clear = scfia.new_bv_concrete(random as u128 & mask, width);
+            let set = scfia.new_bv_concrete((random >> 32) as u128 & mask, width);
+            let cleared = scfia.new_bv_and(&symbol, &clear, width, None, &mut None, None);
+            scfia.new_bv_or(&cleared, &set, width, None, &mut None, None)
+        };
+        let (s1, s2) = (operand(next(), next()), operand(next(), next()));
+        let counterexample = scfia.get_counterexample().unwrap();
+        for (name, constructor) in BINARY_CONSTRUCTORS {
+            let value = constructor(&scfia, &s1, &s2, width);
+            let abstraction = value.get_abstraction();
+            let concrete = counterexample.eval(&value);
+            assert!(abstraction.contains(concrete), "{} = {:#x} escapes {:x?}", name, concrete, abstraction);
+        }
+    }
+
+    // Conditions the abstraction decides never reach the solver
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let x = scfia.new_bv_symbol(32, None, &mut None, None);
+    let byte = scfia.new_bv_and(&x, &scfia.new_bv_concrete(0xff, 32), 32, None, &mut None, None);
+    let abstraction = byte.get_abstraction();
+    assert_eq!((abstraction.min, abstraction.max, abstraction.known_zeros), (0, 0xff, 0xffffff00));
+    let in_range = scfia.new_bool_unsigned_less_than(&byte, &scfia.new_bv_concrete(0x100, 32), None, false, &mut None, None);
+    assert!(scfia.check_condition(&in_range, &mut None));
+    let is_large = scfia.new_bool_eq(&byte, &scfia.new_bv_concrete(0x100, 32), None, false, &mut None, None);
+    assert!(!scfia.check_condition(&is_large, &mut None));
+    let odd = scfia.new_bv_or(&x, &scfia.new_bv_concrete(1, 32), 32, None, &mut None, None);
+    let low_bit = scfia.new_bv_slice(&odd, 0, 0, None, &mut None, None);
+    let is_odd = scfia.new_bool_eq(&low_bit, &scfia.new_bv_concrete(1, 1), None, false, &mut None, None);
+    assert!(scfia.check_condition(&is_odd, &mut None));
+    let extended = scfia.new_bv_zero_extend(&scfia.new_bv_slice(&x, 7, 0, None, &mut None, None), 8, 32, None, &mut None, None);
+    let negative = scfia.new_bool_signed_less_than(&extended, &scfia.new_bv_concrete(0, 32), None, false, &mut None, None);
+    assert!(!scfia.check_condition(&negative, &mut None));
+    assert_eq!(scfia.z3.decided_by_abstraction.get(), 4);
+
+    // Inconclusive abstractions fall back to the solver
+    scfia.new_bool_eq(&x, &scfia.new_bv_concrete(3, 32), None, true, &mut None, None);
+    let small = scfia.new_bool_unsigned_less_than(&byte, &scfia.new_bv_concrete(0x80, 32), None, false, &mut None, None);
+    assert!(scfia.check_condition(&small, &mut None));
+    assert_eq!(scfia.z3.decided_by_abstraction.get(), 4);
+}
+
+#[test]
+fn test_query_cache() {
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    let x = scfia.new_bv_symbol(32, None, &mut None, None);
+    scfia.new_bool_unsigned_less_than(&x, &scfia.new_bv_concrete(5, 32), None, true, &mut None, None);
+    let condition = scfia.new_bool_unsigned_less_than(&x, &scfia.new_bv_concrete(10, 32), None, false, &mut None, None);
+    let low_bits = scfia.new_bv_and(&x, &scfia.new_bv_concrete(3, 32), 32, None, &mut None, None);
+
+    // Repeated queries against the same assertions are answered from the cache
+    assert!(scfia.check_condition(&condition, &mut None));
+    let mut candidates = vec![];
+    scfia.monomorphize_active(&low_bits, &mut candidates);
+    assert_eq!((scfia.z3.query_cache_hits.get(), scfia.z3.query_cache_misses.get()), (0, 3));
+    assert!(scfia.check_condition(&condition, &mut None));
+    let mut cached_candidates = vec![];
+    scfia.monomorphize_active(&low_bits, &mut cached_candidates);
+    assert_eq!((scfia.z3.query_cache_hits.get(), scfia.z3.query_cache_misses.get()), (3, 3));
+    candidates.sort();
+    cached_candidates.sort();
+    assert_eq!(candidates, vec![0, 1, 2, 3]);
+    assert_eq!(cached_candidates, candidates);
+
+    // A new assertion invalidates them
+    let fingerprint = scfia.z3.assertions_fingerprint.get();
+    scfia.new_bool_eq(&low_bits, &scfia.new_bv_concrete(2, 32), None, true, &mut None, None);
+    assert_ne!(scfia.z3.assertions_fingerprint.get(), fingerprint);
+    let mut candidates = vec![];
+    scfia.monomorphize_active(&low_bits, &mut candidates);
+    assert_eq!(candidates, vec![2]);
+    assert!(scfia.check_condition(&condition, &mut None));
+    assert_eq!((scfia.z3.query_cache_hits.get(), scfia.z3.query_cache_misses.get()), (3, 6));
+
+    // So are unique values
+    let low_bits_ast = low_bits.get_z3_ast();
+    assert_eq!(scfia.z3.get_unique(&low_bits_ast), Some(2));
+    let hits = scfia.z3.query_cache_hits.get();
+    assert_eq!(scfia.z3.get_unique(&low_bits_ast), Some(2));
+    assert_eq!(scfia.z3.query_cache_hits.get(), hits + 1);
+
+    // The least recently used results are evicted once the cache is full
+    let x_ast = x.get_z3_ast();
+    for value in 0..QUERY_CACHE_CAPACITY as u128 {
+        let other = scfia.z3.new_not(&scfia.z3.new_eq(&x_ast, &scfia.z3.new_bv_concrete(value, 32), false), false);
+        scfia.z3.check_assumptions(&[&other]);
+    }
+    let misses = scfia.z3.query_cache_misses.get();
+    assert!(scfia.check_condition(&condition, &mut None));
+    assert_eq!(scfia.z3.query_cache_misses.get(), misses + 2);
+}
+
+#[test]
+fn test_expression_depth_limit() {
+    // Values the path condition fixes are concretised
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    scfia.limit_expression_depth(4, DepthLimitPolicy::ConcretiseOrAbstract);
+    let s = scfia.new_bv_symbol(32, None, &mut None, None);
+    scfia.new_bool_eq(&s, &scfia.new_bv_concrete(5, 32), None, true, &mut None, None);
+    let mut value = s.clone();
+    let mut concretised = false;
+    for _ in 0..10 {
+        value = scfia.new_bv_multiply(&value, &s, 32, None, &mut None, None);
+        assert!(value.get_depth() <= 4);
+        concretised |= matches!(value, ActiveValue::BVConcrete(_, _));
+    }
+    assert!(concretised);
+    assert_eq!(scfia.get_counterexample().unwrap().eval(&value), 5u128.pow(11) & 0xffffffff);
+
+    // Others are replaced by fresh symbols without losing their constraints
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    scfia.limit_expression_depth(4, DepthLimitPolicy::ConcretiseOrAbstract);
+    let s = scfia.new_bv_symbol(32, None, &mut None, None);
+    let t = scfia.new_bv_symbol(32, None, &mut None, None);
+    let mut value = t.clone();
+    let mut expected: u32 = 1;
+    for _ in 0..10 {
+        let product = scfia.new_bv_multiply(&value, &s, 32, None, &mut None, None);
+        value = scfia.new_bv_add(&product, &t, 32, None, &mut None, None);
+        assert!(value.get_depth() <= 4);
+        assert!(!matches!(value, ActiveValue::BVConcrete(_, _)));
+        expected = expected.wrapping_mul(3).wrapping_add(1);
+    }
+    scfia.new_bool_eq(&s, &scfia.new_bv_concrete(3, 32), None, true, &mut None, None);
+    scfia.new_bool_eq(&t, &scfia.new_bv_concrete(1, 32), None, true, &mut None, None);
+    assert_eq!(scfia.get_counterexample().unwrap().eval(&value), expected as u128);
+
+    // Hash consing never hands out the too deep value in place of its replacement
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    scfia.enable_hash_consing();
+    scfia.limit_expression_depth(2, DepthLimitPolicy::ConcretiseOrAbstract);
+    let s = scfia.new_bv_symbol(32, None, &mut None, None);
+    let square = scfia.new_bv_multiply(&s, &s, 32, None, &mut None, None);
+    let cached = scfia.structural_cache_len();
+    let first = scfia.new_bv_multiply(&square, &s, 32, None, &mut None, None);
+    let second = scfia.new_bv_multiply(&square, &s, 32, None, &mut None, None);
+    assert_eq!((first.get_depth(), second.get_depth()), (1, 1));
+    assert_eq!(scfia.structural_cache_len(), cached);
+
+    // Values are only concretised or only abstracted if the policy says so
+    let scfia: Rc<Scfia<RV32iScfiaComposition>> = Scfia::new(None);
+    scfia.limit_expression_depth(2, DepthLimitPolicy::Concretise);
+    let s = scfia.new_bv_symbol(32, None, &mut None, None);
+    let square = scfia.new_bv_multiply(&s, &s, 32, None, &mut None, None);
+    let cube = scfia.new_bv_multiply(&square, &s, 32, None, &mut None, None);
+    assert_eq!(cube.get_depth(), 3);
+    scfia.new_bool_eq(&s, &scfia.new_bv_concrete(2, 32), None, true, &mut None, None);
+    let cube = scfia.new_bv_multiply(&square, &s, 32, None, &mut None, None);
+    assert!(matches!(cube, ActiveValue::BVConcrete(8, 32)));
+    scfia.limit_expression_depth(2, DepthLimitPolicy::Abstract);
+    let cube = scfia.new_bv_multiply(&square, &s, 32, None, &mut None, None);
+    assert!(!matches!(cube, ActiveValue::BVConcrete(_, _)));
+    assert_eq!(cube.get_depth(), 1);
+    assert_eq!(scfia.get_counterexample().unwrap().eval(&cube), 8);
+}